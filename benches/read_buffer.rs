@@ -0,0 +1,61 @@
+//! Counts the allocations made while reading a 10k-row result set.
+//!
+//! Like the integration tests, this requires a PostgreSQL server on `localhost:5432`.
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    net::{IpAddr, Ipv4Addr},
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Instant,
+};
+
+use smol_pg::connection::Connection;
+
+/// How often the query is repeated.
+const ITERATIONS: usize = 20;
+
+/// The query producing the 10k rows.
+const QUERY: &str = "SELECT * FROM generate_series(1, 10000)";
+
+/// An allocator counting every allocation it makes.
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+fn main() {
+    smol::block_on(async {
+        let mut connection = Connection::create(IpAddr::V4(Ipv4Addr::LOCALHOST), None)
+            .await
+            .unwrap();
+
+        // Warm up the connection
+        connection.query(QUERY).await.unwrap();
+
+        let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+        let start = Instant::now();
+
+        for _ in 0..ITERATIONS {
+            let rows = connection.query(QUERY).await.unwrap();
+            assert_eq!(rows.len(), 10_000);
+        }
+
+        let elapsed = start.elapsed() / ITERATIONS as u32;
+        let allocations = (ALLOCATIONS.load(Ordering::Relaxed) - allocations) / ITERATIONS;
+
+        println!("10k rows: {elapsed:?} per query, {allocations} allocations per query");
+    });
+}
@@ -0,0 +1,41 @@
+//! Measures cloning a row with 50 columns.
+//!
+//! Like the integration tests, this requires a PostgreSQL server on `localhost:5432`.
+
+use std::{
+    hint::black_box,
+    net::{IpAddr, Ipv4Addr},
+    time::Instant,
+};
+
+use smol_pg::connection::Connection;
+
+/// How often the row is cloned.
+const ITERATIONS: u32 = 1_000_000;
+
+fn main() {
+    smol::block_on(async {
+        let mut connection = Connection::create(IpAddr::V4(Ipv4Addr::LOCALHOST), None)
+            .await
+            .unwrap();
+
+        // 50 text columns of 20 bytes each
+        let columns = (1..=50)
+            .map(|column| format!("repeat('x', 20) AS c{column}"))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let rows = connection
+            .query(&format!("SELECT {columns}"))
+            .await
+            .unwrap();
+        let row = &rows[0];
+
+        let start = Instant::now();
+        for _ in 0..ITERATIONS {
+            black_box(black_box(row).clone());
+        }
+        let elapsed = start.elapsed() / ITERATIONS;
+
+        println!("50-column row: {elapsed:?} per clone");
+    });
+}
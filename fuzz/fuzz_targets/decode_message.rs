@@ -0,0 +1,16 @@
+//! Feeds arbitrary bytes to the server message decoder, which has to reject
+//! malformed messages with an error instead of panicking.
+//!
+//! Run it with `cargo fuzz run decode_message` from the repository root.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use smol_pg::{protocol::message::server::Message, util::Reader};
+
+fuzz_target!(|data: &[u8]| {
+    // With the length checked against the buffer, like messages read from the stream
+    let _ = Message::try_from(data);
+    // And without, so the decoders see lengths that don't match their content
+    let _ = Message::try_from(Reader::new(data));
+});
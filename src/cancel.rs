@@ -0,0 +1,52 @@
+//! Cancelling a running query from elsewhere, e.g. a spawned task.
+//!
+//! The protocol cancels queries over a new connection to the server, so a
+//! [`CancelToken`] keeps everything needed to open one.
+
+use std::net::SocketAddr;
+
+use async_net::TcpStream;
+use futures_lite::{AsyncReadExt, AsyncWriteExt};
+
+use crate::{protocol::message::client::CancelRequest, Error};
+
+/// A handle to cancel the query a [`Connection`](crate::connection::Connection)
+/// is running, independent of the connection itself.
+///
+/// Created by [`Connection::cancel_token`](crate::connection::Connection::cancel_token).
+#[derive(Debug, Clone)]
+pub struct CancelToken {
+    /// The address of the server the connection is connected to, if known.
+    pub(crate) peer_address: Option<SocketAddr>,
+    /// The configured host, used if the address isn't known.
+    pub(crate) host: String,
+    /// The configured port, used if the address isn't known.
+    pub(crate) port: u16,
+    /// The process ID of the server process running the queries.
+    pub(crate) process_id: i32,
+    /// The secret key the server process sent on startup.
+    pub(crate) secret_key: i32,
+}
+
+impl CancelToken {
+    /// Ask the server to cancel the query the connection is running.
+    ///
+    /// Cancellation is racy by design of the protocol: the query may finish
+    /// before the request arrives, in which case nothing happens, or, if the
+    /// connection already sent the next one, that one is cancelled instead.
+    /// A cancelled query fails with the error code `57014` (`query_canceled`).
+    pub async fn cancel(&self) -> Result<(), Error> {
+        let mut stream = match self.peer_address {
+            Some(address) => TcpStream::connect(address).await?,
+            None => TcpStream::connect((self.host.as_str(), self.port)).await?,
+        };
+
+        let request = CancelRequest::new(self.process_id, self.secret_key);
+        stream.write_all(&Vec::from(&request)).await?;
+
+        // The server closes the connection once it handled the request
+        stream.read_to_end(&mut Vec::new()).await?;
+
+        Ok(())
+    }
+}
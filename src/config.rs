@@ -0,0 +1,504 @@
+//! Configuration for opening a [`Connection`](crate::connection::Connection).
+
+use std::{net::SocketAddr, time::Duration};
+
+use macro_rules_attribute::apply;
+use thiserror_lite::err_enum;
+
+use crate::protocol::message::server::{FormatCode, Severity};
+
+/// The configuration of a connection to a PostgreSQL server.
+///
+/// Start with [`ConnectionConfig::new`] and adjust the defaults
+/// using the builder methods.
+///
+/// The password is redacted from the [`Debug`] output, so the configuration
+/// can be logged safely.
+#[derive(Clone)]
+pub struct ConnectionConfig {
+    /// The host name or IP address of the server.
+    pub(crate) host: String,
+    /// The port the server listens on.
+    pub(crate) port: u16,
+    /// Several servers to try in order instead of `host` and `port`, if not empty.
+    pub(crate) hosts: Vec<(String, u16)>,
+    /// Which kind of session the connection must end up in.
+    pub(crate) target_session_attrs: TargetSessionAttrs,
+    /// The user name to connect as.
+    pub(crate) user: String,
+    /// The password of the user.
+    ///
+    /// It isn't used yet, no password-based authentication method is supported.
+    pub(crate) password: Option<String>,
+    /// The database to connect to, the server defaults to the user name.
+    pub(crate) database: Option<String>,
+    /// The name the connection reports to the server, e.g. in `pg_stat_activity`.
+    pub(crate) application_name: Option<String>,
+    /// Which kind of replication connection to open, `None` for a regular one.
+    pub(crate) replication: Option<ReplicationMode>,
+    /// Which kind of addresses the host name may resolve to.
+    pub(crate) address_family: AddressFamily,
+    /// Whether to send TCP keepalives to detect dead connections.
+    pub(crate) keepalives: bool,
+    /// The idle time before the first keepalive, the system default if `None`.
+    pub(crate) keepalives_idle: Option<Duration>,
+    /// The time between unanswered keepalives, the system default if `None`.
+    pub(crate) keepalives_interval: Option<Duration>,
+    /// The maximum number of prepared statements kept open on the server.
+    pub(crate) max_prepared_statements: Option<usize>,
+    /// What to do when preparing a statement would exceed the limit.
+    pub(crate) statement_limit_policy: StatementLimitPolicy,
+    /// The number of statements cached by their query text, `0` disables the cache.
+    pub(crate) statement_cache_size: usize,
+    /// The maximum number of rows a simple query may buffer, unlimited if `None`.
+    pub(crate) max_rows_buffered: Option<usize>,
+    /// The format extended-protocol queries request their results in.
+    pub(crate) default_result_format: FormatCode,
+    /// Notices below this severity are dropped, `None` keeps all of them.
+    pub(crate) min_notice_severity: Option<Severity>,
+    /// The number of notices kept until they are taken, older ones are dropped.
+    pub(crate) max_notices: usize,
+    /// Whether messages of unknown types are an error instead of being skipped.
+    pub(crate) strict_messages: bool,
+    /// The capacity of the buffer incoming messages are read into.
+    pub(crate) read_buffer_capacity: usize,
+    /// The capacity of the buffer outgoing messages are encoded into.
+    pub(crate) write_buffer_capacity: usize,
+    /// How much of each message is logged.
+    #[cfg(feature = "trace-protocol")]
+    pub(crate) protocol_trace: ProtocolTrace,
+}
+
+/// Errors that can occur when reading a configuration.
+#[allow(missing_docs)]
+#[derive(Debug)]
+#[non_exhaustive]
+#[apply(err_enum)]
+pub enum ConfigError {
+    #[error("invalid value `{value}` for environment variable `{name}`")]
+    InvalidEnvVar { name: &'static str, value: String },
+}
+
+/// What to do when preparing a statement would exceed
+/// [`ConnectionConfig::max_prepared_statements`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum StatementLimitPolicy {
+    /// Close the oldest prepared statement to make room for the new one.
+    #[default]
+    Evict,
+    /// Refuse to prepare the new statement.
+    Error,
+}
+
+/// The kind of replication connection to open, see
+/// [`ConnectionConfig::replication`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ReplicationMode {
+    /// Physical replication of the whole cluster (`replication=true`).
+    Physical,
+    /// Logical replication of the database (`replication=database`).
+    Logical,
+}
+
+impl ReplicationMode {
+    /// The value of the `replication` startup parameter.
+    pub(crate) fn parameter(self) -> &'static str {
+        match self {
+            ReplicationMode::Physical => "true",
+            ReplicationMode::Logical => "database",
+        }
+    }
+}
+
+/// Which kind of server [`Connection::connect`] accepts, see
+/// [`ConnectionConfig::target_session_attrs`].
+///
+/// [`Connection::connect`]: crate::connection::Connection::connect
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum TargetSessionAttrs {
+    /// Any server.
+    #[default]
+    Any,
+    /// A server that accepts writes, e.g. the primary.
+    ReadWrite,
+    /// A server that only accepts reads, e.g. a standby.
+    ReadOnly,
+}
+
+impl TargetSessionAttrs {
+    /// Whether a session with the given `transaction_read_only` matches.
+    pub(crate) fn matches(self, read_only: bool) -> bool {
+        match self {
+            TargetSessionAttrs::Any => true,
+            TargetSessionAttrs::ReadWrite => !read_only,
+            TargetSessionAttrs::ReadOnly => read_only,
+        }
+    }
+}
+
+impl std::fmt::Display for TargetSessionAttrs {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // The names libpq uses
+        f.write_str(match self {
+            TargetSessionAttrs::Any => "any",
+            TargetSessionAttrs::ReadWrite => "read-write",
+            TargetSessionAttrs::ReadOnly => "read-only",
+        })
+    }
+}
+
+/// Which IP addresses of the server are tried when the host name
+/// resolves to several.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum AddressFamily {
+    /// Try all addresses in the order they were resolved.
+    #[default]
+    Any,
+    /// Only try IPv4 addresses.
+    V4,
+    /// Only try IPv6 addresses.
+    V6,
+}
+
+impl AddressFamily {
+    /// Whether the address belongs to this family.
+    pub(crate) fn matches(self, address: &SocketAddr) -> bool {
+        match self {
+            AddressFamily::Any => true,
+            AddressFamily::V4 => address.is_ipv4(),
+            AddressFamily::V6 => address.is_ipv6(),
+        }
+    }
+}
+
+/// How much of each message sent or received is logged
+/// with the `trace-protocol` feature.
+#[cfg(feature = "trace-protocol")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum ProtocolTrace {
+    /// Only the message type and length.
+    #[default]
+    Headers,
+    /// Also a hex dump of the message, except for authentication messages
+    /// which may contain passwords or SASL exchanges.
+    Redacted,
+    /// A hex dump of every message, including authentication messages.
+    Full,
+}
+
+impl ConnectionConfig {
+    /// Create a configuration for connecting to `localhost:5432` as `postgres`.
+    pub fn new() -> Self {
+        Self {
+            host: "localhost".to_string(),
+            port: crate::POSTGRES_DEFAULT_PORT,
+            hosts: Vec::new(),
+            target_session_attrs: TargetSessionAttrs::default(),
+            user: "postgres".to_string(),
+            password: None,
+            database: None,
+            application_name: None,
+            replication: None,
+            address_family: AddressFamily::default(),
+            keepalives: true,
+            keepalives_idle: None,
+            keepalives_interval: None,
+            max_prepared_statements: None,
+            statement_limit_policy: StatementLimitPolicy::default(),
+            statement_cache_size: 0,
+            max_rows_buffered: None,
+            default_result_format: FormatCode::Text,
+            min_notice_severity: None,
+            max_notices: 1000,
+            strict_messages: false,
+            read_buffer_capacity: 8 * 1024,
+            write_buffer_capacity: 8 * 1024,
+            #[cfg(feature = "trace-protocol")]
+            protocol_trace: ProtocolTrace::default(),
+        }
+    }
+
+    /// Create a configuration from the environment variables `libpq` uses, falling
+    /// back to the defaults of [`ConnectionConfig::new`] for unset variables.
+    ///
+    /// Reads `PGHOST`, `PGPORT`, `PGUSER`, `PGPASSWORD`, `PGDATABASE` and `PGAPPNAME`. Settings
+    /// made with the builder methods afterwards take precedence.
+    pub fn from_env() -> Result<Self, ConfigError> {
+        let var = |name| std::env::var(name).ok().filter(|value| !value.is_empty());
+
+        let mut config = Self::new();
+
+        if let Some(host) = var("PGHOST") {
+            config.host = host;
+        }
+        if let Some(port) = var("PGPORT") {
+            config.port = port.parse().map_err(|_| ConfigError::InvalidEnvVar {
+                name: "PGPORT",
+                value: port,
+            })?;
+        }
+        if let Some(user) = var("PGUSER") {
+            config.user = user;
+        }
+        config.password = var("PGPASSWORD");
+        config.database = var("PGDATABASE");
+        config.application_name = var("PGAPPNAME");
+
+        Ok(config)
+    }
+
+    /// Set the host name or IP address of the server.
+    pub fn host(mut self, host: impl Into<String>) -> Self {
+        self.host = host.into();
+        self
+    }
+
+    /// Set the port the server listens on.
+    pub fn port(mut self, port: u16) -> Self {
+        self.port = port;
+        self
+    }
+
+    /// Set several servers to try in order, e.g. a primary and its standbys,
+    /// instead of the single [`host`](Self::host) and [`port`](Self::port).
+    ///
+    /// The first server that can be reached and matches the
+    /// [`target_session_attrs`](Self::target_session_attrs) is used.
+    pub fn hosts(mut self, hosts: Vec<(String, u16)>) -> Self {
+        self.hosts = hosts;
+        self
+    }
+
+    /// Set which kind of server to accept (any by default).
+    ///
+    /// Other than for [`TargetSessionAttrs::Any`], every server is asked
+    /// for `transaction_read_only` after connecting, and skipped if it doesn't match.
+    pub fn target_session_attrs(mut self, target_session_attrs: TargetSessionAttrs) -> Self {
+        self.target_session_attrs = target_session_attrs;
+        self
+    }
+
+    /// The servers to try in order, see [`ConnectionConfig::hosts`].
+    pub(crate) fn candidates(&self) -> Vec<(String, u16)> {
+        if self.hosts.is_empty() {
+            vec![(self.host.clone(), self.port)]
+        } else {
+            self.hosts.clone()
+        }
+    }
+
+    /// Set the user name to connect as.
+    pub fn user(mut self, user: impl Into<String>) -> Self {
+        self.user = user.into();
+        self
+    }
+
+    /// Set the password of the user.
+    pub fn password(mut self, password: impl Into<String>) -> Self {
+        self.password = Some(password.into());
+        self
+    }
+
+    /// Set the database to connect to.
+    pub fn database(mut self, database: impl Into<String>) -> Self {
+        self.database = Some(database.into());
+        self
+    }
+
+    /// Set the name the connection reports to the server, e.g. in `pg_stat_activity`.
+    pub fn application_name(mut self, application_name: impl Into<String>) -> Self {
+        self.application_name = Some(application_name.into());
+        self
+    }
+
+    /// Open a replication connection instead of a regular one.
+    ///
+    /// It accepts replication commands like `IDENTIFY_SYSTEM` and
+    /// `START_REPLICATION`, see [`Connection::copy_both`].
+    ///
+    /// [`Connection::copy_both`]: crate::connection::Connection::copy_both
+    pub fn replication(mut self, mode: ReplicationMode) -> Self {
+        self.replication = Some(mode);
+        self
+    }
+
+    /// Only connect to addresses of the given family (all are tried by default).
+    ///
+    /// Useful when a host name resolves to both IPv4 and IPv6 addresses
+    /// but one of them is unreachable.
+    pub fn address_family(mut self, address_family: AddressFamily) -> Self {
+        self.address_family = address_family;
+        self
+    }
+
+    /// Enable or disable TCP keepalives (enabled by default).
+    ///
+    /// Keepalives detect connections that were silently dropped, e.g. by a NAT
+    /// or firewall, and keep idle connections through them alive.
+    pub fn keepalives(mut self, enabled: bool) -> Self {
+        self.keepalives = enabled;
+        self
+    }
+
+    /// Set the idle time before the first keepalive is sent (system default if unset).
+    pub fn keepalives_idle(mut self, idle: Duration) -> Self {
+        self.keepalives_idle = Some(idle);
+        self
+    }
+
+    /// Set the time between unanswered keepalives (system default if unset).
+    pub fn keepalives_interval(mut self, interval: Duration) -> Self {
+        self.keepalives_interval = Some(interval);
+        self
+    }
+
+    /// Limit the number of prepared statements kept open on the server.
+    ///
+    /// Statements of the [statement cache](ConnectionConfig::statement_cache_size)
+    /// count as well. Once the limit is reached, preparing another statement closes
+    /// the least recently used cached one, or else either closes the oldest
+    /// prepared one or fails, depending on the [`StatementLimitPolicy`].
+    pub fn max_prepared_statements(mut self, limit: usize, policy: StatementLimitPolicy) -> Self {
+        self.max_prepared_statements = Some(limit);
+        self.statement_limit_policy = policy;
+        self
+    }
+
+    /// Cache up to `size` prepared statements by their query text (disabled by default).
+    ///
+    /// [`Connection::prepare_cached`], [`Connection::query_typed`] and pipelines
+    /// then reuse the statement when they see the same query again, instead of
+    /// parsing it anew. Once the cache is full, the least recently used statement
+    /// is closed. Cached statements count towards
+    /// [`ConnectionConfig::max_prepared_statements`].
+    ///
+    /// [`Connection::prepare_cached`]: crate::connection::Connection::prepare_cached
+    /// [`Connection::query_typed`]: crate::connection::Connection::query_typed
+    pub fn statement_cache_size(mut self, size: usize) -> Self {
+        self.statement_cache_size = size;
+        self
+    }
+
+    /// Fail simple queries, like [`Connection::query`], that return more than
+    /// `limit` rows instead of buffering all of them (unlimited by default).
+    ///
+    /// This is a guard against e.g. accidentally loading a huge table into
+    /// memory. The rest of the result is read and dropped, so the error can
+    /// tell how many rows there were.
+    ///
+    /// [`Connection::query`]: crate::connection::Connection::query
+    pub fn max_rows_buffered(mut self, limit: usize) -> Self {
+        self.max_rows_buffered = Some(limit);
+        self
+    }
+
+    /// Set the format extended-protocol queries, like [`Connection::query_typed`] and
+    /// pipelines, request their results in (text by default).
+    ///
+    /// Binary is faster to decode for e.g. numeric-heavy results, but requires
+    /// [`FromSql::from_binary`] support for every column type you read.
+    /// Text works for every type.
+    ///
+    /// [`Connection::query_typed`]: crate::connection::Connection::query_typed
+    /// [`FromSql::from_binary`]: crate::protocol::message::parsing::FromSql::from_binary
+    pub fn default_result_format(mut self, format: FormatCode) -> Self {
+        self.default_result_format = format;
+        self
+    }
+
+    /// Drop notices less severe than `severity` as soon as they are received
+    /// (all notices are kept by default).
+    ///
+    /// Notices whose severity isn't known are always kept.
+    pub fn min_notice_severity(mut self, severity: Severity) -> Self {
+        self.min_notice_severity = Some(severity);
+        self
+    }
+
+    /// Keep at most `max` notices until they are taken with
+    /// [`Connection::take_notices`] (1000 by default).
+    ///
+    /// Once there are more, the oldest one is dropped with a warning, so a
+    /// connection whose notices are never taken doesn't grow without bounds.
+    ///
+    /// [`Connection::take_notices`]: crate::connection::Connection::take_notices
+    pub fn max_notices(mut self, max: usize) -> Self {
+        self.max_notices = max;
+        self
+    }
+
+    /// Fail on messages of unknown types instead of skipping them (disabled by default).
+    ///
+    /// Newer servers may send messages this client doesn't know yet. By default
+    /// they are logged and skipped, so the connection stays usable.
+    pub fn strict_messages(mut self, strict: bool) -> Self {
+        self.strict_messages = strict;
+        self
+    }
+
+    /// Set the capacity of the buffer incoming messages are read into (8 KiB by default).
+    ///
+    /// The buffer is reused across messages. It grows to fit larger messages
+    /// but is shrunk back to this capacity afterwards.
+    pub fn read_buffer_capacity(mut self, capacity: usize) -> Self {
+        self.read_buffer_capacity = capacity;
+        self
+    }
+
+    /// Set the capacity of the buffer outgoing messages are encoded into (8 KiB by default).
+    ///
+    /// The buffer is reused across messages. It grows to fit larger messages
+    /// but is shrunk back to this capacity afterwards.
+    pub fn write_buffer_capacity(mut self, capacity: usize) -> Self {
+        self.write_buffer_capacity = capacity;
+        self
+    }
+
+    /// Set how much of each message sent or received is logged
+    /// (only the type and length by default).
+    #[cfg(feature = "trace-protocol")]
+    pub fn protocol_trace(mut self, protocol_trace: ProtocolTrace) -> Self {
+        self.protocol_trace = protocol_trace;
+        self
+    }
+}
+
+impl std::fmt::Debug for ConnectionConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut debug = f.debug_struct("ConnectionConfig");
+        debug
+            .field("host", &self.host)
+            .field("port", &self.port)
+            .field("hosts", &self.hosts)
+            .field("target_session_attrs", &self.target_session_attrs)
+            .field("user", &self.user)
+            // Keep the password out of logs
+            .field("password", &self.password.as_ref().map(|_| "***"))
+            .field("database", &self.database)
+            .field("application_name", &self.application_name)
+            .field("replication", &self.replication)
+            .field("address_family", &self.address_family)
+            .field("keepalives", &self.keepalives)
+            .field("keepalives_idle", &self.keepalives_idle)
+            .field("keepalives_interval", &self.keepalives_interval)
+            .field("max_prepared_statements", &self.max_prepared_statements)
+            .field("statement_limit_policy", &self.statement_limit_policy)
+            .field("statement_cache_size", &self.statement_cache_size)
+            .field("max_rows_buffered", &self.max_rows_buffered)
+            .field("default_result_format", &self.default_result_format)
+            .field("min_notice_severity", &self.min_notice_severity)
+            .field("max_notices", &self.max_notices)
+            .field("strict_messages", &self.strict_messages)
+            .field("read_buffer_capacity", &self.read_buffer_capacity)
+            .field("write_buffer_capacity", &self.write_buffer_capacity);
+        #[cfg(feature = "trace-protocol")]
+        debug.field("protocol_trace", &self.protocol_trace);
+        debug.finish()
+    }
+}
+
+impl Default for ConnectionConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
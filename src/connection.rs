@@ -1,49 +1,327 @@
 //! This module contains the networking part of the connection.
 //! Here, we write the messages to and read them from the buffer and handle them.
 
-use std::{collections::VecDeque, fmt::Display, net::IpAddr, sync::Arc};
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt::Display,
+    future::Future,
+    io,
+    net::{IpAddr, SocketAddr},
+    ops::Index,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
 
 use async_net::TcpStream;
-use futures_lite::{AsyncReadExt, AsyncWriteExt};
+use futures_lite::{future, ready, AsyncRead, AsyncWrite, AsyncWriteExt, Stream};
 use macro_rules_attribute::apply;
+use socket2::{SockRef, TcpKeepalive};
 use thiserror_lite::err_enum;
 
 use crate::{
-    protocol::message::{
-        client,
-        parsing::FromSql,
-        server::{self, Data, FormatCode, RowDescription},
+    cancel::CancelToken,
+    config::{ConnectionConfig, StatementLimitPolicy, TargetSessionAttrs},
+    protocol::{
+        message::{
+            client::{self, Encode},
+            parsing::{FromSql, ToSql},
+            server::{
+                self, Data, DataRow, FieldDescription, FormatCode, ParameterDescription,
+                RowDescription, TransactionStatus,
+            },
+        },
+        oids::Oid,
+        types::PgValue,
     },
-    util::{self, BoxError, DecodeError},
+    util::{self, BoxError, DecodeError, Writer},
     Error,
 };
 
 /// Errors that can occur when using the protocol.
 #[allow(missing_docs)]
 #[derive(Debug)]
+#[non_exhaustive]
 #[apply(err_enum)]
 pub enum ProtocolError {
     #[error("missing row description")]
     MissingRowDescription,
+    #[error("expected the server to start a copy")]
+    MissingCopyResponse,
+    #[error("expected the server to abort the copy")]
+    MissingCopyFailure,
+    #[error("missing command complete")]
+    MissingCommandComplete,
+    #[error("unexpected command tag `{0}`")]
+    UnexpectedCommandTag(String),
+    #[error("expected the connection to be idle")]
+    NotIdle,
+    #[error("the connection is not ready for a query, it is in state {0:?}")]
+    NotReady(ConnectionState),
+    #[error("received more than {0} unexpected messages")]
+    TooManyUnexpectedMessages(usize),
+}
+
+/// How many unexpected messages an operation may receive before it fails,
+/// so a misbehaving server can't make the connection buffer them forever.
+const MAX_UNEXPECTED_MESSAGES: usize = 1024;
+
+/// Where the connection is in the protocol, which decides what may be sent next.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ConnectionState {
+    /// The startup handshake hasn't finished yet.
+    Startup,
+    /// Ready for a query, outside of a transaction block.
+    ReadyIdle,
+    /// Ready for a query, inside of a transaction block.
+    ReadyInTransaction,
+    /// Ready for a query, inside of a failed transaction block that only
+    /// accepts a rollback.
+    ReadyInFailedTransaction,
+    /// The server waits for the data of a `COPY ... FROM STDIN`.
+    CopyIn,
+    /// The server sends the data of a `COPY ... TO STDOUT`.
+    CopyOut,
+    /// Both sides send copy data, e.g. during streaming replication.
+    CopyBoth,
+    /// Something was sent and the server isn't ready for the next query yet.
+    AwaitingResponse,
+}
+
+impl ConnectionState {
+    /// Whether the server is ready for the next query.
+    pub fn is_ready(&self) -> bool {
+        matches!(
+            self,
+            ConnectionState::ReadyIdle
+                | ConnectionState::ReadyInTransaction
+                | ConnectionState::ReadyInFailedTransaction
+        )
+    }
+}
+
+impl From<TransactionStatus> for ConnectionState {
+    fn from(status: TransactionStatus) -> Self {
+        match status {
+            TransactionStatus::Idle => ConnectionState::ReadyIdle,
+            TransactionStatus::InTransaction => ConnectionState::ReadyInTransaction,
+            TransactionStatus::Failed => ConnectionState::ReadyInFailedTransaction,
+        }
+    }
+}
+
+/// Errors that can occur while authenticating with the server.
+#[allow(missing_docs)]
+#[derive(Debug)]
+#[non_exhaustive]
+#[apply(err_enum)]
+pub enum AuthenticationError {
+    #[error("no supported SASL mechanism, the server offered {0:?}")]
+    NoSupportedSaslMechanism(Vec<String>),
+    #[error("unsupported authentication method, request type `{0}`")]
+    UnsupportedMethod(i32),
+    #[error("GSSAPI authentication is not supported")]
+    GssNotSupported,
+    #[error("SSPI authentication is not supported")]
+    SspiNotSupported,
+}
+
+/// The result columns of a prepared statement didn't match the expected ones.
+#[allow(missing_docs)]
+#[derive(Debug)]
+#[apply(err_enum)]
+pub enum SchemaMismatch {
+    #[error("expected {expected} result columns, got {actual}")]
+    ColumnCount { expected: usize, actual: usize },
+    #[error("expected {expected} result rows, got {actual}")]
+    RowCount { expected: usize, actual: usize },
+    #[error("expected column `{column}` to have type oid {expected}, got {actual}")]
+    ColumnType {
+        column: String,
+        expected: i32,
+        actual: i32,
+    },
 }
 
 /// Attempted and failed to access a field of a row because it doesn't exist.
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct FieldNotFound(String);
 
+/// Attempted and failed to read a field of a row.
+#[derive(Debug)]
+pub struct ColumnError {
+    /// The name of the column we tried to read.
+    column: String,
+    /// Why reading the column failed.
+    source: BoxError,
+}
+
+/// Attempted and failed to convert a row of a result set using [`FromRow`].
+#[derive(Debug)]
+pub struct FromRowError {
+    /// The (zero-based) index of the row in the result set.
+    row: usize,
+    /// The column that couldn't be converted.
+    error: ColumnError,
+}
+
+/// A type that can be constructed from a [`Row`] of a result set.
+///
+/// Implementations should read their fields using [`Row::try_get`]
+/// so that errors carry the name of the offending column.
+pub trait FromRow: Sized {
+    /// Construct a value from a row.
+    fn from_row(row: &Row) -> Result<Self, ColumnError>;
+}
+
+/// A type that can be constructed from a [`Row`] and borrow from it,
+/// e.g. a tuple of `&str`s, to read rows without allocating.
+///
+/// Every [`FromRow`] tuple of owned values implements it as well.
+pub trait FromRowRef<'a>: Sized {
+    /// Construct a value borrowing from a row.
+    fn from_row_ref(row: &'a Row) -> Result<Self, ColumnError>;
+}
+
+/// A way to pick a field of a [`Row`]: its name or its (zero-based) position.
+pub trait ColumnIndex: Display {
+    /// The position of the field among the columns, if there is one.
+    fn index_in(&self, columns: &[FieldDescription]) -> Option<usize>;
+}
+
+impl ColumnIndex for str {
+    fn index_in(&self, columns: &[FieldDescription]) -> Option<usize> {
+        columns.iter().position(|column| column.name == self)
+    }
+}
+
+impl ColumnIndex for String {
+    fn index_in(&self, columns: &[FieldDescription]) -> Option<usize> {
+        self.as_str().index_in(columns)
+    }
+}
+
+impl<T: ColumnIndex + ?Sized> ColumnIndex for &T {
+    fn index_in(&self, columns: &[FieldDescription]) -> Option<usize> {
+        (**self).index_in(columns)
+    }
+}
+
+impl ColumnIndex for usize {
+    fn index_in(&self, columns: &[FieldDescription]) -> Option<usize> {
+        (*self < columns.len()).then_some(*self)
+    }
+}
+
+/// Read the columns of a row in order.
+macro_rules! impl_from_row_for_tuple {
+    ($($index:tt $name:ident),+) => {
+        impl<$($name: for<'a> FromSql<'a>),+> FromRow for ($($name,)+) {
+            fn from_row(row: &Row) -> Result<Self, ColumnError> {
+                Ok(($(row.try_get::<$name>($index)?,)+))
+            }
+        }
+
+        impl<'a, $($name: FromSql<'a>),+> FromRowRef<'a> for ($($name,)+) {
+            fn from_row_ref(row: &'a Row) -> Result<Self, ColumnError> {
+                Ok(($(row.try_get::<$name>($index)?,)+))
+            }
+        }
+    };
+}
+
+impl_from_row_for_tuple!(0 A);
+impl_from_row_for_tuple!(0 A, 1 B);
+impl_from_row_for_tuple!(0 A, 1 B, 2 C);
+impl_from_row_for_tuple!(0 A, 1 B, 2 C, 3 D);
+impl_from_row_for_tuple!(0 A, 1 B, 2 C, 3 D, 4 E);
+impl_from_row_for_tuple!(0 A, 1 B, 2 C, 3 D, 4 E, 5 F);
+impl_from_row_for_tuple!(0 A, 1 B, 2 C, 3 D, 4 E, 5 F, 6 G);
+impl_from_row_for_tuple!(0 A, 1 B, 2 C, 3 D, 4 E, 5 F, 6 G, 7 H);
+
+/// A bi-directional byte stream a [`Connection`] can run over,
+/// e.g. a TCP socket or an SSH tunnel.
+///
+/// It is implemented for every type that implements the required traits.
+pub trait Transport: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> Transport for T {}
+
 /// A connection to a PostgreSQL server.
 ///
 /// This struct is generic over all transport layers
-/// that implement the required traits.
+/// that implement [`Transport`].
+///
+/// Dropping the future of a query, e.g. on a timeout, doesn't cancel the query
+/// on the server. Instead, the rest of its response is skipped before the next
+/// query is sent, so the connection stays usable. If the future is dropped
+/// while the query is only partially sent, the connection is closed.
 pub struct Connection {
     /// The bi-directional stream that is the transport layer.
-    stream: TcpStream,
-    /// Here we buffer responses from the server until we handle them.
+    stream: Box<dyn Transport>,
+    /// Messages the last operation didn't expect and nothing else handles,
+    /// see [`Connection::unexpected_messages`]. Cleared when the next operation
+    /// starts and limited to [`MAX_UNEXPECTED_MESSAGES`].
     response_buffer: VecDeque<server::Message>,
-    /// Whether we are ready to send a query to the server.
-    ready_to_query: bool,
+    /// The notices the server sent that weren't taken yet, oldest first.
+    notices: VecDeque<server::Notice>,
+    /// Where we are in the protocol.
+    pub(crate) state: ConnectionState,
     /// The key data from the backend we need to cancel queries.
     key_data: Option<server::KeyData>,
+    /// The address of the server, if we opened the TCP connection ourselves.
+    peer_address: Option<SocketAddr>,
+    /// The configuration this connection was opened with.
+    pub(crate) config: ConnectionConfig,
+    /// The names of the statements we prepared, oldest first.
+    prepared_statements: VecDeque<String>,
+    /// The statements cached by their query, least recently used first.
+    statement_cache: VecDeque<CachedStatement>,
+    /// The number used to name the next prepared statement.
+    next_statement_id: u32,
+    /// The buffer we read messages into, reused across messages.
+    read_buffer: Vec<u8>,
+    /// How much of the current message was read into the read buffer.
+    read_position: usize,
+    /// The buffer we encode messages into until they are sent, reused across messages.
+    pub(crate) write_buffer: Writer,
+    /// Whether the server closed the connection.
+    closed: bool,
+    /// Whether a `ReadyForQuery` is on its way, because a query or `Sync` was sent.
+    sync_pending: bool,
+    /// Whether a [`Batch`](crate::pipeline::Batch) sent queries that still need a `Sync`.
+    unsynced: bool,
+    /// Whether the batch that sent those queries is gone, so they are rolled back.
+    batch_abandoned: bool,
+    /// Whether messages are being written to the stream, still `true` on the next
+    /// send if that write was cancelled halfway.
+    sending: bool,
+    /// The transaction status of the last `ReadyForQuery`.
+    transaction_status: TransactionStatus,
+    /// The current values of the parameters the server reported, e.g. `server_version`.
+    parameters: HashMap<String, String>,
+}
+
+/// A statement that was prepared on the server.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Statement {
+    /// The name of the statement on the server.
+    name: String,
+    /// The object IDs of the parameter types.
+    parameter_types: Vec<i32>,
+    /// The description of the result columns, if the statement returns any rows.
+    row_description: Option<Arc<RowDescription>>,
+}
+
+/// A statement in the cache of a [`Connection`].
+struct CachedStatement {
+    /// The query text the statement was prepared from.
+    query: String,
+    /// The explicitly given parameter types, empty if the server inferred them.
+    parameter_types: Vec<Oid>,
+    /// The prepared statement.
+    statement: Statement,
 }
 
 /// A row in a result set.
@@ -51,8 +329,8 @@ pub struct Connection {
 pub struct Row {
     /// The reference to the metadata of this row.
     metadata: Arc<RowDescription>,
-    /// The fields in this row.
-    fields: Vec<Data>,
+    /// The fields in this row, shared between clones.
+    fields: Arc<[Data]>,
 }
 
 impl Connection {
@@ -61,19 +339,103 @@ impl Connection {
     ///
     /// Uses port `5432` if none is provided.
     pub async fn create(address: IpAddr, port: Option<u16>) -> Result<Self, Error> {
-        let port = port.unwrap_or(crate::POSTGRES_DEFAULT_PORT);
+        let config = ConnectionConfig::new()
+            .host(address.to_string())
+            .port(port.unwrap_or(crate::POSTGRES_DEFAULT_PORT));
+
+        Self::connect(config).await
+    }
+
+    /// Open and return a new connection to the PostgreSQL server
+    /// described by the configuration.
+    ///
+    /// With several [`hosts`](ConnectionConfig::hosts), they are tried in
+    /// order and the first one that matches the
+    /// [`target_session_attrs`](ConnectionConfig::target_session_attrs) is used.
+    /// If none does, the error of the last one is returned.
+    pub async fn connect(config: ConnectionConfig) -> Result<Self, Error> {
+        let mut last_error = None;
+
+        for (host, port) in config.candidates() {
+            let mut candidate = config.clone();
+            candidate.host = host.clone();
+            candidate.port = port;
+
+            let result = match Self::connect_host(candidate).await {
+                Ok(mut connection) => connection.check_session_attrs().await.map(|()| connection),
+                Err(error) => Err(error),
+            };
+
+            match result {
+                Ok(connection) => return Ok(connection),
+                Err(error) => {
+                    tracing::debug!(%host, port, %error, "Skipping server");
+                    last_error = Some(error);
+                }
+            }
+        }
+
+        Err(last_error.unwrap_or(Error::NoMatchingServer(config.target_session_attrs)))
+    }
+
+    /// Check whether the session matches the `target_session_attrs`.
+    async fn check_session_attrs(&mut self) -> Result<(), Error> {
+        let target = self.config.target_session_attrs;
+        if target == TargetSessionAttrs::Any {
+            return Ok(());
+        }
+
+        let read_only = self
+            .query_scalar::<String>("SHOW transaction_read_only")
+            .await?;
+        if !target.matches(read_only == "on") {
+            return Err(Error::NoMatchingServer(target));
+        }
+
+        Ok(())
+    }
 
+    /// Open a connection to the single server in `config.host` and `config.port`.
+    async fn connect_host(config: ConnectionConfig) -> Result<Self, Error> {
         // Create the TCP connection
-        let stream = TcpStream::connect((address, port))
-            .await
-            .map_err(Error::NetworkError)?;
+        let stream = Self::connect_tcp(&config).await?;
 
+        let peer_address = stream.peer_addr().ok();
+        let mut connection = Self::with_stream(stream, config).await?;
+        connection.peer_address = peer_address;
+
+        Ok(connection)
+    }
+
+    /// Open a connection over an already established stream, e.g. one
+    /// through a proxy or tunnel, by running only the startup handshake.
+    ///
+    /// The host, port, address family and keepalive settings of the
+    /// configuration are not used, the stream is already connected.
+    pub async fn with_stream(
+        stream: impl Transport + 'static,
+        config: ConnectionConfig,
+    ) -> Result<Self, Error> {
         // Create the connection
         let mut conn = Self::new(stream);
 
-        // Startup routine
-        let startup_message = client::Startup::new("postgres".to_string(), None, None);
-        conn.send_message(&startup_message).await?;
+        let mut startup_message =
+            client::Startup::new(config.user.clone(), config.database.clone(), None);
+        if let Some(application_name) = &config.application_name {
+            startup_message =
+                startup_message.parameter("application_name".into(), application_name.clone());
+        }
+        if let Some(replication) = config.replication {
+            startup_message =
+                startup_message.parameter("replication".into(), replication.parameter().into());
+        }
+        conn.read_buffer = Vec::with_capacity(config.read_buffer_capacity);
+        conn.write_buffer = Writer::with_capacity(config.write_buffer_capacity);
+        conn.config = config;
+
+        // Startup routine, the connection isn't ready for regular messages yet
+        conn.write_message(&startup_message);
+        conn.send_write_buffer().await?;
 
         // Buffer all messages until we are ready to query
         loop {
@@ -81,38 +443,225 @@ impl Connection {
 
             tracing::debug!(response=?&response, "Received message from server");
 
-            // We won't handle any messages until we are ready to query
+            // Apart from authentication, we won't handle any messages until we are ready to query
             match response {
-                server::Message::ReadyForQuery => {
-                    conn.ready_to_query = true;
-                    break;
+                server::Message::ReadyForQuery => break,
+                server::Message::Authentication(server::Authentication::Ok) => {}
+                // We don't implement any SASL mechanism yet
+                server::Message::Authentication(server::Authentication::Sasl(mechanisms)) => {
+                    return Err(AuthenticationError::NoSupportedSaslMechanism(mechanisms).into());
+                }
+                server::Message::Authentication(server::Authentication::Gss) => {
+                    return Err(AuthenticationError::GssNotSupported.into());
                 }
-                otherwise => conn.response_buffer.push_back(otherwise),
+                server::Message::Authentication(server::Authentication::Sspi) => {
+                    return Err(AuthenticationError::SspiNotSupported.into());
+                }
+                server::Message::Authentication(server::Authentication::Unsupported(
+                    request_type,
+                )) => {
+                    return Err(AuthenticationError::UnsupportedMethod(request_type).into());
+                }
+                // The server rejected the connection and will close it
+                server::Message::Error(error) => return Err(Error::QueryError(error)),
+                otherwise => conn.buffer_message(otherwise)?,
             }
         }
 
         Ok(conn)
     }
 
+    /// Open a TCP connection to the first reachable address of the server
+    /// that matches the configured address family, with the configured keepalives.
+    ///
+    /// This is the stream [`Connection::connect`] runs over, e.g. to wrap it
+    /// before passing it to [`Connection::with_stream`].
+    pub async fn connect_tcp(config: &ConnectionConfig) -> Result<TcpStream, Error> {
+        let addresses = async_net::resolve((config.host.as_str(), config.port))
+            .await
+            .map_err(Error::NetworkError)?;
+
+        let mut last_error = None;
+        for address in addresses
+            .into_iter()
+            .filter(|address| config.address_family.matches(address))
+        {
+            match TcpStream::connect(address).await {
+                Ok(stream) => return Self::configure_keepalives(stream, config),
+                Err(error) => {
+                    tracing::debug!(%address, %error, "Failed to connect, trying the next address");
+                    last_error = Some(error);
+                }
+            }
+        }
+
+        Err(Error::NetworkError(last_error.unwrap_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::AddrNotAvailable,
+                format!(
+                    "`{}` has no address of family {:?}",
+                    config.host, config.address_family
+                ),
+            )
+        })))
+    }
+
+    /// Enable TCP keepalives on `stream` if configured.
+    fn configure_keepalives(
+        stream: TcpStream,
+        config: &ConnectionConfig,
+    ) -> Result<TcpStream, Error> {
+        if config.keepalives {
+            let mut keepalive = TcpKeepalive::new();
+            if let Some(idle) = config.keepalives_idle {
+                keepalive = keepalive.with_time(idle);
+            }
+            if let Some(interval) = config.keepalives_interval {
+                keepalive = keepalive.with_interval(interval);
+            }
+
+            SockRef::from(&stream)
+                .set_tcp_keepalive(&keepalive)
+                .map_err(Error::NetworkError)?;
+        }
+
+        Ok(stream)
+    }
+
+    /// Send a query to the server like [`Connection::query`], but give up
+    /// once `cancel` completes, e.g. on a shutdown signal.
+    ///
+    /// On cancellation, a cancel request is sent like with
+    /// [`Connection::cancel_token`], the rest of the response is skipped and [`Error::Cancelled`]
+    /// is returned, even if the query finished in the meantime.
+    pub async fn query_until(
+        &mut self,
+        query: &str,
+        cancel: impl Future,
+    ) -> Result<Vec<Row>, Error> {
+        let result = future::or(async { Some(self.query(query).await) }, async {
+            cancel.await;
+            None
+        })
+        .await;
+        if let Some(result) = result {
+            return result;
+        }
+
+        match self.cancel_token() {
+            Some(token) => {
+                if let Err(error) = token.cancel().await {
+                    tracing::warn!(%error, "Failed to send a cancel request");
+                }
+            }
+            None => tracing::warn!("The server sent no key data, can't cancel the query"),
+        }
+        self.settle().await?;
+
+        Err(Error::Cancelled)
+    }
+
+    /// A handle to cancel the query this connection is running, e.g. from
+    /// a spawned task, see [`CancelToken::cancel`].
+    ///
+    /// The cancel request goes to the same server address, or to the configured
+    /// host and port for a connection opened [`with_stream`](Connection::with_stream).
+    /// Returns `None` if the server sent no key data to cancel queries with.
+    pub fn cancel_token(&self) -> Option<CancelToken> {
+        let key_data = self.key_data.as_ref()?;
+
+        Some(CancelToken {
+            peer_address: self.peer_address,
+            host: self.config.host.clone(),
+            port: self.config.port,
+            process_id: key_data.process_id(),
+            secret_key: key_data.secret_key(),
+        })
+    }
+
     /// Send a query to the server.
+    ///
+    /// Statements that don't return rows, like `INSERT` or `CREATE TABLE`,
+    /// return no rows.
+    ///
+    /// The results are always in text format, the simple query protocol
+    /// can't request binary. Use [`Connection::query_binary`] for that.
     pub async fn query(&mut self, query: &str) -> Result<Vec<Row>, Error> {
+        let (rows, _) = self.query_with_count(query).await?;
+
+        Ok(rows)
+    }
+
+    /// Send a query to the server and return its rows along with the number
+    /// of rows it affected, see [`server::CommandComplete::rows`].
+    ///
+    /// This is useful for statements like `INSERT ... RETURNING id`, whose
+    /// returned rows are the affected ones. With several statements in the
+    /// query, the count is the one of the last statement.
+    pub async fn query_with_count(
+        &mut self,
+        query: &str,
+    ) -> Result<(Vec<Row>, Option<u64>), Error> {
+        let (row_description, data_rows, command_complete) = self.simple_query(query).await?;
+        let count = command_complete.and_then(|complete| complete.rows());
+
+        // Statements like `INSERT` without `RETURNING` or DDL don't return rows
+        if data_rows.is_empty() {
+            return Ok((Vec::new(), count));
+        }
+
+        // We received the complete response, now we can return the rows
+        let row_description =
+            Arc::new(row_description.ok_or(ProtocolError::MissingRowDescription)?);
+
+        let rows = data_rows
+            .into_iter()
+            .map(|data_row| Row {
+                metadata: row_description.clone(),
+                fields: data_row.fields,
+            })
+            .collect();
+
+        Ok((rows, count))
+    }
+
+    /// Send a simple query to the server and return the row description,
+    /// data rows and last command completion of the response.
+    async fn simple_query(
+        &mut self,
+        query: &str,
+    ) -> Result<
+        (
+            Option<RowDescription>,
+            Vec<DataRow>,
+            Option<server::CommandComplete>,
+        ),
+        Error,
+    > {
         let query_message = client::Query::new(query.to_string());
         self.send_message(&query_message).await?;
 
         let mut row_description = None;
         let mut data_rows = Vec::new();
+        let mut last_complete = None;
+        // Including the rows dropped because of `max_rows_buffered`
+        let mut received = 0;
+
+        let mut error = None;
 
-        // Read messages until we have a command complete message
+        // Read messages until the server is ready for the next query
         loop {
             let response = self.read_message().await?;
 
             tracing::debug!(response=?&response, "Received message from server");
 
             match response {
-                // Command complete means we are done reading messages for this query
+                // Ready for query means we are done reading messages for this query
+                server::Message::ReadyForQuery => break,
+                // Command complete means the result set is complete
                 server::Message::CommandComplete(command_complete) => {
                     tracing::debug!(command_complete=?command_complete, "Command complete");
-                    break;
+                    last_complete = Some(command_complete);
                 }
                 // Row description is the header info for the result set
                 server::Message::RowDescription(description) => {
@@ -121,169 +670,1483 @@ impl Connection {
                 // Data row is a row in the result set
                 server::Message::DataRow(data_row) => {
                     tracing::debug!(data_row=?data_row, "Data row");
-                    data_rows.push(data_row);
+                    received += 1;
+                    if self
+                        .config
+                        .max_rows_buffered
+                        .is_none_or(|limit| data_rows.len() < limit)
+                    {
+                        data_rows.push(data_row);
+                    }
                 }
-                // Error means something went wrong
-                server::Message::Error(error) => {
-                    tracing::error!(error=?error, "Query error");
-                    panic!("oops");
+                // Error means something went wrong, the server will skip to ready for query
+                server::Message::Error(response) => {
+                    tracing::error!(error=?response, "Query error");
+                    error = Some(response);
                 }
                 // Otherwise, we just buffer this message for later processing
-                otherwise => self.response_buffer.push_back(otherwise),
+                otherwise => self.buffer_message(otherwise)?,
             }
         }
 
-        // We received the complete response, now we can return the rows
-        let row_description =
-            Arc::new(row_description.ok_or(ProtocolError::MissingRowDescription)?);
-
-        let rows = data_rows
-            .into_iter()
-            .map(|data_row| Row {
-                metadata: row_description.clone(),
-                fields: data_row.fields,
-            })
-            .collect();
+        if let Some(error) = error {
+            return Err(Error::QueryError(error));
+        }
+        if received > data_rows.len() {
+            return Err(Error::TooManyRows {
+                limit: data_rows.len(),
+                received,
+            });
+        }
 
-        Ok(rows)
+        Ok((row_description, data_rows, last_complete))
     }
 
-    /// Create a new connection from a bi-directional stream.
-    pub fn new(stream: TcpStream) -> Self {
-        Self {
-            stream,
-            response_buffer: VecDeque::new(),
-            ready_to_query: false,
-            key_data: None,
+    /// Send a query to the server and return every message of the response in order,
+    /// without assembling rows.
+    ///
+    /// The final `ReadyForQuery` is not included. Error responses are returned as
+    /// [`server::Message::Error`] like any other message.
+    pub async fn query_raw(&mut self, query: &str) -> Result<Vec<server::Message>, Error> {
+        let query_message = client::Query::new(query.to_string());
+        self.send_message(&query_message).await?;
+
+        let mut messages = Vec::new();
+
+        // Read messages until the server is ready for the next query
+        loop {
+            let response = self.read_message().await?;
+
+            tracing::debug!(response=?&response, "Received message from server");
+
+            match response {
+                server::Message::ReadyForQuery => break,
+                otherwise => messages.push(otherwise),
+            }
         }
+
+        Ok(messages)
     }
 
-    /// Send a message to the server.
-    async fn send_message(&mut self, message: impl Into<Vec<u8>>) -> Result<(), Error> {
-        // Write the message to the stream
-        self.stream
-            .write_all(&message.into())
-            .await
-            .map_err(Error::NetworkError)?;
+    /// Run a script of one or more statements separated by semicolons, e.g. a
+    /// schema migration, and discard their results.
+    ///
+    /// The statements run in one implicit transaction unless the script contains
+    /// explicit transaction control. The first error stops the script and is returned.
+    pub async fn batch_execute(&mut self, script: &str) -> Result<(), Error> {
+        let query_message = client::Query::new(script.to_string());
+        self.send_message(&query_message).await?;
 
-        // Flush the stream to ensure the message is sent
-        self.stream.flush().await.map_err(Error::NetworkError)?;
+        let mut error = None;
+
+        // Read messages until the server is ready for the next query
+        loop {
+            let response = self.read_message().await?;
+
+            tracing::debug!(response=?&response, "Received message from server");
+
+            match response {
+                server::Message::ReadyForQuery => break,
+                // Every statement has its own result, we don't need any of them
+                server::Message::CommandComplete(_)
+                | server::Message::EmptyQuery
+                | server::Message::RowDescription(_)
+                | server::Message::DataRow(_) => {}
+                // The server skips the rest of the script after an error
+                server::Message::Error(response) => {
+                    tracing::error!(error=?response, "Batch error");
+                    error = Some(response);
+                }
+                otherwise => self.buffer_message(otherwise)?,
+            }
+        }
+
+        if let Some(error) = error {
+            return Err(Error::QueryError(error));
+        }
 
         Ok(())
     }
 
-    /// Read a message from the stream, appending it to the buffer (resizing it if necessary).
-    async fn read_message(&mut self) -> Result<server::Message, Error> {
-        // This is how many bytes of header each response has
-        const HEADER_LENGTH: usize = 5;
-
-        // Read the message type
-        let mut message_type_buf = [0; 1];
-        self.stream
-            .read_exact(&mut message_type_buf)
-            .await
-            .map_err(Error::NetworkError)?;
+    /// Send a query to the server and return every message of the response as a raw
+    /// frame of message type and body (without the length), up to and including
+    /// `ReadyForQuery`.
+    ///
+    /// Nothing is decoded, so the frames can be forwarded verbatim, except that
+    /// parameter statuses still update [`Connection::parameter`]. If the stream
+    /// is dropped early, the rest of the response is skipped before the next query.
+    pub fn query_raw_frames<'a>(
+        &'a mut self,
+        query: &str,
+    ) -> impl Stream<Item = Result<(u8, Vec<u8>), Error>> + 'a {
+        let query_message = Some(client::Query::new(query.to_string()));
 
-        // Read the message length
-        let mut message_length_buf = [0; 4];
-        self.stream
-            .read_exact(&mut message_length_buf)
-            .await
-            .map_err(Error::NetworkError)?;
+        // The state is the connection, the query until it is sent and whether we are done
+        futures_lite::stream::unfold(
+            (self, query_message, false),
+            |(connection, query_message, done)| async move {
+                if done {
+                    return None;
+                }
 
-        // Convert message length and sanity check
-        let message_length = i32::from_be_bytes(message_length_buf);
-        if message_length < 4 {
-            return Err(Error::CodecError(
-                DecodeError::UnexpectedValue("message length implausibly small".to_string()).into(),
-            ));
-        }
+                if let Some(query_message) = query_message {
+                    if let Err(error) = connection.send_message(&query_message).await {
+                        return Some((Err(error), (connection, None, true)));
+                    }
+                }
 
-        // Actual message length is one byte larger since it doesn't include the message type
-        let actual_message_length = message_length as usize + 1;
+                if let Err(error) = connection.read_frame().await {
+                    return Some((Err(error), (connection, None, true)));
+                }
 
-        // Make sure there is enough space in the buffer
-        let mut buf = Vec::with_capacity(actual_message_length);
+                // Skip the message type and length
+                let message_type = connection.read_buffer[0];
+                let body = connection.read_buffer[5..].to_vec();
 
-        // Add the message type and length to the buffer
-        buf.extend_from_slice(&message_type_buf);
-        buf.extend_from_slice(&message_length_buf);
+                // Parameter statuses are still applied, e.g. after a `SET TimeZone`
+                if message_type == b'S' {
+                    if let Err(error) = connection.decode_frame() {
+                        return Some((Err(error), (connection, None, true)));
+                    }
+                } else {
+                    connection.shrink_read_buffer();
+                }
 
-        // Fill the buffer with zeros where the message content will be written
-        buf.resize(actual_message_length, 0u8);
+                let done = message_type == b'Z';
+                Some((Ok((message_type, body)), (connection, None, done)))
+            },
+        )
+    }
 
-        // Read the message content
-        self.stream
-            .read_exact(&mut buf[HEADER_LENGTH..])
-            .await
-            .map_err(Error::NetworkError)?;
+    /// Send a query to the server and convert every row of the result
+    /// into a `T`.
+    ///
+    /// If a row can't be converted, the returned error names the row index
+    /// and the column that caused the failure.
+    pub async fn query_as<T: FromRow>(&mut self, query: &str) -> Result<Vec<T>, Error> {
+        let rows = self.query(query).await?;
 
-        // Decode the message
-        let message =
-            server::Message::try_from(util::Reader::new(&buf)).map_err(Error::CodecError)?;
+        let values = rows
+            .iter()
+            .enumerate()
+            .map(|(index, row)| {
+                T::from_row(row).map_err(|error| FromRowError { row: index, error })
+            })
+            .collect::<Result<Vec<T>, FromRowError>>()?;
 
-        Ok(message)
+        Ok(values)
     }
 
-    /// Read a message from the stream now, without waiting for more data,
-    /// or return `None` if there are no bytes available to read.
-    async fn read_message_now(&mut self) -> Result<Option<server::Message>, Error> {
-        tracing::trace!("Checking for available bytes");
+    /// Send a query that returns a single value, like `SELECT count(*) FROM ...`,
+    /// and parse it into a `T`.
+    ///
+    /// Fails if the result doesn't have exactly one row and one column.
+    pub async fn query_scalar<T: for<'a> FromSql<'a>>(&mut self, query: &str) -> Result<T, Error> {
+        let (row_description, data_rows, _) = self.simple_query(query).await?;
 
-        // If there are no bytes available, return `None`
-        if !self.has_bytes().await? {
-            tracing::trace!("No bytes available to read");
-            return Ok(None);
-        }
+        // Parse the value straight from the data row, building a `Row` would
+        // cost more than the query for probes like `SELECT 1`
+        let [data_row] = data_rows.as_slice() else {
+            return Err(SchemaMismatch::RowCount {
+                expected: 1,
+                actual: data_rows.len(),
+            }
+            .into());
+        };
+        let row_description = row_description.ok_or(ProtocolError::MissingRowDescription)?;
 
-        tracing::trace!("Bytes available, reading message");
+        let [column] = row_description.fields.as_slice() else {
+            return Err(SchemaMismatch::ColumnCount {
+                expected: 1,
+                actual: row_description.fields.len(),
+            }
+            .into());
+        };
+        let [field] = &data_row.fields[..] else {
+            return Err(SchemaMismatch::ColumnCount {
+                expected: 1,
+                actual: data_row.fields.len(),
+            }
+            .into());
+        };
 
-        // Otherwise, read the message
-        Ok(Some(self.read_message().await?))
+        parse_column(column, field).map_err(|error| FromRowError { row: 0, error }.into())
     }
 
-    /// Check whether there are any bytes available to read.
-    async fn has_bytes(&mut self) -> Result<bool, Error> {
-        let mut buf = [0u8; 1];
+    /// Run a query with parameters (`$1`, `$2`, ...) and return its rows
+    /// in binary format, regardless of [`ConnectionConfig::default_result_format`].
+    ///
+    /// Parsing binary values is usually faster than parsing text, and
+    /// [`Row::get_and_parse`] reads them with [`FromSql::from_binary`].
+    pub async fn query_binary(
+        &mut self,
+        query: &str,
+        parameters: &[&dyn ToSql],
+    ) -> Result<Vec<Row>, Error> {
+        let mut pipeline = self.pipeline();
+        pipeline
+            .result_format(FormatCode::Binary)
+            .push(query, parameters);
 
-        // Peek at the first byte with a timeout of 0 to avoid blocking
-        let n = futures_lite::future::or(
-            self.stream.peek(&mut buf),
-            futures_lite::future::ready(Ok(0)),
-        )
-        .await
-        .map_err(Error::NetworkError)?;
+        let mut results = pipeline.run().await?;
 
-        Ok(n > 0)
+        Ok(results.pop().unwrap_or_default())
     }
-}
 
-impl Row {
-    /// Get the value of a field by its name.
-    pub fn get(&self, name: &str) -> Option<&Data> {
-        let index = self.metadata.field_index(name)?;
+    /// Run a query with parameters (`$1`, `$2`, ...) of explicitly given types.
+    ///
+    /// Use this if the server can't infer the type of a parameter from
+    /// its context, e.g. `SELECT $1`.
+    pub async fn query_typed(
+        &mut self,
+        query: &str,
+        parameters: &[(&dyn ToSql, Oid)],
+    ) -> Result<Vec<Row>, Error> {
+        let mut pipeline = self.pipeline();
+        pipeline.push_typed(query, parameters);
 
-        self.fields.get(index)
+        let mut results = pipeline.run().await?;
+
+        Ok(results.pop().unwrap_or_default())
     }
 
-    /// Get the value of a field and parse it to a specific type.
-    pub fn get_and_parse<'a, T: FromSql<'a>>(&'a self, name: &str) -> Result<T, BoxError> {
-        let data = self
-            .get(name)
-            .ok_or_else(|| Box::new(FieldNotFound(name.to_owned())))?;
+    /// Prepare a statement on the server without executing it.
+    ///
+    /// The server infers the types of the parameters and the result columns.
+    ///
+    /// If [`ConnectionConfig::max_prepared_statements`] is reached, this closes
+    /// the least recently used cached statement, or else either closes the
+    /// oldest statement (invalidating its [`Statement`]) or fails.
+    pub async fn prepare(&mut self, query: &str) -> Result<Statement, Error> {
+        self.make_room_for_statement().await?;
 
-        let field_index = self.metadata.field_index(name).unwrap();
-        match self.metadata.fields[field_index].format_code {
-            FormatCode::Binary => data.parse_binary(),
-            FormatCode::Text => data.parse_text(),
-        }
+        let statement = self.prepare_named(query, Vec::new()).await?;
+        self.prepared_statements.push_back(statement.name.clone());
+
+        Ok(statement)
     }
-}
 
-impl Display for FieldNotFound {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "row doesn't contain field `{}`", &self.0)
+    /// Prepare a statement, or reuse the one prepared for the same query before.
+    ///
+    /// Statements are cached with [`ConnectionConfig::statement_cache_size`]. Once
+    /// the cache is full, the least recently used statement is closed, which
+    /// invalidates its [`Statement`]. Without a cache, this is [`Connection::prepare`].
+    pub async fn prepare_cached(&mut self, query: &str) -> Result<Statement, Error> {
+        if self.config.statement_cache_size == 0 {
+            return self.prepare(query).await;
+        }
+
+        self.prepare_cached_typed(query, &[]).await
     }
-}
 
-impl std::error::Error for FieldNotFound {}
+    /// Prepare a statement with explicit parameter types through the statement cache.
+    ///
+    /// The cache must be enabled.
+    pub(crate) async fn prepare_cached_typed(
+        &mut self,
+        query: &str,
+        parameter_types: &[Oid],
+    ) -> Result<Statement, Error> {
+        let cached_index = self
+            .statement_cache
+            .iter()
+            .position(|cached| cached.query == query && cached.parameter_types == parameter_types);
+
+        // Move a hit to the back, as it's now the most recently used one
+        if let Some(index) = cached_index {
+            let cached = self
+                .statement_cache
+                .remove(index)
+                .expect("cached statement at the found index");
+            let statement = cached.statement.clone();
+            self.statement_cache.push_back(cached);
+            return Ok(statement);
+        }
+
+        if self.statement_cache.len() >= self.config.statement_cache_size {
+            if let Some(oldest) = self.statement_cache.pop_front() {
+                self.close_statement(&oldest.statement.name).await?;
+            }
+        }
+        self.make_room_for_statement().await?;
+
+        let statement = self.prepare_named(query, parameter_types.to_vec()).await?;
+        self.statement_cache.push_back(CachedStatement {
+            query: query.to_string(),
+            parameter_types: parameter_types.to_vec(),
+            statement: statement.clone(),
+        });
+
+        Ok(statement)
+    }
+
+    /// How many statements can be cached without closing one another, limited
+    /// by the cache size and the room [`ConnectionConfig::max_prepared_statements`]
+    /// leaves next to the prepared statements.
+    pub(crate) fn cacheable_statements(&self) -> usize {
+        let room = self
+            .config
+            .max_prepared_statements
+            .map_or(usize::MAX, |limit| {
+                limit.saturating_sub(self.prepared_statements.len())
+            });
+
+        self.config.statement_cache_size.min(room)
+    }
+
+    /// Make sure another statement can be prepared without exceeding
+    /// [`ConnectionConfig::max_prepared_statements`].
+    ///
+    /// Both cached and prepared statements count towards the limit. Cached
+    /// ones are closed first, least recently used first, then the policy decides
+    /// whether the oldest prepared statement is closed.
+    async fn make_room_for_statement(&mut self) -> Result<(), Error> {
+        let Some(limit) = self.config.max_prepared_statements else {
+            return Ok(());
+        };
+
+        while self.prepared_statements.len() + self.statement_cache.len() >= limit {
+            let oldest = match self.statement_cache.pop_front() {
+                Some(cached) => cached.statement.name,
+                None => match self.config.statement_limit_policy {
+                    StatementLimitPolicy::Evict if limit > 0 => self
+                        .prepared_statements
+                        .pop_front()
+                        .expect("at least one prepared statement"),
+                    _ => return Err(Error::StatementLimitReached(limit)),
+                },
+            };
+            self.close_statement(&oldest).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Prepare a statement under the next free name.
+    async fn prepare_named(
+        &mut self,
+        query: &str,
+        parameter_types: Vec<Oid>,
+    ) -> Result<Statement, Error> {
+        let name = format!("s{}", self.next_statement_id);
+        self.next_statement_id += 1;
+
+        let (parameters, row_description) = self
+            .parse_and_describe(&name, query, parameter_types)
+            .await?;
+
+        Ok(Statement {
+            name,
+            parameter_types: parameters.types,
+            row_description: row_description.map(Arc::new),
+        })
+    }
+
+    /// Ask the server for the parameter types and result columns of a query
+    /// without executing it.
+    ///
+    /// The returned row description has no fields if the query doesn't return rows.
+    pub async fn describe(
+        &mut self,
+        query: &str,
+    ) -> Result<(ParameterDescription, RowDescription), Error> {
+        // The unnamed statement is replaced by the next one, so we don't need to close it
+        let (parameters, row_description) = self.parse_and_describe("", query, Vec::new()).await?;
+
+        let row_description = row_description.unwrap_or(RowDescription { fields: Vec::new() });
+
+        Ok((parameters, row_description))
+    }
+
+    /// Deallocate the prepared statement with the given name on the server
+    /// using `DEALLOCATE`, and forget about it locally.
+    pub async fn deallocate(&mut self, name: &str) -> Result<(), Error> {
+        let query = format!("DEALLOCATE {}", util::quote_identifier(name));
+        self.execute_command(&query).await?;
+
+        self.prepared_statements
+            .retain(|prepared_name| prepared_name != name);
+        self.statement_cache
+            .retain(|cached| cached.statement.name != name);
+
+        Ok(())
+    }
+
+    /// Set the schemas unqualified names are looked up in, in order, using
+    /// `SET search_path`.
+    ///
+    /// Each schema is quoted as an identifier, so names are taken literally
+    /// (e.g. `MyTenant` keeps its case). An empty list leaves no schema to look in.
+    pub async fn set_search_path(&mut self, schemas: &[&str]) -> Result<(), Error> {
+        if let Some(schema) = schemas
+            .iter()
+            .find(|schema| schema.is_empty() || schema.contains('\0'))
+        {
+            return Err(Error::InvalidIdentifier(schema.to_string()));
+        }
+
+        let search_path = if schemas.is_empty() {
+            "''".to_string()
+        } else {
+            schemas
+                .iter()
+                .map(|schema| util::quote_identifier(schema))
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+
+        self.expect_command(&format!("SET search_path = {}", search_path), "SET")
+            .await
+    }
+
+    /// Act with the privileges of `role` for the rest of the session using `SET ROLE`,
+    /// e.g. to scope queries to a tenant.
+    ///
+    /// The role name is quoted as an identifier, so it is taken literally.
+    /// The session user must be a member of the role.
+    pub async fn set_role(&mut self, role: &str) -> Result<(), Error> {
+        if role.is_empty() || role.contains('\0') {
+            return Err(Error::InvalidIdentifier(role.to_string()));
+        }
+
+        let query = format!("SET ROLE {}", util::quote_identifier(role));
+        self.expect_command(&query, "SET").await
+    }
+
+    /// Go back to the privileges of the session user using `RESET ROLE`.
+    pub async fn reset_role(&mut self) -> Result<(), Error> {
+        self.expect_command("RESET ROLE", "RESET").await
+    }
+
+    /// Run a command with [`Connection::execute_command`] and check its command tag.
+    async fn expect_command(&mut self, query: &str, tag: &str) -> Result<(), Error> {
+        let complete = self.execute_command(query).await?;
+        if complete.tag() != tag {
+            return Err(ProtocolError::UnexpectedCommandTag(complete.tag().to_string()).into());
+        }
+
+        Ok(())
+    }
+
+    /// Run a simple query that doesn't return rows and return its completion.
+    async fn execute_command(&mut self, query: &str) -> Result<server::CommandComplete, Error> {
+        let query_message = client::Query::new(query.to_string());
+        self.send_message(&query_message).await?;
+
+        let mut command_complete = None;
+        let mut error = None;
+
+        // Read messages until the server is ready for the next query
+        loop {
+            let response = self.read_message().await?;
+
+            tracing::debug!(response=?&response, "Received message from server");
+
+            match response {
+                server::Message::ReadyForQuery => break,
+                server::Message::CommandComplete(complete) => command_complete = Some(complete),
+                server::Message::Error(response) => {
+                    tracing::error!(error=?response, "Command error");
+                    error = Some(response);
+                }
+                otherwise => self.buffer_message(otherwise)?,
+            }
+        }
+
+        if let Some(error) = error {
+            return Err(Error::QueryError(error));
+        }
+
+        Ok(command_complete.ok_or(ProtocolError::MissingCommandComplete)?)
+    }
+
+    /// Close the prepared statement with the given name on the server.
+    async fn close_statement(&mut self, name: &str) -> Result<(), Error> {
+        let close = client::Close::new(client::Target::Statement, name.to_string());
+        self.write_message(&close);
+        self.send_message(&client::Sync).await?;
+
+        let mut error = None;
+
+        // Read messages until the server is done with the sync
+        loop {
+            let response = self.read_message().await?;
+
+            tracing::debug!(response=?&response, "Received message from server");
+
+            match response {
+                server::Message::ReadyForQuery => break,
+                server::Message::CloseComplete => {}
+                server::Message::Error(response) => {
+                    tracing::error!(error=?response, "Close error");
+                    error = Some(response);
+                }
+                otherwise => self.buffer_message(otherwise)?,
+            }
+        }
+
+        if let Some(error) = error {
+            return Err(Error::QueryError(error));
+        }
+
+        Ok(())
+    }
+
+    /// Parse a query into a prepared statement with the given name and describe it.
+    async fn parse_and_describe(
+        &mut self,
+        name: &str,
+        query: &str,
+        parameter_types: Vec<Oid>,
+    ) -> Result<(ParameterDescription, Option<RowDescription>), Error> {
+        let parse = client::Parse::new(name.to_string(), query.to_string(), parameter_types);
+        self.write_message(&parse);
+        let describe = client::Describe::new(client::Target::Statement, name.to_string());
+        self.write_message(&describe);
+        self.send_message(&client::Sync).await?;
+
+        let mut parameters = ParameterDescription { types: Vec::new() };
+        let mut row_description = None;
+        let mut error = None;
+
+        // Read messages until the server is done with the sync
+        loop {
+            let response = self.read_message().await?;
+
+            tracing::debug!(response=?&response, "Received message from server");
+
+            match response {
+                server::Message::ReadyForQuery => break,
+                server::Message::ParseComplete | server::Message::NoData => {}
+                server::Message::ParameterDescription(description) => {
+                    parameters = description;
+                }
+                server::Message::RowDescription(description) => {
+                    row_description = Some(description);
+                }
+                server::Message::Error(response) => {
+                    tracing::error!(error=?response, "Prepare error");
+                    error = Some(response);
+                }
+                otherwise => self.buffer_message(otherwise)?,
+            }
+        }
+
+        if let Some(error) = error {
+            return Err(Error::QueryError(error));
+        }
+
+        Ok((parameters, row_description))
+    }
+
+    /// Prepare a statement and check that its result columns have the
+    /// given type object IDs, in order.
+    ///
+    /// This catches schema drift (e.g. a column changing its type)
+    /// before the statement is ever executed.
+    pub async fn prepare_typed(
+        &mut self,
+        query: &str,
+        column_types: &[i32],
+    ) -> Result<Statement, Error> {
+        let statement = self.prepare(query).await?;
+        let columns = statement.columns();
+
+        let mismatch = if columns.len() != column_types.len() {
+            Some(SchemaMismatch::ColumnCount {
+                expected: column_types.len(),
+                actual: columns.len(),
+            })
+        } else {
+            columns
+                .iter()
+                .zip(column_types)
+                .find(|(column, expected)| column.data_type_oid != **expected)
+                .map(|(column, expected)| SchemaMismatch::ColumnType {
+                    column: column.name.clone(),
+                    expected: *expected,
+                    actual: column.data_type_oid,
+                })
+        };
+
+        // Don't keep a statement nobody can use
+        if let Some(mismatch) = mismatch {
+            self.deallocate(statement.name()).await?;
+            return Err(mismatch.into());
+        }
+
+        Ok(statement)
+    }
+
+    /// Create a new connection from a bi-directional stream.
+    ///
+    /// This doesn't run the startup handshake, see [`Connection::with_stream`].
+    pub fn new(stream: impl Transport + 'static) -> Self {
+        Self {
+            stream: Box::new(stream),
+            response_buffer: VecDeque::new(),
+            notices: VecDeque::new(),
+            state: ConnectionState::Startup,
+            key_data: None,
+            peer_address: None,
+            config: ConnectionConfig::new(),
+            prepared_statements: VecDeque::new(),
+            statement_cache: VecDeque::new(),
+            next_statement_id: 0,
+            read_buffer: Vec::new(),
+            read_position: 0,
+            write_buffer: Writer::new(),
+            closed: false,
+            sync_pending: false,
+            unsynced: false,
+            batch_abandoned: false,
+            sending: false,
+            transaction_status: TransactionStatus::default(),
+            parameters: HashMap::new(),
+        }
+    }
+
+    /// Where the connection is in the protocol.
+    pub fn state(&self) -> ConnectionState {
+        self.state
+    }
+
+    /// Whether the connection is in a (possibly failed) transaction block,
+    /// as of the end of the last query.
+    pub fn transaction_status(&self) -> TransactionStatus {
+        self.transaction_status
+    }
+
+    /// Reset the connection to a clean session, e.g. before returning it to a pool.
+    ///
+    /// Rolls back an open transaction and runs `DISCARD ALL`, which closes all
+    /// prepared statements and portals, drops temporary tables and resets all
+    /// session settings, including a role set with [`Connection::set_role`].
+    pub async fn reset(&mut self) -> Result<(), Error> {
+        if self.transaction_status != TransactionStatus::Idle {
+            self.execute_command("ROLLBACK").await?;
+        }
+
+        self.execute_command("DISCARD ALL").await?;
+        self.prepared_statements.clear();
+        self.statement_cache.clear();
+
+        if self.transaction_status != TransactionStatus::Idle {
+            return Err(ProtocolError::NotIdle.into());
+        }
+
+        Ok(())
+    }
+
+    /// The current value of a parameter the server reports, e.g. `server_version`,
+    /// `TimeZone` or `client_encoding`.
+    pub fn parameter(&self, name: &str) -> Option<&str> {
+        self.parameters.get(name).map(String::as_str)
+    }
+
+    /// The version of the server as a number that can be compared, like the
+    /// server's `server_version_num`, e.g. `160002` for `16.2` and `90624` for `9.6.24`.
+    pub fn server_version_num(&self) -> Option<u32> {
+        parse_server_version(self.parameter("server_version")?)
+    }
+
+    /// Whether the server closed the connection, e.g. because it shut down or the
+    /// backend was terminated, or a send was cancelled halfway. A closed connection
+    /// can't be used anymore.
+    pub fn is_closed(&self) -> bool {
+        self.closed
+    }
+
+    /// The messages the last operation received but didn't expect, e.g. because
+    /// the server misbehaved, oldest first.
+    ///
+    /// They are kept until the next operation starts. If there are too many,
+    /// the operation fails with [`ProtocolError::TooManyUnexpectedMessages`].
+    pub fn unexpected_messages(&self) -> impl Iterator<Item = &server::Message> {
+        self.response_buffer.iter()
+    }
+
+    /// Take the notices the server sent so far, oldest first.
+    ///
+    /// Notices are kept until they are taken, up to
+    /// [`ConnectionConfig::max_notices`], see
+    /// [`ConnectionConfig::min_notice_severity`] to drop unimportant ones.
+    pub fn take_notices(&mut self) -> Vec<server::Notice> {
+        std::mem::take(&mut self.notices).into()
+    }
+
+    /// Send any client message to the server, for building custom
+    /// extended-protocol flows out of [`client::Parse`], [`client::Bind`],
+    /// [`client::Execute`] and the like.
+    ///
+    /// This is a low-level API: the connection doesn't track what was sent, so
+    /// the responses have to be read with [`Connection::read_server_message`]
+    /// up to the `ReadyForQuery` following a [`Connection::sync`]. Otherwise the
+    /// connection gets out of sync with the server.
+    pub async fn send_client_message(&mut self, message: &impl Encode) -> Result<(), Error> {
+        self.write_message(message);
+        self.send_unchecked().await
+    }
+
+    /// Encode a client message without sending it yet, like
+    /// [`Connection::send_client_message`] does.
+    ///
+    /// Queued messages are sent together with the next message, in a single
+    /// write, e.g. a `Parse`, `Bind` and `Execute` with a [`Connection::sync`].
+    /// They have to be followed by one of the low-level sends, or they are
+    /// sent along with the next query.
+    pub fn queue_client_message(&mut self, message: &impl Encode) {
+        self.write_message(message);
+    }
+
+    /// Send a `Flush`, asking the server to send the responses to everything sent
+    /// so far without ending the batch.
+    ///
+    /// This is a low-level API, see [`Connection::send_client_message`].
+    pub async fn flush(&mut self) -> Result<(), Error> {
+        self.write_message(&client::Flush);
+        self.send_unchecked().await
+    }
+
+    /// Send a `Sync`, ending the batch. The server responds to everything sent so
+    /// far and finishes with `ReadyForQuery`.
+    ///
+    /// This is a low-level API, see [`Connection::send_client_message`].
+    pub async fn sync(&mut self) -> Result<(), Error> {
+        self.write_message(&client::Sync);
+        self.send_unchecked().await
+    }
+
+    /// Read the next message from the server.
+    ///
+    /// Unlike the other methods, this returns messages of unknown types as
+    /// [`server::Message::Unknown`] instead of skipping them, unless
+    /// [`ConnectionConfig::strict_messages`] is enabled.
+    ///
+    /// This is a low-level API, see [`Connection::send_client_message`].
+    pub async fn read_server_message(&mut self) -> Result<server::Message, Error> {
+        self.read_any_message().await
+    }
+
+    /// Handle a message the current operation doesn't expect, e.g. a notice
+    /// in the middle of a query.
+    ///
+    /// Notices are kept for [`Connection::take_notices`] (dropping the oldest
+    /// beyond [`ConnectionConfig::max_notices`]), parameter statuses
+    /// were applied when they were read and the key data is kept for cancelling
+    /// queries. Anything else is buffered, failing once there are too many.
+    pub(crate) fn buffer_message(&mut self, message: server::Message) -> Result<(), Error> {
+        match message {
+            server::Message::Notice(notice) => {
+                self.notices.push_back(notice);
+                while self.notices.len() > self.config.max_notices {
+                    let dropped = self.notices.pop_front();
+                    tracing::warn!(notice=?dropped, "Dropping the oldest notice, too many weren't taken");
+                }
+            }
+            server::Message::ParameterStatus(_) => {}
+            server::Message::KeyData(key_data) => self.key_data = Some(key_data),
+            otherwise => {
+                if self.response_buffer.len() >= MAX_UNEXPECTED_MESSAGES {
+                    return Err(
+                        ProtocolError::TooManyUnexpectedMessages(MAX_UNEXPECTED_MESSAGES).into(),
+                    );
+                }
+
+                tracing::debug!(message=?otherwise, "Buffering unexpected message");
+                self.response_buffer.push_back(otherwise);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Send a message to the server.
+    pub(crate) async fn send_message(&mut self, message: &impl Encode) -> Result<(), Error> {
+        self.write_message(message);
+        self.flush_write_buffer().await
+    }
+
+    /// Encode a message into the write buffer without sending it yet.
+    pub(crate) fn write_message(&mut self, message: &impl Encode) {
+        message.encode(&mut self.write_buffer);
+    }
+
+    /// Send all messages in the write buffer to the server, which has to be
+    /// ready for a query.
+    pub(crate) async fn flush_write_buffer(&mut self) -> Result<(), Error> {
+        self.ensure_ready().await?;
+
+        self.send_write_buffer().await?;
+        self.state = ConnectionState::AwaitingResponse;
+        // Everything sent here ends with a query or `Sync`
+        self.sync_pending = true;
+
+        Ok(())
+    }
+
+    /// Send all messages in the write buffer to the server as part of a
+    /// [`Batch`](crate::pipeline::Batch), which is synced later.
+    pub(crate) async fn send_unsynced(&mut self) -> Result<(), Error> {
+        // The first messages of the batch start a new implicit transaction,
+        // after rolling back the one a dropped batch left open
+        if !self.unsynced || self.batch_abandoned {
+            self.ensure_ready().await?;
+            self.unsynced = true;
+        }
+
+        self.send_write_buffer().await?;
+        self.state = ConnectionState::AwaitingResponse;
+
+        Ok(())
+    }
+
+    /// Check that the server is ready for a query, after finishing everything
+    /// that was abandoned, and drop the messages to send otherwise.
+    async fn ensure_ready(&mut self) -> Result<(), Error> {
+        let ready = match self.settle().await {
+            Ok(()) if self.closed => Err(Error::ConnectionClosed),
+            // E.g. a previous response wasn't read completely
+            Ok(()) if !self.state.is_ready() => Err(ProtocolError::NotReady(self.state).into()),
+            otherwise => otherwise,
+        };
+        if let Err(error) = ready {
+            self.write_buffer.clear();
+            return Err(error);
+        }
+
+        // Nobody looked at the unexpected messages of the last operation
+        self.response_buffer.clear();
+
+        Ok(())
+    }
+
+    /// Send all messages in the write buffer to the server without checking
+    /// whether it is ready, for the low-level API.
+    async fn send_unchecked(&mut self) -> Result<(), Error> {
+        if let Err(error) = self.settle().await {
+            self.write_buffer.clear();
+            return Err(error);
+        }
+
+        self.send_write_buffer().await?;
+        if self.state.is_ready() {
+            self.state = ConnectionState::AwaitingResponse;
+        }
+
+        Ok(())
+    }
+
+    /// End a copy whose reader or writer was dropped and skip the rest of a
+    /// response whose future was dropped, since the server won't listen until
+    /// it's done with them.
+    async fn settle(&mut self) -> Result<(), Error> {
+        // Hold back the messages to send, so they are dropped along with
+        // the future if it is cancelled in the meantime
+        let pending = std::mem::take(&mut self.write_buffer);
+        let result = self.finish_abandoned().await;
+        self.write_buffer = pending;
+
+        result
+    }
+
+    /// End everything that was abandoned, see [`Connection::settle`].
+    async fn finish_abandoned(&mut self) -> Result<(), Error> {
+        loop {
+            self.abort_copy_in().await?;
+            self.skip_copy_out().await?;
+            self.end_copy_both().await?;
+            self.rollback_abandoned_batch().await?;
+
+            // Without a pending `Sync`, e.g. after a low-level `Flush`,
+            // waiting for the server to be ready could take forever
+            if self.state != ConnectionState::AwaitingResponse || !self.sync_pending {
+                return Ok(());
+            }
+
+            tracing::warn!("Skipping the rest of an abandoned response");
+
+            // A copy may start in the middle, which is ended on the next iteration
+            while self.state == ConnectionState::AwaitingResponse {
+                match self.read_message().await? {
+                    // Nobody is interested in the result anymore
+                    server::Message::Error(response) => {
+                        tracing::warn!(error=?response, "Abandoned query failed");
+                    }
+                    message if message.is_asynchronous() => {
+                        self.buffer_message(message)?;
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    /// Roll back the implicit transaction of a [`Batch`](crate::pipeline::Batch)
+    /// that was dropped without syncing it, and sync it so its responses can be
+    /// skipped up to the `ReadyForQuery`.
+    async fn rollback_abandoned_batch(&mut self) -> Result<(), Error> {
+        if !self.unsynced {
+            return Ok(());
+        }
+
+        tracing::warn!("Rolling back a batch that was dropped without syncing it");
+
+        // A `ROLLBACK` aborts the implicit transaction, unless a query in it
+        // failed and the server skips everything up to the `Sync` anyway
+        let parse = client::Parse::new(String::new(), "ROLLBACK".to_string(), Vec::new());
+        self.write_message(&parse);
+        let bind = client::Bind::new(String::new(), String::new(), Vec::new(), Vec::new());
+        self.write_message(&bind);
+        self.write_message(&client::Execute::new(String::new(), 0));
+        self.sync_batch().await?;
+
+        // Skip the responses, including those of a query still in flight
+        while self.state == ConnectionState::AwaitingResponse {
+            match self.read_message().await? {
+                server::Message::Error(response) => {
+                    tracing::warn!(error=?response, "Abandoned batch failed");
+                }
+                // The server warns that there is no transaction block to roll back,
+                // though it does roll back the implicit transaction
+                server::Message::Notice(notice) if notice.code() == Some("25P01") => {}
+                message if message.is_asynchronous() => {
+                    self.buffer_message(message)?;
+                }
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Mark the queries of a [`Batch`](crate::pipeline::Batch) that was dropped
+    /// without syncing it to be rolled back before the next query, because
+    /// a new batch is started.
+    pub(crate) fn abandon_batch(&mut self) {
+        if self.unsynced {
+            self.batch_abandoned = true;
+        }
+    }
+
+    /// Whether a [`Batch`](crate::pipeline::Batch) sent queries that still need a `Sync`.
+    pub(crate) fn unsynced(&self) -> bool {
+        self.unsynced
+    }
+
+    /// End the implicit transaction of a [`Batch`](crate::pipeline::Batch) with a `Sync`.
+    pub(crate) async fn sync_batch(&mut self) -> Result<(), Error> {
+        self.write_message(&client::Sync);
+        self.send_write_buffer().await?;
+        self.unsynced = false;
+        self.batch_abandoned = false;
+        self.sync_pending = true;
+
+        Ok(())
+    }
+
+    /// Send all messages in the write buffer to the server, even during a copy.
+    pub(crate) async fn send_write_buffer(&mut self) -> Result<(), Error> {
+        // The server may have received half a message, so we can't talk to it anymore
+        if self.sending {
+            tracing::error!("Closing the connection after a send was cancelled halfway");
+            self.closed = true;
+        }
+
+        if self.closed {
+            self.write_buffer.clear();
+            return Err(Error::ConnectionClosed);
+        }
+
+        #[cfg(feature = "trace-protocol")]
+        crate::trace::trace_messages(
+            "sent",
+            self.write_buffer.as_bytes(),
+            self.config.protocol_trace,
+        );
+
+        // Write the messages to the stream
+        self.sending = true;
+        let result = self.stream.write_all(self.write_buffer.as_bytes()).await;
+        self.sending = false;
+
+        // Even if that failed, the messages are gone for good
+        self.write_buffer.clear();
+        self.write_buffer
+            .shrink_to(self.config.write_buffer_capacity);
+        result.map_err(Error::NetworkError)?;
+
+        // Flush the stream to ensure the message is sent
+        self.stream.flush().await.map_err(Error::NetworkError)?;
+
+        Ok(())
+    }
+
+    /// Read a message from the stream into the read buffer (resizing it if necessary)
+    /// and decode it.
+    pub(crate) async fn read_message(&mut self) -> Result<server::Message, Error> {
+        futures_lite::future::poll_fn(|cx| self.poll_read_message(cx)).await
+    }
+
+    /// Poll for the next message, like [`Connection::read_message`].
+    pub(crate) fn poll_read_message(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<server::Message, Error>> {
+        loop {
+            ready!(self.poll_read_frame(cx))?;
+
+            match self.decode_frame()? {
+                server::Message::Unknown { type_byte, .. } => {
+                    tracing::warn!(type_byte, "Skipping message of unknown type");
+                }
+                server::Message::Notice(notice) if self.is_filtered(&notice) => {
+                    tracing::trace!(notice=?notice, "Dropping notice below the minimum severity");
+                }
+                message => return Poll::Ready(Ok(message)),
+            }
+        }
+    }
+
+    /// Whether the notice is less severe than the configured minimum.
+    fn is_filtered(&self, notice: &server::Notice) -> bool {
+        match (self.config.min_notice_severity, notice.severity_level()) {
+            (Some(min_severity), Some(severity)) => severity < min_severity,
+            _ => false,
+        }
+    }
+
+    /// Read the next message from the stream, including messages of unknown types.
+    async fn read_any_message(&mut self) -> Result<server::Message, Error> {
+        self.read_frame().await?;
+        self.decode_frame()
+    }
+
+    /// Decode the message in the read buffer.
+    fn decode_frame(&mut self) -> Result<server::Message, Error> {
+        // Decode the message, it copies everything it needs out of the buffer
+        let message = server::Message::try_from(util::Reader::new(&self.read_buffer));
+
+        self.shrink_read_buffer();
+
+        match message.map_err(Error::CodecError)? {
+            server::Message::ParameterStatus(status) => {
+                self.parameters
+                    .insert(status.name().to_string(), status.value().to_string());
+                Ok(server::Message::ParameterStatus(status))
+            }
+            server::Message::Unknown { type_byte, .. } if self.config.strict_messages => {
+                Err(Error::CodecError(
+                    DecodeError::UnexpectedValue(format!(
+                        "unknown message type: `{}`, or byte value `{}`",
+                        type_byte as char, type_byte
+                    ))
+                    .into(),
+                ))
+            }
+            message => Ok(message),
+        }
+    }
+
+    /// Read the next message from the stream into the read buffer without decoding it.
+    async fn read_frame(&mut self) -> Result<(), Error> {
+        futures_lite::future::poll_fn(|cx| self.poll_read_frame(cx)).await
+    }
+
+    /// Poll for the next message to be read into the read buffer.
+    ///
+    /// The message may arrive in parts, what was read so far is kept
+    /// in the read buffer between polls.
+    fn poll_read_frame(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Error>> {
+        // This is how many bytes of header each response has
+        const HEADER_LENGTH: usize = 5;
+
+        if self.closed {
+            return Poll::Ready(Err(Error::ConnectionClosed));
+        }
+
+        // Start a new message by reading its type and length,
+        // reusing the buffer of the previous message
+        if self.read_position == 0 {
+            self.read_buffer.clear();
+            self.read_buffer.resize(HEADER_LENGTH, 0);
+        }
+
+        while self.read_position < self.read_buffer.len() {
+            let result = ready!(Pin::new(&mut self.stream)
+                .poll_read(cx, &mut self.read_buffer[self.read_position..]));
+
+            let read = match result {
+                // The server closing the connection between messages
+                // is the only clean way for it to end
+                Ok(0) if self.read_position == 0 => {
+                    tracing::debug!("The server closed the connection");
+                    self.closed = true;
+                    return Poll::Ready(Err(Error::ConnectionClosed));
+                }
+                Ok(0) => Err(io::Error::from(io::ErrorKind::UnexpectedEof)),
+                otherwise => otherwise,
+            };
+            let read = match read {
+                Ok(read) => read,
+                Err(error) => {
+                    // The rest of a partly read message can't be told apart from the next one
+                    self.read_position = 0;
+                    self.closed = true;
+                    return Poll::Ready(Err(Error::NetworkError(error)));
+                }
+            };
+            self.read_position += read;
+
+            // Once the header is complete, make room for the message content
+            if self.read_position == HEADER_LENGTH && self.read_buffer.len() == HEADER_LENGTH {
+                // The length doesn't include the message type
+                let message_length = i32::from_be_bytes(
+                    self.read_buffer[1..HEADER_LENGTH]
+                        .try_into()
+                        .expect("4 bytes of length"),
+                );
+                if message_length < 4 {
+                    // Without a valid length, the stream is out of sync for good
+                    self.read_position = 0;
+                    self.closed = true;
+                    return Poll::Ready(Err(Error::CodecError(
+                        DecodeError::InvalidMessageLength(message_length).into(),
+                    )));
+                }
+
+                self.read_buffer.resize(message_length as usize + 1, 0);
+            }
+        }
+
+        self.read_position = 0;
+
+        #[cfg(feature = "trace-protocol")]
+        crate::trace::trace_messages("received", &self.read_buffer, self.config.protocol_trace);
+
+        Poll::Ready(self.update_state())
+    }
+
+    /// Keep track of the state by the type of the message in the read buffer.
+    fn update_state(&mut self) -> Result<(), Error> {
+        match self.read_buffer[..] {
+            // The transaction status follows the type and length
+            [b'Z', _, _, _, _, status] => {
+                self.transaction_status = TransactionStatus::try_from(status)
+                    .map_err(|error| Error::CodecError(error.into()))?;
+                self.state = self.transaction_status.into();
+                self.sync_pending = false;
+            }
+            [b'G', ..] => self.state = ConnectionState::CopyIn,
+            [b'H', ..] => self.state = ConnectionState::CopyOut,
+            [b'W', ..] => self.state = ConnectionState::CopyBoth,
+            // An error ends the copy, the server doesn't expect copy data anymore
+            [b'E', ..] if self.state == ConnectionState::CopyBoth => {
+                self.state = ConnectionState::AwaitingResponse;
+            }
+            // The copy out is done, the server sends the command completion next
+            [b'c', ..] if self.state == ConnectionState::CopyOut => {
+                self.state = ConnectionState::AwaitingResponse;
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    /// Don't hold on to the memory of an exceptionally large message.
+    fn shrink_read_buffer(&mut self) {
+        if self.read_buffer.capacity() > self.config.read_buffer_capacity {
+            self.read_buffer.truncate(0);
+            self.read_buffer.shrink_to(self.config.read_buffer_capacity);
+        }
+    }
+}
+
+/// Parse a `server_version` like `16.2`, `9.6.24` or `17beta1 (Debian 17~beta1-1)`
+/// into the form of `server_version_num`.
+fn parse_server_version(version: &str) -> Option<u32> {
+    // Only the leading numbers count, e.g. `17beta1` is `17`
+    let end = version
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(version.len());
+    let mut components = version[..end]
+        .split('.')
+        .map(|component| component.parse::<u32>().ok());
+
+    let major = components.next()??;
+    let minor = components.next().flatten().unwrap_or(0);
+
+    // Since 10, the version has two components, before that it had three
+    if major >= 10 {
+        major.checked_mul(10000)?.checked_add(minor)
+    } else {
+        let patch = components.next().flatten().unwrap_or(0);
+        major
+            .checked_mul(10000)?
+            .checked_add(minor.checked_mul(100)?)?
+            .checked_add(patch)
+    }
+}
+
+/// Parse `data` in the format of `column`, naming the column if that fails.
+fn parse_column<'a, T: FromSql<'a>>(
+    column: &FieldDescription,
+    data: &'a Data,
+) -> Result<T, ColumnError> {
+    let value = match column.format_code {
+        FormatCode::Binary => data.parse_binary(),
+        FormatCode::Text => data.parse_text(),
+    };
+
+    value.map_err(|source| ColumnError {
+        column: column.name.clone(),
+        source,
+    })
+}
+
+impl Statement {
+    /// The name of the statement on the server.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The object IDs of the parameter types.
+    pub fn parameter_types(&self) -> &[i32] {
+        &self.parameter_types
+    }
+
+    /// The result columns of the statement, empty if it doesn't return rows.
+    pub fn columns(&self) -> &[FieldDescription] {
+        self.row_description
+            .as_ref()
+            .map(|description| description.fields.as_slice())
+            .unwrap_or_default()
+    }
+}
+
+impl Row {
+    /// Create a row from its fields and the description of its result set.
+    pub(crate) fn new(metadata: Arc<RowDescription>, fields: Arc<[Data]>) -> Self {
+        Self { metadata, fields }
+    }
+
+    /// The description of the columns of the row, in order.
+    ///
+    /// It is empty for a query without columns, e.g. `SELECT FROM t`.
+    pub fn columns(&self) -> &[FieldDescription] {
+        &self.metadata.fields
+    }
+
+    /// Get the value of a field by its name.
+    pub fn get(&self, name: &str) -> Option<&Data> {
+        let index = self.metadata.field_index(name)?;
+
+        self.fields.get(index)
+    }
+
+    /// Get the raw bytes of a field by its name or position, in the format
+    /// of its column, without copying them.
+    ///
+    /// Returns `None` if the value is `NULL` or there is no such field.
+    pub fn get_raw(&self, column: impl ColumnIndex) -> Option<&[u8]> {
+        let index = column.index_in(self.columns())?;
+
+        self.fields.get(index)?.as_bytes()
+    }
+
+    /// Get the value of a field and parse it to a specific type.
+    pub fn get_and_parse<'a, T: FromSql<'a>>(&'a self, name: &str) -> Result<T, BoxError> {
+        let index = self
+            .metadata
+            .field_index(name)
+            .ok_or_else(|| FieldNotFound(name.to_owned()))?;
+
+        self.parse_field(index).map_err(|error| error.source)
+    }
+
+    /// Parse the field at `index` in the format of its column.
+    fn parse_field<'a, T: FromSql<'a>>(&'a self, index: usize) -> Result<T, ColumnError> {
+        let column = &self.metadata.fields[index];
+        let Some(data) = self.fields.get(index) else {
+            return Err(ColumnError {
+                column: column.name.clone(),
+                source: FieldNotFound(column.name.clone()).into(),
+            });
+        };
+
+        parse_column(column, data)
+    }
+
+    /// Get the value of a field by its position, typed by the type of its column.
+    ///
+    /// Values of types without a [`PgValue`] variant are returned as they are.
+    ///
+    /// Fails with [`FieldNotFound`] if the row is shorter than its columns.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the index is beyond the columns, like indexing a [`Vec`].
+    pub fn get_value(&self, index: usize) -> Result<PgValue, BoxError> {
+        let column = &self.metadata.fields[index];
+        let data = self
+            .fields
+            .get(index)
+            .ok_or_else(|| FieldNotFound(column.name.clone()))?;
+
+        PgValue::decode(column.data_type_oid, column.format_code, data.as_bytes())
+    }
+
+    /// Get the value of a field by its name or position and parse it to
+    /// a specific type, remembering the name of the field if that fails.
+    pub fn try_get<'a, T: FromSql<'a>>(
+        &'a self,
+        column: impl ColumnIndex,
+    ) -> Result<T, ColumnError> {
+        let Some(index) = column.index_in(self.columns()) else {
+            return Err(ColumnError {
+                column: column.to_string(),
+                source: FieldNotFound(column.to_string()).into(),
+            });
+        };
+
+        self.parse_field(index)
+    }
+
+    /// Convert the row into a `T`, usually a tuple whose elements are read
+    /// from the columns in order, e.g. `(i32, String)`.
+    pub fn try_into_tuple<T: FromRow>(&self) -> Result<T, ColumnError> {
+        T::from_row(self)
+    }
+
+    /// Convert the row into a tuple that may borrow from it, e.g. `(&str, i32)`.
+    pub fn try_get_tuple<'a, T: FromRowRef<'a>>(&'a self) -> Result<T, ColumnError> {
+        T::from_row_ref(self)
+    }
+
+    /// Render the field of every column as text, the way the server would send
+    /// it in text format, with `NULL` for null values.
+    ///
+    /// This is meant for tests and debugging, e.g. to compare a whole row
+    /// at once. Binary values of types without a [`PgValue`] variant are
+    /// rendered as hex, like a `bytea`.
+    pub fn to_vec_strings(&self) -> Vec<String> {
+        self.metadata
+            .fields
+            .iter()
+            .enumerate()
+            .map(|(index, column)| {
+                // A field missing from a short row is rendered like a null value
+                let data = self.fields.get(index).and_then(Data::as_bytes);
+
+                match (data, column.format_code) {
+                    (None, _) => "NULL".to_string(),
+                    (Some(text), FormatCode::Text) => String::from_utf8_lossy(text).into_owned(),
+                    (Some(binary), FormatCode::Binary) => match self.get_value(index) {
+                        Ok(PgValue::Bool(value)) => if value { "t" } else { "f" }.to_string(),
+                        Ok(PgValue::Int4(value)) => value.to_string(),
+                        Ok(PgValue::Text(value)) => value,
+                        Ok(PgValue::Bytea(bytes)) => to_hex(&bytes),
+                        _ => to_hex(binary),
+                    },
+                }
+            })
+            .collect()
+    }
+}
+
+/// Format bytes like a `bytea` in text format, e.g. `\\x00ff`.
+fn to_hex(bytes: &[u8]) -> String {
+    let digits = bytes
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect::<String>();
+
+    format!("\\x{}", digits)
+}
+
+/// Get the value of a field by its name.
+///
+/// # Panics
+///
+/// Panics if there is no field with this name, use [`Row::get`] instead
+/// if the field might not exist.
+impl Index<&str> for Row {
+    type Output = Data;
+
+    fn index(&self, name: &str) -> &Data {
+        self.get(name)
+            .unwrap_or_else(|| panic!("no field named `{}` in row", name))
+    }
+}
+
+/// Get the value of a field by its position.
+///
+/// # Panics
+///
+/// Panics if the index is out of bounds, like indexing a [`Vec`].
+impl Index<usize> for Row {
+    type Output = Data;
+
+    fn index(&self, index: usize) -> &Data {
+        &self.fields[index]
+    }
+}
+
+impl Display for FieldNotFound {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "row doesn't contain field `{}`", &self.0)
+    }
+}
+
+impl std::error::Error for FieldNotFound {}
+
+impl FieldNotFound {
+    /// The name of the field that doesn't exist.
+    pub fn name(&self) -> &str {
+        &self.0
+    }
+}
+
+impl ColumnError {
+    /// The name of the column that couldn't be read.
+    pub fn column(&self) -> &str {
+        &self.column
+    }
+}
+
+impl Display for ColumnError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "column `{}`: {}", &self.column, &self.source)
+    }
+}
+
+impl std::error::Error for ColumnError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(self.source.as_ref())
+    }
+}
+
+impl FromRowError {
+    /// The (zero-based) index of the row that couldn't be converted.
+    pub fn row(&self) -> usize {
+        self.row
+    }
+
+    /// The name of the column that couldn't be converted.
+    pub fn column(&self) -> &str {
+        self.error.column()
+    }
+}
+
+impl Display for FromRowError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "row {}, {}", self.row, &self.error)
+    }
+}
+
+impl std::error::Error for FromRowError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
@@ -0,0 +1,781 @@
+//! Support for the `COPY` sub-protocol, which streams data
+//! in and out of the server in bulk.
+
+use std::{
+    future::Future,
+    io,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures_lite::{ready, AsyncRead, AsyncReadExt, AsyncWrite};
+
+use crate::{
+    connection::{Connection, ConnectionState, ProtocolError},
+    protocol::message::{
+        client,
+        server::{self, CopyResponse},
+    },
+    util::{CodecError, DecodeError},
+    Error,
+};
+
+/// A send in progress, started by a `poll_*` method of [`AsyncWrite`].
+///
+/// It owns the connection until it is done and then hands it back.
+/// Once the copy is ended, the result is the number of rows copied.
+type PendingSend<'a> =
+    Pin<Box<dyn Future<Output = (&'a mut Connection, Result<Option<u64>, Error>)> + Send + 'a>>;
+
+/// Reads the data of a `COPY ... TO STDOUT` from the server.
+///
+/// Read the data chunk by chunk with [`CopyOutReader::read`], or as a byte
+/// stream through [`AsyncRead`], e.g. with [`futures_lite::io::copy`].
+///
+/// The connection can't be used for anything else while the reader is alive.
+/// If it is dropped before it is drained (until [`CopyOutReader::read`] returns
+/// `None`), the rest of the data is skipped before the next message is sent.
+pub struct CopyOutReader<'a> {
+    /// The connection the data is read from.
+    connection: &'a mut Connection,
+    /// The format the server announced for the copy.
+    format: CopyResponse,
+    /// The error the server reported, returned once it is ready for the next query.
+    error: Option<server::Error>,
+    /// The chunk of data partially returned by [`AsyncRead::poll_read`].
+    chunk: Vec<u8>,
+    /// How much of the chunk was returned already.
+    chunk_position: usize,
+    /// Whether the server finished the copy and is ready for the next query.
+    done: bool,
+}
+
+/// Sends the data of a `COPY ... FROM STDIN` to the server.
+///
+/// Send the data with [`CopyInWriter::write`] or through [`AsyncWrite`],
+/// e.g. with [`futures_lite::io::copy`]. Every write becomes one or more
+/// `CopyData` messages of at most 64 KiB, so the data doesn't have to be aligned
+/// to rows. Small writes are buffered and sent together.
+///
+/// The copy has to be ended with [`CopyInWriter::finish`] (or by closing it
+/// through [`AsyncWrite`]) or aborted with [`CopyInWriter::fail`]. If the writer
+/// is dropped instead, the copy is aborted (discarding all data) before the next
+/// message is sent.
+pub struct CopyInWriter<'a> {
+    /// The connection the data is sent over, `None` while a send is pending.
+    connection: Option<&'a mut Connection>,
+    /// The send started by a `poll_*` method of [`AsyncWrite`], if it isn't done yet.
+    pending: Option<PendingSend<'a>>,
+    /// The format the server expects for the copy.
+    format: CopyResponse,
+    /// The number of rows copied, once the copy was ended by closing the writer.
+    rows: Option<u64>,
+    /// Whether the copy was finished or aborted.
+    done: bool,
+}
+
+/// Reads the data of a `COPY ... TO STDOUT WITH (FORMAT csv, HEADER)`
+/// row by row.
+pub struct CsvCopyOutReader<'a> {
+    /// The underlying reader of raw copy data.
+    reader: CopyOutReader<'a>,
+    /// The column names from the header line.
+    header: Vec<String>,
+}
+
+/// Reads the data of a `COPY ... TO STDOUT WITH (FORMAT binary)`
+/// row by row.
+pub struct BinaryCopyOutReader<'a> {
+    /// The underlying reader of raw copy data.
+    reader: CopyOutReader<'a>,
+    /// Copy data that was read but not parsed yet, from `position` on.
+    buffer: Vec<u8>,
+    /// How much of the buffer was parsed already.
+    position: usize,
+    /// Whether the trailer was read.
+    done: bool,
+}
+
+/// The most data [`Connection::copy_in_from_reader`] sends in one message.
+const COPY_IN_CHUNK_SIZE: usize = 64 * 1024;
+
+/// The signature every binary copy starts with.
+const BINARY_COPY_SIGNATURE: &[u8; 11] = b"PGCOPY\n\xff\r\n\0";
+
+impl Connection {
+    /// Run a `COPY ... TO STDOUT` query and return a reader for the data.
+    pub async fn copy_out(&mut self, query: &str) -> Result<CopyOutReader<'_>, Error> {
+        let query_message = client::Query::new(query.to_string());
+        self.send_message(&query_message).await?;
+
+        let mut error = None;
+
+        // Read messages until the server starts the copy
+        loop {
+            let response = self.read_message().await?;
+
+            tracing::debug!(response=?&response, "Received message from server");
+
+            match response {
+                server::Message::CopyOutResponse(format) => {
+                    return Ok(CopyOutReader {
+                        connection: self,
+                        format,
+                        error: None,
+                        chunk: Vec::new(),
+                        chunk_position: 0,
+                        done: false,
+                    });
+                }
+                // The query failed, the server will skip to ready for query
+                server::Message::Error(response) => {
+                    tracing::error!(error=?response, "Copy error");
+                    error = Some(response);
+                }
+                // The query is done without ever starting a copy
+                server::Message::ReadyForQuery => break,
+                otherwise => self.buffer_message(otherwise)?,
+            }
+        }
+
+        match error {
+            Some(error) => Err(Error::QueryError(error)),
+            None => Err(ProtocolError::MissingCopyResponse.into()),
+        }
+    }
+
+    /// Run a `COPY ... FROM STDIN` query and return a writer for the data.
+    pub async fn copy_in(&mut self, query: &str) -> Result<CopyInWriter<'_>, Error> {
+        let query_message = client::Query::new(query.to_string());
+        self.send_message(&query_message).await?;
+
+        let mut error = None;
+
+        // Read messages until the server starts the copy
+        loop {
+            let response = self.read_message().await?;
+
+            tracing::debug!(response=?&response, "Received message from server");
+
+            match response {
+                server::Message::CopyInResponse(format) => {
+                    return Ok(CopyInWriter {
+                        connection: Some(self),
+                        pending: None,
+                        format,
+                        rows: None,
+                        done: false,
+                    });
+                }
+                // The query failed, the server will skip to ready for query
+                server::Message::Error(response) => {
+                    tracing::error!(error=?response, "Copy error");
+                    error = Some(response);
+                }
+                // The query is done without ever starting a copy
+                server::Message::ReadyForQuery => break,
+                otherwise => self.buffer_message(otherwise)?,
+            }
+        }
+
+        match error {
+            Some(error) => Err(Error::QueryError(error)),
+            None => Err(ProtocolError::MissingCopyResponse.into()),
+        }
+    }
+
+    /// Run a `COPY ... FROM STDIN` query with all data from `reader`
+    /// and return the number of rows copied.
+    ///
+    /// The data is streamed in chunks, so it never has to fit in memory. If
+    /// reading fails, the copy is aborted and nothing is copied.
+    pub async fn copy_in_from_reader(
+        &mut self,
+        query: &str,
+        mut reader: impl AsyncRead + Unpin,
+    ) -> Result<u64, Error> {
+        let mut writer = self.copy_in(query).await?;
+        let mut chunk = vec![0; COPY_IN_CHUNK_SIZE];
+
+        loop {
+            let length = match reader.read(&mut chunk).await {
+                Ok(0) => break,
+                Ok(length) => length,
+                Err(error) => {
+                    writer
+                        .fail(&format!("reading the copy data failed: {}", error))
+                        .await?;
+                    return Err(Error::CopySourceError(error));
+                }
+            };
+
+            writer.write(&chunk[..length]).await?;
+        }
+
+        writer.finish().await
+    }
+
+    /// Abort a copy in whose writer was dropped before it was finished,
+    /// since the server won't accept anything but copy data until then.
+    pub(crate) async fn abort_copy_in(&mut self) -> Result<(), Error> {
+        if self.state != ConnectionState::CopyIn {
+            return Ok(());
+        }
+
+        tracing::warn!("Aborting an unfinished COPY FROM STDIN");
+
+        // The write buffer already holds the next messages, the abort goes first
+        let pending = std::mem::take(&mut self.write_buffer);
+        let copy_fail = client::CopyFail::new("the copy was abandoned by the client".to_string());
+        self.write_message(&copy_fail);
+        let result = self.send_write_buffer().await;
+        self.write_buffer = pending;
+        result?;
+        self.state = ConnectionState::AwaitingResponse;
+
+        // The server confirms the abort with an error, which nobody is interested in
+        if let Err(error) = self.read_copy_in_result().await {
+            tracing::warn!(error=?error, "Abandoned copy failed");
+        }
+
+        Ok(())
+    }
+
+    /// End a copy in and return the number of rows copied.
+    async fn finish_copy_in(&mut self) -> Result<u64, Error> {
+        self.write_message(&client::CopyDone);
+        self.send_write_buffer().await?;
+        self.state = ConnectionState::AwaitingResponse;
+
+        let complete = self.read_copy_in_result().await?;
+
+        // The tag is `COPY <rows>`
+        let rows = complete
+            .tag()
+            .strip_prefix("COPY ")
+            .and_then(|rows| rows.parse().ok())
+            .ok_or_else(|| {
+                CodecError::from(DecodeError::UnexpectedValue(format!(
+                    "unexpected copy command tag `{}`",
+                    complete.tag()
+                )))
+            })?;
+
+        Ok(rows)
+    }
+
+    /// Read the server's response to the end of a copy in,
+    /// up to the point where it is ready for the next query.
+    async fn read_copy_in_result(&mut self) -> Result<server::CommandComplete, Error> {
+        let mut command_complete = None;
+        let mut error = None;
+
+        loop {
+            let response = self.read_message().await?;
+
+            tracing::debug!(response=?&response, "Received message from server");
+
+            match response {
+                server::Message::ReadyForQuery => break,
+                server::Message::CommandComplete(complete) => command_complete = Some(complete),
+                server::Message::Error(response) => error = Some(response),
+                otherwise => self.buffer_message(otherwise)?,
+            }
+        }
+
+        if let Some(error) = error {
+            return Err(Error::QueryError(error));
+        }
+
+        Ok(command_complete.ok_or(ProtocolError::MissingCommandComplete)?)
+    }
+
+    /// Skip the rest of a copy out whose reader was dropped before it was drained,
+    /// since the server won't listen to anything else until it's done.
+    pub(crate) async fn skip_copy_out(&mut self) -> Result<(), Error> {
+        if self.state != ConnectionState::CopyOut {
+            return Ok(());
+        }
+
+        tracing::warn!("Skipping the rest of an unfinished COPY TO STDOUT");
+
+        // Skip everything until the server is ready for the next query
+        loop {
+            let response = self.read_message().await?;
+
+            match response {
+                server::Message::ReadyForQuery => break,
+                server::Message::CopyData(_)
+                | server::Message::CopyDone
+                | server::Message::CommandComplete(_) => {}
+                // Nobody is interested in the result of the copy anymore
+                server::Message::Error(response) => {
+                    tracing::warn!(error=?response, "Abandoned copy failed");
+                }
+                otherwise => self.buffer_message(otherwise)?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl<'a> CopyOutReader<'a> {
+    /// The format the server announced for the copy.
+    pub fn format(&self) -> &CopyResponse {
+        &self.format
+    }
+
+    /// Read the next chunk of copy data, or `None` once the copy is complete.
+    ///
+    /// For textual formats, the server sends one row per chunk.
+    pub async fn read(&mut self) -> Result<Option<Vec<u8>>, Error> {
+        // The rest of a chunk partially read through `AsyncRead` comes first
+        if self.chunk_position < self.chunk.len() {
+            let rest = self.chunk.split_off(self.chunk_position);
+            self.chunk.clear();
+            self.chunk_position = 0;
+            return Ok(Some(rest));
+        }
+
+        futures_lite::future::poll_fn(|cx| self.poll_data(cx)).await
+    }
+
+    /// Poll for the next chunk of copy data.
+    fn poll_data(&mut self, cx: &mut Context<'_>) -> Poll<Result<Option<Vec<u8>>, Error>> {
+        if self.done {
+            return Poll::Ready(Ok(None));
+        }
+
+        loop {
+            let response = ready!(self.connection.poll_read_message(cx))?;
+
+            match response {
+                server::Message::CopyData(data) => return Poll::Ready(Ok(Some(data))),
+                server::Message::CopyDone | server::Message::CommandComplete(_) => {}
+                server::Message::Error(response) => {
+                    tracing::error!(error=?response, "Copy error");
+                    self.error = Some(response);
+                }
+                server::Message::ReadyForQuery => {
+                    self.done = true;
+
+                    return Poll::Ready(match self.error.take() {
+                        Some(error) => Err(Error::QueryError(error)),
+                        None => Ok(None),
+                    });
+                }
+                otherwise => self.connection.buffer_message(otherwise)?,
+            }
+        }
+    }
+
+    /// Treat the copy data as CSV whose first line is a header
+    /// (as produced by `WITH (FORMAT csv, HEADER)`) and read the header.
+    pub async fn with_csv_header(mut self) -> Result<CsvCopyOutReader<'a>, Error> {
+        let Some(line) = self.read().await? else {
+            return Err(CodecError::from(DecodeError::UnexpectedValue(
+                "missing CSV header line".to_string(),
+            ))
+            .into());
+        };
+
+        let header = parse_csv_record(&line).map_err(CodecError::from)?;
+
+        Ok(CsvCopyOutReader {
+            reader: self,
+            header,
+        })
+    }
+
+    /// Treat the copy data as the binary `COPY` format (as produced by
+    /// `WITH (FORMAT binary)`) and read its header.
+    pub async fn with_binary_header(self) -> Result<BinaryCopyOutReader<'a>, Error> {
+        let mut reader = BinaryCopyOutReader {
+            reader: self,
+            buffer: Vec::new(),
+            position: 0,
+            done: false,
+        };
+
+        if reader.take(BINARY_COPY_SIGNATURE.len()).await? != BINARY_COPY_SIGNATURE {
+            return Err(CodecError::from(DecodeError::UnexpectedValue(
+                "invalid binary copy signature".to_string(),
+            ))
+            .into());
+        }
+
+        // Skip the flags and the header extension, neither is used yet
+        reader.take(4).await?;
+        let extension_length = reader.take_i32().await?;
+        let extension_length = usize::try_from(extension_length).map_err(|_| {
+            CodecError::from(DecodeError::UnexpectedValue(format!(
+                "negative binary copy header extension length `{}`",
+                extension_length
+            )))
+        })?;
+        reader.take(extension_length).await?;
+
+        Ok(reader)
+    }
+}
+
+impl CopyInWriter<'_> {
+    /// The format the server expects for the copy.
+    pub fn format(&self) -> &CopyResponse {
+        &self.format
+    }
+
+    /// Send a chunk of copy data, e.g. one or more rows.
+    ///
+    /// Small chunks are buffered and sent together.
+    pub async fn write(&mut self, data: &[u8]) -> Result<(), Error> {
+        let connection = self.settle().await?;
+
+        for data in data.chunks(COPY_IN_CHUNK_SIZE) {
+            connection.write_message(&client::CopyData::new(data));
+
+            if connection.write_buffer.len() >= connection.config.write_buffer_capacity {
+                connection.send_write_buffer().await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// End the copy and return the number of rows copied.
+    ///
+    /// If the writer was closed through [`AsyncWrite`], this only returns the
+    /// number of rows.
+    pub async fn finish(mut self) -> Result<u64, Error> {
+        self.settle().await?;
+        if self.done {
+            return Ok(self.rows.ok_or(ProtocolError::MissingCommandComplete)?);
+        }
+
+        self.done = true;
+        self.settle().await?.finish_copy_in().await
+    }
+
+    /// Abort the copy, e.g. after a client-side error, so the server discards all
+    /// data and the connection can be used again.
+    ///
+    /// The message ends up in the error the server reports, which is not returned.
+    pub async fn fail(mut self, message: &str) -> Result<(), Error> {
+        self.settle().await?;
+        // The copy was already completed by closing the writer
+        if self.done {
+            return Err(ProtocolError::MissingCopyFailure.into());
+        }
+
+        self.done = true;
+        let connection = self.settle().await?;
+
+        // Nothing buffered has to reach the server anymore
+        connection.write_buffer.clear();
+        connection.write_message(&client::CopyFail::new(message.to_string()));
+        connection.send_write_buffer().await?;
+        connection.state = ConnectionState::AwaitingResponse;
+
+        match connection.read_copy_in_result().await {
+            Err(Error::QueryError(error)) => {
+                tracing::debug!(error=?error, "Copy aborted");
+                Ok(())
+            }
+            Err(error) => Err(error),
+            // The server can't have completed the copy without the data being done
+            Ok(_) => Err(ProtocolError::MissingCopyFailure.into()),
+        }
+    }
+}
+
+impl<'a> CopyInWriter<'a> {
+    /// Wait for a send started through [`AsyncWrite`] and return the connection.
+    async fn settle(&mut self) -> Result<&mut Connection, Error> {
+        futures_lite::future::poll_fn(|cx| self.poll_pending(cx)).await?;
+
+        Ok(self
+            .connection
+            .as_deref_mut()
+            .expect("the connection to be back after the send"))
+    }
+
+    /// Poll the pending send, if there is one.
+    fn poll_pending(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Error>> {
+        let Some(pending) = &mut self.pending else {
+            return Poll::Ready(Ok(()));
+        };
+
+        let (connection, result) = ready!(pending.as_mut().poll(cx));
+        self.pending = None;
+        self.connection = Some(connection);
+
+        Poll::Ready(result.map(|rows| {
+            if rows.is_some() {
+                self.rows = rows;
+            }
+        }))
+    }
+
+    /// Start sending the write buffer, or end the copy if `finish` is set,
+    /// and poll it once.
+    fn start_send(&mut self, cx: &mut Context<'_>, finish: bool) -> Poll<Result<(), Error>> {
+        let connection = self
+            .connection
+            .take()
+            .expect("the connection to be back after the last send");
+        self.pending = Some(Box::pin(async move {
+            let result = if finish {
+                connection.finish_copy_in().await.map(Some)
+            } else {
+                connection.send_write_buffer().await.map(|()| None)
+            };
+            (connection, result)
+        }));
+
+        self.poll_pending(cx)
+    }
+}
+
+impl AsyncWrite for CopyInWriter<'_> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        ready!(this.poll_pending(cx)).map_err(into_io_error)?;
+
+        if this.done {
+            return Poll::Ready(Err(io::Error::new(
+                io::ErrorKind::BrokenPipe,
+                "the copy has ended",
+            )));
+        }
+
+        // Larger writes are split, like the writer's own `write` does
+        let data = &buf[..buf.len().min(COPY_IN_CHUNK_SIZE)];
+
+        // Like a `BufWriter`, send what is buffered before it would overflow.
+        // The data is only accepted once nothing is in flight anymore, so a
+        // dropped writer never leaves a message half sent.
+        let connection = this
+            .connection
+            .as_deref()
+            .expect("the connection to be back after the send");
+        let buffered = connection.write_buffer.len();
+        if buffered > 0 && buffered + 5 + data.len() > connection.config.write_buffer_capacity {
+            ready!(this.start_send(cx, false)).map_err(into_io_error)?;
+        }
+
+        this.connection
+            .as_deref_mut()
+            .expect("the connection to be back after the send")
+            .write_message(&client::CopyData::new(data));
+
+        Poll::Ready(Ok(data.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        ready!(this.poll_pending(cx)).map_err(into_io_error)?;
+
+        let buffered = this
+            .connection
+            .as_ref()
+            .is_some_and(|connection| connection.write_buffer.len() > 0);
+        if buffered && !this.done {
+            ready!(this.start_send(cx, false)).map_err(into_io_error)?;
+        }
+
+        Poll::Ready(Ok(()))
+    }
+
+    /// Send `CopyDone` to end the copy and wait for the server to complete it.
+    ///
+    /// Use [`CopyInWriter::finish`] afterwards to get the number of rows copied.
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        ready!(this.poll_pending(cx)).map_err(into_io_error)?;
+
+        if !this.done {
+            this.done = true;
+            ready!(this.start_send(cx, true)).map_err(into_io_error)?;
+        }
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl Drop for CopyInWriter<'_> {
+    fn drop(&mut self) {
+        // We can't abort the copy here, so the next message sent does it.
+        // Buffered data must not be sent anymore.
+        if !self.done {
+            if let Some(connection) = &mut self.connection {
+                connection.write_buffer.clear();
+            }
+        }
+    }
+}
+
+impl AsyncRead for CopyOutReader<'_> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+
+        while this.chunk_position == this.chunk.len() {
+            match ready!(this.poll_data(cx)).map_err(into_io_error)? {
+                Some(chunk) => {
+                    this.chunk = chunk;
+                    this.chunk_position = 0;
+                }
+                None => return Poll::Ready(Ok(0)),
+            }
+        }
+
+        let chunk = &this.chunk[this.chunk_position..];
+        let length = chunk.len().min(buf.len());
+        buf[..length].copy_from_slice(&chunk[..length]);
+        this.chunk_position += length;
+
+        Poll::Ready(Ok(length))
+    }
+}
+
+/// Convert an error for the [`AsyncRead`] and [`AsyncWrite`] implementations.
+fn into_io_error(error: Error) -> io::Error {
+    match error {
+        Error::NetworkError(error) => error,
+        error => io::Error::other(error),
+    }
+}
+
+impl CsvCopyOutReader<'_> {
+    /// The column names from the header line.
+    pub fn header(&self) -> &[String] {
+        &self.header
+    }
+
+    /// Read and parse the next row, or `None` once the copy is complete.
+    ///
+    /// `NULL` values and empty strings both become empty strings.
+    pub async fn next_row(&mut self) -> Result<Option<Vec<String>>, Error> {
+        let Some(line) = self.reader.read().await? else {
+            return Ok(None);
+        };
+
+        Ok(Some(parse_csv_record(&line).map_err(CodecError::from)?))
+    }
+}
+
+impl BinaryCopyOutReader<'_> {
+    /// Read the next row, or `None` once the copy is complete.
+    ///
+    /// Every field is in the binary format of its column, `None` for `NULL`.
+    /// Use [`FromSql::from_binary`](crate::protocol::message::parsing::FromSql::from_binary)
+    /// to parse them.
+    pub async fn next_row(&mut self) -> Result<Option<Vec<Option<Vec<u8>>>>, Error> {
+        if self.done {
+            return Ok(None);
+        }
+
+        // The trailer is a field count of -1
+        let field_count =
+            i16::from_be_bytes(self.take(2).await?.try_into().expect("to take 2 bytes"));
+        if field_count == -1 {
+            self.done = true;
+            // Nothing follows the trailer, but the copy still has to end
+            while self.reader.read().await?.is_some() {}
+            return Ok(None);
+        }
+        if field_count < -1 {
+            return Err(CodecError::from(DecodeError::UnexpectedValue(format!(
+                "negative number of fields in binary copy row: `{}`",
+                field_count
+            )))
+            .into());
+        }
+
+        let mut fields = Vec::with_capacity(field_count as usize);
+        for _ in 0..field_count {
+            // A length of -1 marks a `NULL` field
+            let field = match self.take_i32().await? {
+                length @ 0.. => Some(self.take(length as usize).await?),
+                _ => None,
+            };
+            fields.push(field);
+        }
+
+        Ok(Some(fields))
+    }
+
+    /// Take the next `n` bytes of copy data, reading more from the server if needed.
+    ///
+    /// The rows aren't aligned to the messages, so they may span several of them.
+    async fn take(&mut self, n: usize) -> Result<Vec<u8>, Error> {
+        while self.buffer.len() - self.position < n {
+            let Some(data) = self.reader.read().await? else {
+                return Err(CodecError::from(DecodeError::UnexpectedEof).into());
+            };
+
+            // Drop what was parsed once per chunk instead of once per field
+            self.buffer.drain(..self.position);
+            self.position = 0;
+            self.buffer.extend_from_slice(&data);
+        }
+
+        let bytes = self.buffer[self.position..self.position + n].to_vec();
+        self.position += n;
+
+        Ok(bytes)
+    }
+
+    /// Take the next 32-bit integer of copy data.
+    async fn take_i32(&mut self) -> Result<i32, Error> {
+        Ok(i32::from_be_bytes(
+            self.take(4).await?.try_into().expect("to take 4 bytes"),
+        ))
+    }
+}
+
+/// Parse a single CSV record (a line, including the line break) into its fields.
+///
+/// Quoted fields may contain commas, line breaks and doubled quotes.
+fn parse_csv_record(line: &[u8]) -> Result<Vec<String>, DecodeError> {
+    let line = String::from_utf8(line.to_vec()).map_err(DecodeError::Utf8Error)?;
+    let line = line.strip_suffix('\n').unwrap_or(&line);
+    let line = line.strip_suffix('\r').unwrap_or(line);
+
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+
+    while let Some(char) = chars.next() {
+        match (quoted, char) {
+            // A doubled quote inside a quoted field is a literal quote
+            (true, '"') if chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            (true, '"') => quoted = false,
+            (false, '"') => quoted = true,
+            (false, ',') => fields.push(std::mem::take(&mut field)),
+            (_, char) => field.push(char),
+        }
+    }
+
+    if quoted {
+        return Err(DecodeError::UnexpectedValue(
+            "unterminated quoted CSV field".to_string(),
+        ));
+    }
+
+    fields.push(field);
+
+    Ok(fields)
+}
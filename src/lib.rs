@@ -5,8 +5,17 @@
 #![forbid(unsafe_code)]
 #![warn(missing_docs)]
 
+pub mod cancel;
+pub mod config;
 pub mod connection;
+pub mod copy;
+#[cfg(feature = "test-util")]
+pub mod mock;
+pub mod pipeline;
 pub mod protocol;
+pub mod replication;
+#[cfg(feature = "trace-protocol")]
+mod trace;
 pub mod util;
 
 use macro_rules_attribute::apply;
@@ -26,13 +35,38 @@ const POSTGRES_DEFAULT_PORT: u16 = 5432;
 #[allow(missing_docs)]
 #[apply(err_enum)]
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum Error {
     #[error("error encoding or decoding a message")]
     CodecError(util::CodecError),
     #[error("error communicating via network")]
     NetworkError(std::io::Error),
+    #[error("the server closed the connection")]
+    ConnectionClosed,
     #[error("unexpected message flow")]
     ProtocolError(connection::ProtocolError),
+    #[error("error authenticating with the server: {0}")]
+    AuthenticationError(connection::AuthenticationError),
+    #[error("the server responded with an error: {0}")]
+    QueryError(protocol::message::server::Error),
+    #[error("unexpected result schema: {0}")]
+    SchemaMismatch(connection::SchemaMismatch),
+    #[error("reached the limit of {0} prepared statements")]
+    StatementLimitReached(usize),
+    #[error("error converting a row: {0}")]
+    FromRowError(connection::FromRowError),
+    #[error("{0}")]
+    FieldNotFound(connection::FieldNotFound),
+    #[error("invalid identifier `{0}`")]
+    InvalidIdentifier(String),
+    #[error("error reading the copy data: {0}")]
+    CopySourceError(std::io::Error),
+    #[error("no server matched target_session_attrs={0}")]
+    NoMatchingServer(config::TargetSessionAttrs),
+    #[error("the query was cancelled")]
+    Cancelled,
+    #[error("the query returned {received} rows, more than the limit of {limit}")]
+    TooManyRows { limit: usize, received: usize },
 }
 
 impl From<std::io::Error> for Error {
@@ -52,3 +86,27 @@ impl From<connection::ProtocolError> for Error {
         Error::ProtocolError(value)
     }
 }
+
+impl From<connection::FromRowError> for Error {
+    fn from(value: connection::FromRowError) -> Self {
+        Error::FromRowError(value)
+    }
+}
+
+impl From<connection::FieldNotFound> for Error {
+    fn from(value: connection::FieldNotFound) -> Self {
+        Error::FieldNotFound(value)
+    }
+}
+
+impl From<connection::AuthenticationError> for Error {
+    fn from(value: connection::AuthenticationError) -> Self {
+        Error::AuthenticationError(value)
+    }
+}
+
+impl From<connection::SchemaMismatch> for Error {
+    fn from(value: connection::SchemaMismatch) -> Self {
+        Error::SchemaMismatch(value)
+    }
+}
@@ -0,0 +1,102 @@
+//! An in-memory transport for testing message flows without a server,
+//! enabled by the `test-util` feature.
+
+use std::{
+    io,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+};
+
+use futures_lite::{AsyncRead, AsyncWrite};
+
+use crate::util::Writer;
+
+/// A stream that replays canned server bytes and records everything the
+/// client sends.
+///
+/// Once all canned bytes are read, reading returns end of file,
+/// like a server that closed the connection.
+#[derive(Debug, Default)]
+pub struct MockStream {
+    /// The bytes the server sends.
+    incoming: Vec<u8>,
+    /// How many of the incoming bytes were read.
+    position: usize,
+    /// The bytes the client sent, shared with the test.
+    sent: Arc<Mutex<Vec<u8>>>,
+}
+
+impl MockStream {
+    /// Create a stream without any server bytes.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Append a server message of the given type, the length is added.
+    pub fn message(mut self, message_type: u8, body: &[u8]) -> Self {
+        let mut writer = Writer::with_capacity(1 + 4 + body.len());
+        writer.write_u8(message_type);
+        writer.write_i32(body.len() as i32 + 4);
+        writer.write_bytes(body);
+
+        self.incoming.extend_from_slice(writer.as_bytes());
+        self
+    }
+
+    /// Append raw server bytes, e.g. a truncated message.
+    pub fn bytes(mut self, bytes: &[u8]) -> Self {
+        self.incoming.extend_from_slice(bytes);
+        self
+    }
+
+    /// Append the response to a successful startup: authentication
+    /// succeeded and the server is ready for queries.
+    pub fn startup(self) -> Self {
+        self.message(b'R', &0i32.to_be_bytes()).message(b'Z', b"I")
+    }
+
+    /// The bytes the client sent so far, it stays valid after the stream
+    /// is moved into a connection.
+    pub fn sent(&self) -> Arc<Mutex<Vec<u8>>> {
+        self.sent.clone()
+    }
+}
+
+impl AsyncRead for MockStream {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let remaining = &self.incoming[self.position..];
+        let n = remaining.len().min(buf.len());
+        buf[..n].copy_from_slice(&remaining[..n]);
+        self.position += n;
+
+        Poll::Ready(Ok(n))
+    }
+}
+
+impl AsyncWrite for MockStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        self.sent
+            .lock()
+            .expect("sent bytes not to be poisoned")
+            .extend_from_slice(buf);
+
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
@@ -0,0 +1,366 @@
+//! Pipelining of extended-protocol queries: several queries are sent at once
+//! and their results are read afterwards, saving a round trip per query.
+//!
+//! A [`Batch`] instead runs queries one at a time within a single implicit
+//! transaction, which stays open until the batch is synced.
+
+use std::{collections::HashSet, sync::Arc};
+
+use crate::{
+    connection::{Connection, ProtocolError, Row},
+    protocol::{
+        message::{
+            client,
+            parsing::ToSql,
+            server::{self, FormatCode},
+        },
+        oids::Oid,
+    },
+    Error,
+};
+
+/// A batch of queries that are sent to the server together.
+///
+/// Created by [`Connection::pipeline`].
+pub struct Pipeline<'a> {
+    /// The connection the queries are sent over.
+    connection: &'a mut Connection,
+    /// The queries, in submission order.
+    queries: Vec<PipelinedQuery>,
+    /// The format all results are requested in.
+    result_format: FormatCode,
+}
+
+/// A query waiting in a [`Pipeline`].
+struct PipelinedQuery {
+    /// The query text.
+    query: String,
+    /// The types of the parameters, empty to let the server infer them.
+    parameter_types: Vec<Oid>,
+    /// The parameters encoded as text.
+    parameters: Vec<Option<Vec<u8>>>,
+}
+
+impl Connection {
+    /// Start a batch of queries that are sent to the server together.
+    pub fn pipeline(&mut self) -> Pipeline<'_> {
+        let result_format = self.config.default_result_format;
+
+        Pipeline {
+            connection: self,
+            queries: Vec::new(),
+            result_format,
+        }
+    }
+}
+
+/// Queries that run one after another in a single implicit transaction,
+/// which is only committed when the batch is synced.
+///
+/// Created by [`Connection::batch`].
+///
+/// Each query is followed by a `Flush` instead of a `Sync`: the server sends
+/// the results right away, but keeps the implicit transaction open. A `Sync`
+/// commits it, see [`Batch::sync`]. If a query fails, the server skips
+/// everything up to the next `Sync`, so the batch is synced right away: the
+/// queries so far are rolled back and the next query starts a new transaction.
+///
+/// If the batch is dropped without syncing it, e.g. when returning early on an
+/// error, its implicit transaction is rolled back before the next query on the
+/// connection, including the next batch.
+pub struct Batch<'a> {
+    /// The connection the queries are sent over.
+    connection: &'a mut Connection,
+    /// Whether the results of a query weren't read completely, because
+    /// its future was dropped.
+    in_flight: bool,
+}
+
+impl Connection {
+    /// Start a batch of queries that run in a single implicit transaction,
+    /// see [`Batch`].
+    pub fn batch(&mut self) -> Batch<'_> {
+        // A previous batch can only still be unsynced if it was dropped
+        self.abandon_batch();
+
+        Batch {
+            connection: self,
+            in_flight: false,
+        }
+    }
+}
+
+impl Batch<'_> {
+    /// Run a query with its parameters (`$1`, `$2`, ...) and return its rows,
+    /// without ending the implicit transaction.
+    pub async fn execute(
+        &mut self,
+        query: &str,
+        parameters: &[&dyn ToSql],
+    ) -> Result<Vec<Row>, Error> {
+        // Skip the rest of a query whose future was dropped
+        if self.in_flight {
+            match self.read_results().await {
+                Ok(_) => {}
+                Err(Error::QueryError(error)) => {
+                    tracing::warn!(error=?error, "Abandoned query failed");
+                }
+                Err(error) => return Err(error),
+            }
+        }
+
+        let parameters = parameters
+            .iter()
+            .map(|parameter| parameter.to_text_or_null())
+            .collect();
+
+        let connection = &mut *self.connection;
+        let parse = client::Parse::new(String::new(), query.to_string(), Vec::new());
+        connection.write_message(&parse);
+        let bind = client::Bind::new(
+            String::new(),
+            String::new(),
+            parameters,
+            vec![connection.config.default_result_format],
+        );
+        connection.write_message(&bind);
+        let describe = client::Describe::new(client::Target::Portal, String::new());
+        connection.write_message(&describe);
+        connection.write_message(&client::Execute::new(String::new(), 0));
+        connection.write_message(&client::Flush);
+        connection.send_unsynced().await?;
+
+        self.in_flight = true;
+        self.read_results().await
+    }
+
+    /// Read the results of the query that was sent last.
+    async fn read_results(&mut self) -> Result<Vec<Row>, Error> {
+        let connection = &mut *self.connection;
+
+        let mut row_description = None;
+        let mut rows = Vec::new();
+
+        // Read messages until the query is done, there is no `ReadyForQuery` yet
+        loop {
+            let response = connection.read_message().await?;
+
+            tracing::debug!(response=?&response, "Received message from server");
+
+            match response {
+                server::Message::ParseComplete
+                | server::Message::BindComplete
+                | server::Message::NoData => {}
+                server::Message::RowDescription(description) => {
+                    row_description = Some(Arc::new(description));
+                }
+                server::Message::DataRow(data_row) => {
+                    let metadata = row_description
+                        .clone()
+                        .ok_or(ProtocolError::MissingRowDescription)?;
+                    rows.push(Row::new(metadata, data_row.fields));
+                }
+                server::Message::CommandComplete(_) | server::Message::EmptyQuery => break,
+                server::Message::Error(response) => {
+                    tracing::error!(error=?response, "Batch error");
+                    self.in_flight = false;
+                    // The server skips everything up to the next `Sync`
+                    self.sync().await?;
+                    return Err(Error::QueryError(response));
+                }
+                otherwise => connection.buffer_message(otherwise)?,
+            }
+        }
+
+        self.in_flight = false;
+        Ok(rows)
+    }
+
+    /// End the implicit transaction with a `Sync`, committing the queries
+    /// unless one of them failed, and wait until the server is ready.
+    ///
+    /// The batch can be used for further queries afterwards, which start a
+    /// new implicit transaction.
+    pub async fn sync(&mut self) -> Result<(), Error> {
+        if !self.connection.unsynced() {
+            return Ok(());
+        }
+
+        self.connection.sync_batch().await?;
+        self.in_flight = false;
+
+        let mut error = None;
+
+        // Read messages until the server is done with the sync, skipping
+        // the rest of a query whose future was dropped
+        loop {
+            match self.connection.read_message().await? {
+                server::Message::ReadyForQuery => break,
+                server::Message::Error(response) => error = Some(response),
+                message if message.is_asynchronous() => {
+                    self.connection.buffer_message(message)?;
+                }
+                _ => {}
+            }
+        }
+
+        match error {
+            Some(error) => Err(Error::QueryError(error)),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Pipeline<'_> {
+    /// Set the format the results of all queries are requested in
+    /// ([`ConnectionConfig::default_result_format`] by default).
+    ///
+    /// [`ConnectionConfig::default_result_format`]: crate::config::ConnectionConfig::default_result_format
+    pub fn result_format(&mut self, format: FormatCode) -> &mut Self {
+        self.result_format = format;
+        self
+    }
+
+    /// Add a query with its parameters (`$1`, `$2`, ...) to the batch.
+    pub fn push(&mut self, query: &str, parameters: &[&dyn ToSql]) -> &mut Self {
+        let parameters = parameters
+            .iter()
+            .map(|parameter| parameter.to_text_or_null())
+            .collect();
+
+        self.queries.push(PipelinedQuery {
+            query: query.to_string(),
+            parameter_types: Vec::new(),
+            parameters,
+        });
+        self
+    }
+
+    /// Add a query with its parameters and their explicit types to the batch.
+    pub(crate) fn push_typed(
+        &mut self,
+        query: &str,
+        parameters: &[(&dyn ToSql, Oid)],
+    ) -> &mut Self {
+        let (parameters, parameter_types) = parameters
+            .iter()
+            .map(|(parameter, oid)| (parameter.to_text_or_null(), *oid))
+            .unzip();
+
+        self.queries.push(PipelinedQuery {
+            query: query.to_string(),
+            parameter_types,
+            parameters,
+        });
+        self
+    }
+
+    /// Send all queries and return their rows, in submission order.
+    ///
+    /// The batch ends with a single `Sync`, so all queries run in one implicit
+    /// transaction: if one of them fails, the server skips the remaining ones,
+    /// earlier ones are rolled back and the error is returned.
+    ///
+    /// With [`ConnectionConfig::statement_cache_size`], the queries reuse cached
+    /// statements, unless the batch has more distinct queries than fit in the cache
+    /// next to the other prepared statements.
+    ///
+    /// [`ConnectionConfig::statement_cache_size`]: crate::config::ConnectionConfig::statement_cache_size
+    pub async fn run(self) -> Result<Vec<Vec<Row>>, Error> {
+        let query_count = self.queries.len();
+
+        let connection = self.connection;
+
+        // Preparing a query could otherwise evict a statement an earlier query in the batch uses
+        let distinct_queries = self
+            .queries
+            .iter()
+            .map(|query| (&query.query, &query.parameter_types))
+            .collect::<HashSet<_>>()
+            .len();
+        let use_cache = distinct_queries <= connection.cacheable_statements();
+
+        let mut statement_names = Vec::with_capacity(query_count);
+        for query in &self.queries {
+            let name = if use_cache {
+                let statement = connection
+                    .prepare_cached_typed(&query.query, &query.parameter_types)
+                    .await?;
+                Some(statement.name().to_string())
+            } else {
+                None
+            };
+            statement_names.push(name);
+        }
+
+        // Encode all queries before sending them, so they are sent at once
+        for (query, statement_name) in self.queries.into_iter().zip(statement_names) {
+            // Without a cached statement, the query is parsed into the unnamed one
+            let statement_name = match statement_name {
+                Some(name) => name,
+                None => {
+                    let parse =
+                        client::Parse::new(String::new(), query.query, query.parameter_types);
+                    connection.write_message(&parse);
+                    String::new()
+                }
+            };
+            let bind = client::Bind::new(
+                String::new(),
+                statement_name,
+                query.parameters,
+                vec![self.result_format],
+            );
+            connection.write_message(&bind);
+            let describe = client::Describe::new(client::Target::Portal, String::new());
+            connection.write_message(&describe);
+            connection.write_message(&client::Execute::new(String::new(), 0));
+        }
+        connection.send_message(&client::Sync).await?;
+
+        let mut results = Vec::with_capacity(query_count);
+        let mut row_description = None;
+        let mut rows = Vec::new();
+        let mut error = None;
+
+        // Read messages until the server is done with the sync
+        loop {
+            let response = connection.read_message().await?;
+
+            tracing::debug!(response=?&response, "Received message from server");
+
+            match response {
+                server::Message::ReadyForQuery => break,
+                server::Message::ParseComplete
+                | server::Message::BindComplete
+                | server::Message::NoData => {}
+                server::Message::RowDescription(description) => {
+                    row_description = Some(Arc::new(description));
+                }
+                server::Message::DataRow(data_row) => {
+                    // We described every portal, so the description always comes first
+                    let metadata = row_description
+                        .clone()
+                        .ok_or(ProtocolError::MissingRowDescription)?;
+                    rows.push(Row::new(metadata, data_row.fields));
+                }
+                // The current query is done, the next one starts
+                server::Message::CommandComplete(_) | server::Message::EmptyQuery => {
+                    results.push(std::mem::take(&mut rows));
+                    row_description = None;
+                }
+                server::Message::Error(response) => {
+                    tracing::error!(error=?response, "Pipeline error");
+                    error = Some(response);
+                }
+                otherwise => connection.buffer_message(otherwise)?,
+            }
+        }
+
+        if let Some(error) = error {
+            return Err(Error::QueryError(error));
+        }
+
+        Ok(results)
+    }
+}
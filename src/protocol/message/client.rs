@@ -4,6 +4,14 @@ use std::collections::HashMap;
 
 use crate::util::Writer;
 
+use super::server::FormatCode;
+
+/// A message that can be encoded for sending to the server.
+pub trait Encode {
+    /// Append the encoded message to the writer.
+    fn encode(&self, writer: &mut Writer);
+}
+
 /// The startup message sent by the client.
 pub struct Startup {
     /// The user name to connect as.
@@ -12,7 +20,6 @@ pub struct Startup {
     options: HashMap<String, String>,
 }
 
-
 /// A simple query message.
 pub struct Query {
     /// The query to send to the server.
@@ -34,11 +41,36 @@ impl Startup {
 
         Self { user, options }
     }
+
+    /// Add another parameter, like `application_name`.
+    pub fn parameter(mut self, name: String, value: String) -> Self {
+        self.options.insert(name, value);
+        self
+    }
 }
 
-impl From<&Startup> for Vec<u8> {
-    fn from(message: &Startup) -> Self {
-        let mut writer = Writer::new();
+impl std::fmt::Debug for Startup {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // Keep a password out of logs
+        let options = self
+            .options
+            .iter()
+            .map(|(key, value)| match key.as_str() {
+                "password" => (key, "<redacted>"),
+                _ => (key, value.as_str()),
+            })
+            .collect::<HashMap<_, _>>();
+
+        f.debug_struct("Startup")
+            .field("user", &self.user)
+            .field("options", &options)
+            .finish()
+    }
+}
+
+impl Encode for Startup {
+    fn encode(&self, writer: &mut Writer) {
+        let start = writer.len();
 
         // Reserve space for the length of the message.
         writer.skip(4);
@@ -48,10 +80,10 @@ impl From<&Startup> for Vec<u8> {
 
         // Write the user name
         writer.write_cstring("user");
-        writer.write_cstring(&message.user);
+        writer.write_cstring(&self.user);
 
         // Write the other options.
-        for (key, value) in &message.options {
+        for (key, value) in &self.options {
             writer.write_cstring(key);
             writer.write_cstring(value);
         }
@@ -61,10 +93,15 @@ impl From<&Startup> for Vec<u8> {
 
         // Overwrite the length of the message.
         writer
-            .write_i32_at(writer.len() as i32, 0)
+            .write_i32_at((writer.len() - start) as i32, start)
             .expect("more than 4 bytes of message content");
+    }
+}
 
-        // Finish the message.
+impl From<&Startup> for Vec<u8> {
+    fn from(message: &Startup) -> Self {
+        let mut writer = Writer::new();
+        message.encode(&mut writer);
         writer.finish()
     }
 }
@@ -76,9 +113,12 @@ impl Query {
     }
 }
 
-impl From<&Query> for Vec<u8> {
-    fn from(message: &Query) -> Self {
-        let mut writer = Writer::new();
+impl Encode for Query {
+    fn encode(&self, writer: &mut Writer) {
+        let start = writer.len();
+
+        // The type, length and null-terminated query.
+        writer.reserve(1 + 4 + self.query.len() + 1);
 
         // This is the message type for a simple query.
         writer.write_u8(b'Q');
@@ -87,14 +127,505 @@ impl From<&Query> for Vec<u8> {
         writer.skip(4);
 
         // Write the query string.
-        writer.write_cstring(&message.query);
+        writer.write_cstring(&self.query);
+
+        // Overwrite the length field (-1 because this excludes the message type).
+        writer
+            .write_i32_at((writer.len() - start) as i32 - 1, start + 1)
+            .expect("more than 4 bytes of message content");
+    }
+}
+
+impl From<&Query> for Vec<u8> {
+    fn from(message: &Query) -> Self {
+        let mut writer = Writer::new();
+        message.encode(&mut writer);
+        writer.finish()
+    }
+}
+
+/// A message asking the server to parse a query into a prepared statement.
+pub struct Parse {
+    /// The name of the prepared statement (empty for the unnamed statement).
+    name: String,
+    /// The query to parse.
+    query: String,
+    /// The object IDs of the parameter types, `0` leaves the type unspecified.
+    parameter_types: Vec<i32>,
+}
+
+/// A message asking the server to describe a prepared statement or portal.
+pub struct Describe {
+    /// Whether to describe a prepared statement or a portal.
+    target: Target,
+    /// The name of the prepared statement or portal.
+    name: String,
+}
+
+/// A message binding parameters to a prepared statement, creating a portal.
+pub struct Bind {
+    /// The name of the portal to create (empty for the unnamed portal).
+    portal: String,
+    /// The name of the prepared statement.
+    statement: String,
+    /// The parameters in text format, `None` being `NULL`.
+    parameters: Vec<Option<Vec<u8>>>,
+    /// The formats the result columns should be sent in.
+    ///
+    /// An empty list means text, a single entry applies to all columns.
+    result_formats: Vec<FormatCode>,
+}
+
+/// A message asking the server to execute a portal.
+pub struct Execute {
+    /// The name of the portal.
+    portal: String,
+    /// The maximum number of rows to return, `0` means no limit.
+    max_rows: i32,
+}
+
+/// A message marking the end of an extended-protocol batch.
+pub struct Sync;
+
+/// A message asking the server to send everything it has buffered so far,
+/// without ending the extended-protocol batch.
+pub struct Flush;
+
+/// The kind of object a [`Describe`] message refers to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Target {
+    /// A prepared statement.
+    Statement,
+    /// A portal.
+    Portal,
+}
+
+impl Parse {
+    /// Create a new parse message.
+    pub fn new(name: String, query: String, parameter_types: Vec<i32>) -> Self {
+        Self {
+            name,
+            query,
+            parameter_types,
+        }
+    }
+}
+
+impl Encode for Parse {
+    fn encode(&self, writer: &mut Writer) {
+        let start = writer.len();
+
+        // The type, length, both null-terminated strings and the parameter types.
+        writer.reserve(
+            1 + 4 + self.name.len() + 1 + self.query.len() + 1 + 2 + 4 * self.parameter_types.len(),
+        );
+
+        // This is the message type for parse.
+        writer.write_u8(b'P');
+
+        // Reserve space for the length field.
+        writer.skip(4);
+
+        // Write the statement name and the query string.
+        writer.write_cstring(&self.name);
+        writer.write_cstring(&self.query);
+
+        // Write the parameter types.
+        writer.write_i16(self.parameter_types.len() as i16);
+        for parameter_type in &self.parameter_types {
+            writer.write_i32(*parameter_type);
+        }
+
+        // Overwrite the length field (-1 because this excludes the message type).
+        writer
+            .write_i32_at((writer.len() - start) as i32 - 1, start + 1)
+            .expect("more than 4 bytes of message content");
+    }
+}
+
+impl From<&Parse> for Vec<u8> {
+    fn from(message: &Parse) -> Self {
+        let mut writer = Writer::new();
+        message.encode(&mut writer);
+        writer.finish()
+    }
+}
+
+impl Bind {
+    /// Create a new bind message.
+    pub fn new(
+        portal: String,
+        statement: String,
+        parameters: Vec<Option<Vec<u8>>>,
+        result_formats: Vec<FormatCode>,
+    ) -> Self {
+        Self {
+            portal,
+            statement,
+            parameters,
+            result_formats,
+        }
+    }
+}
+
+impl Encode for Bind {
+    fn encode(&self, writer: &mut Writer) {
+        let start = writer.len();
+
+        // The type, length, both null-terminated names, the format codes and
+        // every parameter with its length.
+        let parameters_len = self
+            .parameters
+            .iter()
+            .map(|parameter| 4 + parameter.as_ref().map_or(0, Vec::len))
+            .sum::<usize>();
+        writer.reserve(
+            1 + 4
+                + self.portal.len()
+                + 1
+                + self.statement.len()
+                + 1
+                + 2
+                + 2
+                + parameters_len
+                + 2
+                + 2 * self.result_formats.len(),
+        );
+
+        // This is the message type for bind.
+        writer.write_u8(b'B');
+
+        // Reserve space for the length field.
+        writer.skip(4);
+
+        // Write the portal and statement names.
+        writer.write_cstring(&self.portal);
+        writer.write_cstring(&self.statement);
+
+        // All parameters are sent in text format, which is the default.
+        writer.write_i16(0);
+
+        // Write the parameters, each prefixed by its length (-1 for `NULL`).
+        writer.write_i16(self.parameters.len() as i16);
+        for parameter in &self.parameters {
+            match parameter {
+                Some(value) => {
+                    writer.write_i32(value.len() as i32);
+                    writer.write_bytes(value);
+                }
+                None => writer.write_i32(-1),
+            }
+        }
+
+        // Write the result formats.
+        writer.write_i16(self.result_formats.len() as i16);
+        for format in &self.result_formats {
+            writer.write_i16((*format).into());
+        }
 
         // Overwrite the length field (-1 because this excludes the message type).
         writer
-            .write_i32_at(writer.len() as i32 - 1, 1)
+            .write_i32_at((writer.len() - start) as i32 - 1, start + 1)
             .expect("more than 4 bytes of message content");
+    }
+}
+
+impl From<&Bind> for Vec<u8> {
+    fn from(message: &Bind) -> Self {
+        let mut writer = Writer::new();
+        message.encode(&mut writer);
+        writer.finish()
+    }
+}
+
+impl Execute {
+    /// Create a new execute message.
+    pub fn new(portal: String, max_rows: i32) -> Self {
+        Self { portal, max_rows }
+    }
+}
+
+impl Encode for Execute {
+    fn encode(&self, writer: &mut Writer) {
+        let start = writer.len();
+
+        // This is the message type for execute.
+        writer.write_u8(b'E');
+
+        // Reserve space for the length field.
+        writer.skip(4);
+
+        // Write the portal name and the row limit.
+        writer.write_cstring(&self.portal);
+        writer.write_i32(self.max_rows);
+
+        // Overwrite the length field (-1 because this excludes the message type).
+        writer
+            .write_i32_at((writer.len() - start) as i32 - 1, start + 1)
+            .expect("more than 4 bytes of message content");
+    }
+}
+
+impl From<&Execute> for Vec<u8> {
+    fn from(message: &Execute) -> Self {
+        let mut writer = Writer::new();
+        message.encode(&mut writer);
+        writer.finish()
+    }
+}
+
+impl Describe {
+    /// Create a new describe message.
+    pub fn new(target: Target, name: String) -> Self {
+        Self { target, name }
+    }
+}
 
-        // Finish the message.
+impl Encode for Describe {
+    fn encode(&self, writer: &mut Writer) {
+        let start = writer.len();
+
+        // This is the message type for describe.
+        writer.write_u8(b'D');
+
+        // Reserve space for the length field.
+        writer.skip(4);
+
+        // Write what we want described.
+        writer.write_u8(self.target.into());
+        writer.write_cstring(&self.name);
+
+        // Overwrite the length field (-1 because this excludes the message type).
+        writer
+            .write_i32_at((writer.len() - start) as i32 - 1, start + 1)
+            .expect("more than 4 bytes of message content");
+    }
+}
+
+impl From<&Describe> for Vec<u8> {
+    fn from(message: &Describe) -> Self {
+        let mut writer = Writer::new();
+        message.encode(&mut writer);
+        writer.finish()
+    }
+}
+
+impl Encode for Sync {
+    fn encode(&self, writer: &mut Writer) {
+        // This is the message type for sync, followed by the length.
+        writer.write_u8(b'S');
+        writer.write_i32(4);
+    }
+}
+
+impl From<&Sync> for Vec<u8> {
+    fn from(message: &Sync) -> Self {
+        let mut writer = Writer::new();
+        message.encode(&mut writer);
+        writer.finish()
+    }
+}
+
+impl Encode for Flush {
+    fn encode(&self, writer: &mut Writer) {
+        // This is the message type for flush, followed by the length.
+        writer.write_u8(b'H');
+        writer.write_i32(4);
+    }
+}
+
+impl From<&Flush> for Vec<u8> {
+    fn from(message: &Flush) -> Self {
+        let mut writer = Writer::new();
+        message.encode(&mut writer);
+        writer.finish()
+    }
+}
+
+impl From<Target> for u8 {
+    fn from(target: Target) -> Self {
+        match target {
+            Target::Statement => b'S',
+            Target::Portal => b'P',
+        }
+    }
+}
+
+/// A message asking the server to close a prepared statement or portal.
+pub struct Close {
+    /// Whether to close a prepared statement or a portal.
+    target: Target,
+    /// The name of the prepared statement or portal.
+    name: String,
+}
+
+impl Close {
+    /// Create a new close message.
+    pub fn new(target: Target, name: String) -> Self {
+        Self { target, name }
+    }
+}
+
+impl Encode for Close {
+    fn encode(&self, writer: &mut Writer) {
+        let start = writer.len();
+
+        // This is the message type for close.
+        writer.write_u8(b'C');
+
+        // Reserve space for the length field.
+        writer.skip(4);
+
+        // Write what we want closed.
+        writer.write_u8(self.target.into());
+        writer.write_cstring(&self.name);
+
+        // Overwrite the length field (-1 because this excludes the message type).
+        writer
+            .write_i32_at((writer.len() - start) as i32 - 1, start + 1)
+            .expect("more than 4 bytes of message content");
+    }
+}
+
+impl From<&Close> for Vec<u8> {
+    fn from(message: &Close) -> Self {
+        let mut writer = Writer::new();
+        message.encode(&mut writer);
+        writer.finish()
+    }
+}
+
+/// A chunk of data sent during a `COPY ... FROM STDIN`.
+pub struct CopyData<'a> {
+    /// The data, the server doesn't care about chunk boundaries.
+    data: &'a [u8],
+}
+
+/// A message ending a `COPY ... FROM STDIN` successfully.
+pub struct CopyDone;
+
+/// A message aborting a `COPY ... FROM STDIN`.
+pub struct CopyFail {
+    /// Why the copy failed, the server reports it in its error.
+    message: String,
+}
+
+impl<'a> CopyData<'a> {
+    /// Create a new copy data message.
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data }
+    }
+}
+
+impl Encode for CopyData<'_> {
+    fn encode(&self, writer: &mut Writer) {
+        writer.reserve(1 + 4 + self.data.len());
+
+        // This is the message type for copy data, followed by the length.
+        writer.write_u8(b'd');
+        writer.write_i32(self.data.len() as i32 + 4);
+
+        // Write the data.
+        writer.write_bytes(self.data);
+    }
+}
+
+impl From<&CopyData<'_>> for Vec<u8> {
+    fn from(message: &CopyData<'_>) -> Self {
+        let mut writer = Writer::new();
+        message.encode(&mut writer);
+        writer.finish()
+    }
+}
+
+impl Encode for CopyDone {
+    fn encode(&self, writer: &mut Writer) {
+        // This is the message type for copy done, followed by the length.
+        writer.write_u8(b'c');
+        writer.write_i32(4);
+    }
+}
+
+impl From<&CopyDone> for Vec<u8> {
+    fn from(message: &CopyDone) -> Self {
+        let mut writer = Writer::new();
+        message.encode(&mut writer);
+        writer.finish()
+    }
+}
+
+impl CopyFail {
+    /// Create a new copy fail message.
+    pub fn new(message: String) -> Self {
+        Self { message }
+    }
+}
+
+impl Encode for CopyFail {
+    fn encode(&self, writer: &mut Writer) {
+        let start = writer.len();
+
+        // The type, length and null-terminated reason.
+        writer.reserve(1 + 4 + self.message.len() + 1);
+
+        // This is the message type for copy fail.
+        writer.write_u8(b'f');
+
+        // Reserve space for the length field.
+        writer.skip(4);
+
+        // Write the reason.
+        writer.write_cstring(&self.message);
+
+        // Overwrite the length field (-1 because this excludes the message type).
+        writer
+            .write_i32_at((writer.len() - start) as i32 - 1, start + 1)
+            .expect("more than 4 bytes of message content");
+    }
+}
+
+impl From<&CopyFail> for Vec<u8> {
+    fn from(message: &CopyFail) -> Self {
+        let mut writer = Writer::new();
+        message.encode(&mut writer);
+        writer.finish()
+    }
+}
+
+/// A request to cancel the query a server process is running, sent on a
+/// new connection instead of a startup message.
+pub struct CancelRequest {
+    /// The process ID of the server process running the query.
+    process_id: i32,
+    /// The secret key the server process sent on startup.
+    secret_key: i32,
+}
+
+impl CancelRequest {
+    /// Create a new cancel request.
+    pub fn new(process_id: i32, secret_key: i32) -> Self {
+        Self {
+            process_id,
+            secret_key,
+        }
+    }
+}
+
+impl Encode for CancelRequest {
+    fn encode(&self, writer: &mut Writer) {
+        // There is no message type, only the length and the cancel request code.
+        writer.write_i32(16);
+        writer.write_i32(80877102);
+
+        writer.write_i32(self.process_id);
+        writer.write_i32(self.secret_key);
+    }
+}
+
+impl From<&CancelRequest> for Vec<u8> {
+    fn from(message: &CancelRequest) -> Self {
+        let mut writer = Writer::new();
+        message.encode(&mut writer);
         writer.finish()
     }
 }
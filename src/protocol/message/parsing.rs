@@ -1,6 +1,14 @@
-//! This module contains functions for parsing values from the PostgreSQL protocol.
+//! This module contains functions for parsing values from and encoding values to
+//! the PostgreSQL protocol.
 
-use crate::util::BoxError;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use crate::util::{BoxError, Reader};
+
+/// The address family of an IPv4 `inet`/`cidr` value in binary format.
+const PGSQL_AF_INET: u8 = 2;
+/// The address family of an IPv6 `inet`/`cidr` value in binary format.
+const PGSQL_AF_INET6: u8 = 3;
 
 /// A trait for parsing a value from a query result.
 pub trait FromSql<'a>: Sized {
@@ -9,6 +17,29 @@ pub trait FromSql<'a>: Sized {
 
     /// Parse a value from the binary representation.
     fn from_binary(binary: &'a [u8]) -> Result<Self, BoxError>;
+
+    /// Parse a value from `NULL`.
+    ///
+    /// This fails by default, use [`Option`] for values that may be `NULL`.
+    fn from_null() -> Result<Self, BoxError> {
+        Err("unexpected NULL value".into())
+    }
+}
+
+/// A trait for encoding a value as a query parameter.
+///
+/// Parameters are sent in text format, so the server parses them
+/// just like it would parse a literal of the parameter's type.
+pub trait ToSql {
+    /// Encode the value to its text representation.
+    fn to_text(&self) -> Vec<u8>;
+
+    /// Encode the value to its text representation, or `None` for `NULL`.
+    ///
+    /// Only [`Option`] is ever `NULL` by default.
+    fn to_text_or_null(&self) -> Option<Vec<u8>> {
+        Some(self.to_text())
+    }
 }
 
 impl<'a> FromSql<'a> for &'a str {
@@ -37,6 +68,551 @@ impl<'a> FromSql<'a> for i32 {
     }
 
     fn from_binary(binary: &'a [u8]) -> Result<Self, BoxError> {
-        Ok(i32::from_le_bytes(binary.try_into()?))
+        Ok(i32::from_be_bytes(binary.try_into()?))
+    }
+}
+
+/// Parses `float8` (and, without loss, `float4`) values, including `NaN`
+/// and the infinities.
+impl<'a> FromSql<'a> for f64 {
+    fn from_text(text: &'a [u8]) -> Result<Self, BoxError> {
+        parse_float_text(std::str::from_utf8(text)?)
+    }
+
+    fn from_binary(binary: &'a [u8]) -> Result<Self, BoxError> {
+        match binary.len() {
+            4 => Ok(f32::from_be_bytes(binary.try_into()?).into()),
+            _ => Ok(f64::from_be_bytes(binary.try_into()?)),
+        }
+    }
+}
+
+/// Parses `float4` values, including `NaN` and the infinities.
+impl<'a> FromSql<'a> for f32 {
+    fn from_text(text: &'a [u8]) -> Result<Self, BoxError> {
+        parse_float_text(std::str::from_utf8(text)?)
+    }
+
+    fn from_binary(binary: &'a [u8]) -> Result<Self, BoxError> {
+        Ok(f32::from_be_bytes(binary.try_into()?))
+    }
+}
+
+/// PostgreSQL has no unsigned integer types, so unsigned values are only read
+/// from types that are unsigned by nature or from signed values that fit.
+///
+/// Parses `oid` (and `xid`, `cid`, `regclass`, ...) values, or non-negative
+/// integers in text format.
+impl<'a> FromSql<'a> for u32 {
+    fn from_text(text: &'a [u8]) -> Result<Self, BoxError> {
+        Ok(std::str::from_utf8(text)?.parse::<u32>()?)
+    }
+
+    fn from_binary(binary: &'a [u8]) -> Result<Self, BoxError> {
+        Ok(u32::from_be_bytes(binary.try_into()?))
+    }
+}
+
+/// Parses non-negative `bigint` values, failing for negative ones.
+impl<'a> FromSql<'a> for u64 {
+    fn from_text(text: &'a [u8]) -> Result<Self, BoxError> {
+        Ok(std::str::from_utf8(text)?.parse::<u64>()?)
+    }
+
+    fn from_binary(binary: &'a [u8]) -> Result<Self, BoxError> {
+        Ok(u64::try_from(i64::from_be_bytes(binary.try_into()?))?)
+    }
+}
+
+/// Parses `smallint` values between 0 and 255, failing for all others.
+impl<'a> FromSql<'a> for u8 {
+    fn from_text(text: &'a [u8]) -> Result<Self, BoxError> {
+        Ok(std::str::from_utf8(text)?.parse::<u8>()?)
+    }
+
+    fn from_binary(binary: &'a [u8]) -> Result<Self, BoxError> {
+        Ok(u8::try_from(i16::from_be_bytes(binary.try_into()?))?)
+    }
+}
+
+/// Parses `inet` and `cidr` values, discarding the netmask.
+impl<'a> FromSql<'a> for IpAddr {
+    fn from_text(text: &'a [u8]) -> Result<Self, BoxError> {
+        let text = std::str::from_utf8(text)?;
+
+        // The netmask is only present if it doesn't cover the whole address (or for `cidr`)
+        let address = text.split_once('/').map_or(text, |(address, _)| address);
+
+        Ok(address.parse::<IpAddr>()?)
+    }
+
+    fn from_binary(binary: &'a [u8]) -> Result<Self, BoxError> {
+        let mut reader = Reader::new(binary);
+
+        // The family, the netmask bits, the `is_cidr` flag and the address length
+        let mut header = reader.sub_reader(4)?;
+        let family = header.read_u8()?;
+        header.skip(2)?;
+        let length = header.read_u8()? as usize;
+        header.finish()?;
+
+        let mut address = reader.sub_reader(length)?;
+        let value = match family {
+            PGSQL_AF_INET => Ipv4Addr::from(*address.read_bytes_const::<4>()?).into(),
+            PGSQL_AF_INET6 => Ipv6Addr::from(*address.read_bytes_const::<16>()?).into(),
+            otherwise => return Err(format!("unknown inet address family `{}`", otherwise).into()),
+        };
+        address.finish()?;
+        reader.finish()?;
+
+        Ok(value)
+    }
+}
+
+/// Parses fixed-size binary values like `bytea` (in `\x` hex form) or `macaddr`
+/// (in `08:00:2b:01:02:03` form), failing unless they are exactly `N` bytes long.
+impl<'a, const N: usize> FromSql<'a> for [u8; N] {
+    fn from_text(text: &'a [u8]) -> Result<Self, BoxError> {
+        let text = std::str::from_utf8(text)?;
+
+        let invalid = || format!("expected {} bytes of hex, got `{}`", N, text);
+
+        let digits = text.strip_prefix("\\x").unwrap_or(text);
+        let mut digits = digits
+            .chars()
+            .filter(|&char| char != ':')
+            .map(|char| char.to_digit(16).ok_or_else(invalid));
+
+        let mut bytes = [0; N];
+        for byte in &mut bytes {
+            let (Some(high), Some(low)) = (digits.next(), digits.next()) else {
+                return Err(invalid().into());
+            };
+            *byte = (high? << 4 | low?) as u8;
+        }
+
+        if digits.next().is_some() {
+            return Err(invalid().into());
+        }
+
+        Ok(bytes)
+    }
+
+    fn from_binary(binary: &'a [u8]) -> Result<Self, BoxError> {
+        binary
+            .try_into()
+            .map_err(|_| format!("expected {} bytes, got {}", N, binary.len()).into())
+    }
+}
+
+#[cfg(feature = "uuid")]
+impl<'a> FromSql<'a> for uuid::Uuid {
+    fn from_text(text: &'a [u8]) -> Result<Self, BoxError> {
+        Ok(uuid::Uuid::try_parse_ascii(text)?)
+    }
+
+    fn from_binary(binary: &'a [u8]) -> Result<Self, BoxError> {
+        Ok(uuid::Uuid::from_slice(binary)?)
+    }
+}
+
+/// Parses `NULL` as `None`.
+impl<'a, T: FromSql<'a>> FromSql<'a> for Option<T> {
+    fn from_text(text: &'a [u8]) -> Result<Self, BoxError> {
+        T::from_text(text).map(Some)
+    }
+
+    fn from_binary(binary: &'a [u8]) -> Result<Self, BoxError> {
+        T::from_binary(binary).map(Some)
+    }
+
+    fn from_null() -> Result<Self, BoxError> {
+        Ok(None)
+    }
+}
+
+/// Parses one-dimensional arrays, or nested `Vec`s for multi-dimensional
+/// arrays in text format.
+///
+/// Use `Vec<Option<T>>` if the array may contain `NULL` elements.
+impl<'a, T: for<'b> FromSql<'b>> FromSql<'a> for Vec<T> {
+    fn from_text(text: &'a [u8]) -> Result<Self, BoxError> {
+        parse_text_array(std::str::from_utf8(text)?)?
+            .into_iter()
+            .map(|element| match element {
+                Some(element) => T::from_text(element.as_bytes()),
+                None => T::from_null(),
+            })
+            .collect()
+    }
+
+    fn from_binary(binary: &'a [u8]) -> Result<Self, BoxError> {
+        let mut reader = Reader::new(binary);
+
+        // The number of dimensions, the `has_nulls` flag and the element type
+        let mut header = reader.sub_reader(12)?;
+        let dimensions = header.read_i32()?;
+        header.skip(8)?;
+        header.finish()?;
+
+        // An empty array has no dimensions at all
+        let length = match dimensions {
+            0 => 0,
+            1 => {
+                // The length and the lower bound of the dimension
+                let mut dimension = reader.sub_reader(8)?;
+                let length = dimension.read_i32()?;
+                dimension.skip(4)?;
+                dimension.finish()?;
+                usize::try_from(length)
+                    .map_err(|_| format!("negative array length `{}`", length))?
+            }
+            otherwise => {
+                return Err(format!("expected a one-dimensional array, got {}", otherwise).into())
+            }
+        };
+
+        let mut elements = Vec::new();
+        for _ in 0..length {
+            // A length of -1 marks a `NULL` element
+            let element = match reader.read_i32()? {
+                size @ 0.. => T::from_binary(reader.read_bytes(size as usize)?)?,
+                _ => T::from_null()?,
+            };
+            elements.push(element);
+        }
+        reader.finish()?;
+
+        Ok(elements)
+    }
+}
+
+/// Implement [`FromSql`] for a tuple, parsing composite types and `ROW(...)` values.
+macro_rules! impl_from_sql_for_tuple {
+    ($length:literal; $($field:ident),+) => {
+        /// Parses composite types and `ROW(...)` values with exactly as many fields.
+        ///
+        /// Use `Option<T>` for fields that may be `NULL`.
+        impl<'a, $($field: for<'b> FromSql<'b>),+> FromSql<'a> for ($($field,)+) {
+            fn from_text(text: &'a [u8]) -> Result<Self, BoxError> {
+                let fields = parse_text_record(std::str::from_utf8(text)?)?;
+                if fields.len() != $length {
+                    return Err(format!(
+                        "expected a record with {} fields, got {}",
+                        $length,
+                        fields.len()
+                    )
+                    .into());
+                }
+
+                let mut fields = fields.into_iter();
+                Ok(($(
+                    match fields.next().flatten() {
+                        Some(field) => <$field as FromSql>::from_text(field.as_bytes())?,
+                        None => <$field as FromSql>::from_null()?,
+                    },
+                )+))
+            }
+
+            fn from_binary(binary: &'a [u8]) -> Result<Self, BoxError> {
+                let mut reader = Reader::new(binary);
+
+                let length = reader.read_i32()?;
+                if length != $length {
+                    return Err(format!(
+                        "expected a record with {} fields, got {}",
+                        $length, length
+                    )
+                    .into());
+                }
+
+                let value = ($(read_binary_record_field::<$field>(&mut reader)?,)+);
+                reader.finish()?;
+
+                Ok(value)
+            }
+        }
+    };
+}
+
+impl_from_sql_for_tuple!(2; A, B);
+impl_from_sql_for_tuple!(3; A, B, C);
+impl_from_sql_for_tuple!(4; A, B, C, D);
+
+impl<T: ToSql + ?Sized> ToSql for &T {
+    fn to_text(&self) -> Vec<u8> {
+        (**self).to_text()
+    }
+
+    fn to_text_or_null(&self) -> Option<Vec<u8>> {
+        (**self).to_text_or_null()
+    }
+}
+
+/// `None` is sent as `NULL`.
+impl<T: ToSql> ToSql for Option<T> {
+    /// The text of the inner value, empty for `None`, which is sent as `NULL` instead.
+    fn to_text(&self) -> Vec<u8> {
+        self.as_ref().map(ToSql::to_text).unwrap_or_default()
+    }
+
+    fn to_text_or_null(&self) -> Option<Vec<u8>> {
+        self.as_ref().and_then(ToSql::to_text_or_null)
+    }
+}
+
+impl ToSql for str {
+    fn to_text(&self) -> Vec<u8> {
+        self.as_bytes().to_vec()
+    }
+}
+
+impl ToSql for String {
+    fn to_text(&self) -> Vec<u8> {
+        self.as_bytes().to_vec()
+    }
+}
+
+impl ToSql for bool {
+    fn to_text(&self) -> Vec<u8> {
+        if *self {
+            b"t".to_vec()
+        } else {
+            b"f".to_vec()
+        }
+    }
+}
+
+impl ToSql for i32 {
+    fn to_text(&self) -> Vec<u8> {
+        self.to_string().into_bytes()
+    }
+}
+
+impl ToSql for i64 {
+    fn to_text(&self) -> Vec<u8> {
+        self.to_string().into_bytes()
+    }
+}
+
+/// Parse a float in the text format of the server, e.g. `1.5e-05` or `-Infinity`.
+///
+/// The server spells the special values `NaN`, `Infinity` and `-Infinity`
+/// regardless of the locale, everything else is a plain decimal number.
+fn parse_float_text<F: std::str::FromStr + From<f32>>(text: &str) -> Result<F, BoxError>
+where
+    F::Err: std::error::Error + Send + Sync + 'static,
+{
+    match text {
+        "NaN" => Ok(F::from(f32::NAN)),
+        "Infinity" => Ok(F::from(f32::INFINITY)),
+        "-Infinity" => Ok(F::from(f32::NEG_INFINITY)),
+        // Rust would also accept `inf` and `nan`, which the server never sends
+        _ if text
+            .bytes()
+            .any(|byte| byte.is_ascii_alphabetic() && byte != b'e') =>
+        {
+            Err(format!("invalid float `{}`", text).into())
+        }
+        _ => Ok(text.parse::<F>()?),
+    }
+}
+
+/// Split the text representation of an array (e.g. `{a,"b,c",NULL}`) into its
+/// unescaped elements, `None` for `NULL` elements.
+///
+/// Elements that are arrays themselves are returned as they are,
+/// including their braces, quotes and escapes.
+///
+/// The server quotes elements containing commas, braces, quotes, backslashes
+/// or whitespace, empty ones and the text `NULL`, and escapes quotes and
+/// backslashes inside quotes. So `{}` has no elements, while `{""}` has a
+/// single empty one.
+fn parse_text_array(text: &str) -> Result<Vec<Option<String>>, BoxError> {
+    // Arrays with a lower bound other than 1 start with their bounds, e.g. `[0:1]={a,b}`
+    let array = if text.starts_with('[') {
+        text.split_once('=').map_or(text, |(_, array)| array)
+    } else {
+        text
+    };
+
+    let Some(inner) = array
+        .strip_prefix('{')
+        .and_then(|array| array.strip_suffix('}'))
+    else {
+        return Err(format!("invalid array `{}`", text).into());
+    };
+
+    let mut elements = Vec::new();
+    if inner.is_empty() {
+        return Ok(elements);
+    }
+
+    let mut element = String::new();
+    // Whether we are inside quotes and whether the element was quoted at all
+    let mut quoted = false;
+    let mut was_quoted = false;
+    // How deep we are inside nested arrays
+    let mut depth = 0usize;
+    let mut chars = inner.chars();
+
+    while let Some(char) = chars.next() {
+        match (quoted, char) {
+            (_, '\\') => {
+                let Some(escaped) = chars.next() else {
+                    return Err(format!("dangling escape in array `{}`", text).into());
+                };
+                // Nested arrays are unescaped when they are parsed themselves
+                if depth > 0 {
+                    element.push('\\');
+                }
+                element.push(escaped);
+            }
+            (_, '"') if depth > 0 => {
+                quoted = !quoted;
+                element.push('"');
+            }
+            (_, '"') => {
+                // A quoted element is quoted as a whole, e.g. not `a"b"`
+                if !quoted && (was_quoted || !element.is_empty()) {
+                    return Err(format!("misplaced quote in array `{}`", text).into());
+                }
+                quoted = !quoted;
+                was_quoted = true;
+            }
+            (false, '{') => {
+                depth += 1;
+                element.push('{');
+            }
+            (false, '}') => {
+                depth = depth
+                    .checked_sub(1)
+                    .ok_or_else(|| format!("unbalanced braces in array `{}`", text))?;
+                element.push('}');
+            }
+            (false, ',') if depth == 0 => {
+                elements.push(finish_array_element(
+                    std::mem::take(&mut element),
+                    was_quoted,
+                    text,
+                )?);
+                was_quoted = false;
+            }
+            (false, _) if was_quoted && depth == 0 => {
+                return Err(
+                    format!("unexpected text after quoted element in array `{}`", text).into(),
+                );
+            }
+            (_, char) => element.push(char),
+        }
+    }
+
+    if quoted || depth > 0 {
+        return Err(format!("unterminated array `{}`", text).into());
+    }
+
+    elements.push(finish_array_element(element, was_quoted, text)?);
+
+    Ok(elements)
+}
+
+/// Turn the unescaped text of an array element into the element,
+/// an unquoted `NULL` is a `NULL` element.
+///
+/// Empty elements are only valid quoted, so `{a,,b}` is an error.
+fn finish_array_element(
+    element: String,
+    was_quoted: bool,
+    array: &str,
+) -> Result<Option<String>, BoxError> {
+    if was_quoted {
+        return Ok(Some(element));
+    }
+
+    let element = element.trim();
+    if element.is_empty() {
+        return Err(format!("empty unquoted element in array `{}`", array).into());
+    }
+
+    if element.eq_ignore_ascii_case("NULL") {
+        Ok(None)
+    } else {
+        Ok(Some(element.to_string()))
+    }
+}
+
+/// Split the text representation of a record (e.g. `(1,"a,b",)`) into its
+/// unescaped fields, `None` for `NULL` fields.
+fn parse_text_record(text: &str) -> Result<Vec<Option<String>>, BoxError> {
+    let Some(inner) = text
+        .strip_prefix('(')
+        .and_then(|record| record.strip_suffix(')'))
+    else {
+        return Err(format!("invalid record `{}`", text).into());
+    };
+
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    // Whether we are inside quotes and whether the field was quoted at all
+    let mut quoted = false;
+    let mut was_quoted = false;
+    let mut chars = inner.chars().peekable();
+
+    while let Some(char) = chars.next() {
+        match (quoted, char) {
+            (_, '\\') => {
+                let Some(escaped) = chars.next() else {
+                    return Err(format!("dangling escape in record `{}`", text).into());
+                };
+                field.push(escaped);
+            }
+            // Inside quotes, a doubled quote is a literal quote
+            (true, '"') if chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            (_, '"') => {
+                quoted = !quoted;
+                was_quoted = true;
+            }
+            (false, ',') => {
+                fields.push(finish_record_field(std::mem::take(&mut field), was_quoted));
+                was_quoted = false;
+            }
+            (_, char) => field.push(char),
+        }
+    }
+
+    if quoted {
+        return Err(format!("unterminated record `{}`", text).into());
+    }
+
+    fields.push(finish_record_field(field, was_quoted));
+
+    Ok(fields)
+}
+
+/// Turn the unescaped text of a record field into the field,
+/// an empty unquoted field is a `NULL` field.
+fn finish_record_field(field: String, was_quoted: bool) -> Option<String> {
+    if field.is_empty() && !was_quoted {
+        None
+    } else {
+        Some(field)
+    }
+}
+
+/// Read a field of a record in binary format: its type, length and value.
+fn read_binary_record_field<T: for<'b> FromSql<'b>>(reader: &mut Reader) -> Result<T, BoxError> {
+    // The type of the field, which is skipped, and its length
+    let mut header = reader.sub_reader(8)?;
+    header.skip(4)?;
+    let length = header.read_i32()?;
+    header.finish()?;
+
+    // A length of -1 marks a `NULL` field
+    match length {
+        size @ 0.. => T::from_binary(reader.read_bytes(size as usize)?),
+        _ => T::from_null(),
     }
 }
@@ -1,15 +1,22 @@
 //! Server-to-client messages.
 
-use std::{collections::HashMap, ops::Index};
+use std::{collections::HashMap, fmt::Display, ops::Deref, sync::Arc};
 
-use crate::util::{BoxError, CodecError, DecodeError, Reader};
+use crate::{
+    protocol::oids,
+    util::{BoxError, CodecError, DecodeError, Reader},
+};
 
 use super::parsing::FromSql;
 
 /// The type of server-to-client messages.
 ///
 /// It can be converted to and from [`u8`] values.
+///
+/// New message types may be added in minor releases, so matches need a
+/// catch-all arm. The `is_*` predicates avoid matching altogether.
 #[derive(Debug, Clone)]
+#[non_exhaustive]
 pub enum Message {
     /// A message regarding authentication.
     Authentication(Authentication),
@@ -32,10 +39,38 @@ pub enum Message {
     RowDescription(RowDescription),
     /// A row of data from a result set.
     DataRow(DataRow),
+    /// A query was parsed into a prepared statement.
+    ParseComplete,
+    /// Parameters were bound to a prepared statement, creating a portal.
+    BindComplete,
+    /// The types of the parameters of a prepared statement.
+    ParameterDescription(ParameterDescription),
+    /// The described statement or portal doesn't return any rows.
+    NoData,
+    /// A prepared statement or portal was closed.
+    CloseComplete,
+    /// The server is about to send the result of a `COPY ... TO STDOUT`.
+    CopyOutResponse(CopyResponse),
+    /// The server is ready to receive the data of a `COPY ... FROM STDIN`.
+    CopyInResponse(CopyResponse),
+    /// Both sides send copy data from now on, e.g. for streaming replication.
+    CopyBothResponse(CopyResponse),
+    /// A chunk of data sent during a `COPY`.
+    CopyData(Vec<u8>),
+    /// The server is done sending `COPY` data.
+    CopyDone,
+    /// A message of a type this client doesn't know.
+    Unknown {
+        /// The message type.
+        type_byte: u8,
+        /// The message without its type and length.
+        body: Vec<u8>,
+    },
 }
 
 /// The different types of authentication responses.
 #[derive(Debug, Clone)]
+#[non_exhaustive]
 pub enum Authentication {
     /// The authentication was successful.
     Ok,
@@ -46,20 +81,130 @@ pub enum Authentication {
     SaslContinue(Vec<u8>),
     /// SASL authentication is complete.
     SaslFinal(Vec<u8>),
+    /// The server requested GSSAPI authentication.
+    Gss,
+    /// The server requested SSPI authentication.
+    Sspi,
+    /// The server requested an authentication method this client doesn't
+    /// support, identified by its authentication request type.
+    Unsupported(i32),
+}
+
+/// The authentication request types the server may send, the first field
+/// of an authentication message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AuthRequestType {
+    /// The authentication was successful.
+    Ok,
+    /// Kerberos V5 authentication, which servers no longer support.
+    KerberosV5,
+    /// A cleartext password.
+    CleartextPassword,
+    /// An MD5-hashed password.
+    Md5Password,
+    /// GSSAPI authentication.
+    Gss,
+    /// More GSSAPI or SSPI authentication data.
+    GssContinue,
+    /// SSPI authentication.
+    Sspi,
+    /// SASL authentication.
+    Sasl,
+    /// More SASL authentication data.
+    SaslContinue,
+    /// The end of SASL authentication.
+    SaslFinal,
+}
+
+impl TryFrom<i32> for AuthRequestType {
+    type Error = DecodeError;
+
+    fn try_from(value: i32) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(AuthRequestType::Ok),
+            2 => Ok(AuthRequestType::KerberosV5),
+            3 => Ok(AuthRequestType::CleartextPassword),
+            5 => Ok(AuthRequestType::Md5Password),
+            7 => Ok(AuthRequestType::Gss),
+            8 => Ok(AuthRequestType::GssContinue),
+            9 => Ok(AuthRequestType::Sspi),
+            10 => Ok(AuthRequestType::Sasl),
+            11 => Ok(AuthRequestType::SaslContinue),
+            12 => Ok(AuthRequestType::SaslFinal),
+            otherwise => Err(DecodeError::UnexpectedValue(format!(
+                "unknown authentication request type `{}`",
+                otherwise
+            ))),
+        }
+    }
 }
 
 /// A response indicating that an error occurred.
-#[derive(Debug, Clone)]
+///
+/// It's displayed as `severity: code: message`. Its fields can be read
+/// through [`ResponseFields`].
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Error {
-    fields: HashMap<u8, String>,
+    fields: ResponseFields,
 }
 
 /// A message indicating a notice in relation to a request.
+///
+/// Its fields can be read through [`ResponseFields`].
 #[derive(Debug, Clone)]
 pub struct Notice {
+    fields: ResponseFields,
+}
+
+/// The fields of an [`Error`] or [`Notice`], keyed by their identifying byte.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResponseFields {
     fields: HashMap<u8, String>,
 }
 
+/// The transaction status the server reports when it is ready for a query.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum TransactionStatus {
+    /// Not in a transaction block.
+    #[default]
+    Idle,
+    /// In a transaction block.
+    InTransaction,
+    /// In a failed transaction block, queries are rejected until it ends.
+    Failed,
+}
+
+impl TryFrom<u8> for TransactionStatus {
+    type Error = DecodeError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            b'I' => Ok(TransactionStatus::Idle),
+            b'T' => Ok(TransactionStatus::InTransaction),
+            b'E' => Ok(TransactionStatus::Failed),
+            otherwise => Err(DecodeError::UnexpectedValue(format!(
+                "unknown transaction status `{}`",
+                otherwise as char
+            ))),
+        }
+    }
+}
+
+/// The severity of a [`Notice`], ordered from least to most severe.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity {
+    /// `DEBUG1` to `DEBUG5`.
+    Debug,
+    /// `LOG`.
+    Log,
+    /// `INFO`.
+    Info,
+    /// `NOTICE`.
+    Notice,
+    /// `WARNING`.
+    Warning,
+}
+
 /// A message indicating that a parameter status has changed.
 #[derive(Debug, Clone)]
 pub struct ParameterStatus {
@@ -85,6 +230,15 @@ pub struct CommandComplete {
     tag: String,
 }
 
+/// The format of the data exchanged during a `COPY`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CopyResponse {
+    /// The format of the whole copy, binary means the binary `COPY` format.
+    pub format: FormatCode,
+    /// The format of each column, always [`FormatCode::Text`] for textual copies.
+    pub column_formats: Vec<FormatCode>,
+}
+
 /// The result of a (select-like) query.
 #[derive(Debug, Clone)]
 pub struct QueryResult {
@@ -100,16 +254,25 @@ pub struct RowDescription {
     pub fields: Vec<FieldDescription>,
 }
 
+/// The types of the parameters of a prepared statement.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ParameterDescription {
+    /// The object IDs of the parameter types.
+    pub types: Vec<i32>,
+}
+
 /// A row containing a series of data cells representing a row in a [`QueryResult`].
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct DataRow {
-    /// The different data fields in this row.
-    pub(crate) fields: Vec<Data>,
+    /// The different data fields in this row, shared with the [`Row`]s built from it.
+    ///
+    /// [`Row`]: crate::connection::Row
+    pub(crate) fields: Arc<[Data]>,
 }
 
-/// A field in a data row.
+/// A field in a data row, `None` if the value is `NULL`.
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
-pub struct Data(Vec<u8>);
+pub struct Data(Option<Vec<u8>>);
 
 /// Information about a field in a result set.
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
@@ -123,7 +286,11 @@ pub struct FieldDescription {
     pub attribute_number: Option<i16>,
     /// The object ID of the data type of the field.
     pub data_type_oid: i32,
-    /// The length of the field in bytes.
+    /// The length of the field in bytes (`pg_type.typlen`).
+    ///
+    /// Negative values mark variable-length types: `-1` for most of them
+    /// (like `text` or arrays) and `-2` for null-terminated C strings.
+    /// See [`FieldDescription::fixed_size`].
     pub data_type_size: i16,
     /// The type modifier of the field.
     pub data_type_modifier: i32,
@@ -135,6 +302,7 @@ pub struct FieldDescription {
 
 /// The format code of a field.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
 pub enum FormatCode {
     /// Text format.
     Text,
@@ -142,6 +310,15 @@ pub enum FormatCode {
     Binary,
 }
 
+impl From<FormatCode> for i16 {
+    fn from(format_code: FormatCode) -> Self {
+        match format_code {
+            FormatCode::Text => 0,
+            FormatCode::Binary => 1,
+        }
+    }
+}
+
 impl<'a> TryFrom<Reader<'a>> for Message {
     type Error = CodecError;
 
@@ -158,16 +335,61 @@ impl<'a> TryFrom<Reader<'a>> for Message {
             b'N' => Message::Notice(Notice::try_from(reader)?),
             b'T' => Message::RowDescription(RowDescription::try_from(reader)?),
             b'D' => Message::DataRow(DataRow::try_from(reader)?),
-            otherwise => Err(DecodeError::UnexpectedValue(format!(
-                "unknown message type: `{}`, or byte value `{}`",
-                otherwise as char, otherwise
-            )))?,
+            b'1' => Message::ParseComplete,
+            b'2' => Message::BindComplete,
+            b't' => Message::ParameterDescription(ParameterDescription::try_from(reader)?),
+            b'n' => Message::NoData,
+            b'3' => Message::CloseComplete,
+            b'H' => Message::CopyOutResponse(CopyResponse::try_from(reader)?),
+            b'G' => Message::CopyInResponse(CopyResponse::try_from(reader)?),
+            b'W' => Message::CopyBothResponse(CopyResponse::try_from(reader)?),
+            b'd' => {
+                // Skip the length field, the rest is the data.
+                reader.skip(4)?;
+                Message::CopyData(reader.read_remaining_bytes()?.to_owned())
+            }
+            b'c' => Message::CopyDone,
+            otherwise => {
+                // Skip the length field, keep the rest for the caller.
+                reader.skip(4)?;
+                Message::Unknown {
+                    type_byte: otherwise,
+                    body: reader.read_remaining_bytes()?.to_owned(),
+                }
+            }
         };
 
         Ok(msg_type)
     }
 }
 
+/// Decode a complete message including its type and length, checking that
+/// the length matches the buffer.
+impl TryFrom<&[u8]> for Message {
+    type Error = CodecError;
+
+    fn try_from(buffer: &[u8]) -> Result<Self, <Self as TryFrom<&[u8]>>::Error> {
+        let mut reader = Reader::new(buffer);
+        reader.skip(1)?;
+        let length = reader.read_i32()?;
+
+        // The length includes itself, but not the message type
+        if length < 4 {
+            return Err(DecodeError::InvalidMessageLength(length).into());
+        }
+        let expected = length as usize;
+        if expected != buffer.len() - 1 {
+            return Err(DecodeError::LengthMismatch {
+                expected,
+                actual: buffer.len() - 1,
+            }
+            .into());
+        }
+
+        Message::try_from(Reader::new(buffer))
+    }
+}
+
 impl<'a> TryFrom<Reader<'a>> for Authentication {
     type Error = CodecError;
 
@@ -179,14 +401,20 @@ impl<'a> TryFrom<Reader<'a>> for Authentication {
         let message_type = reader.read_i32()?;
 
         // Now we match on that type to figure out what to do next.
-        match message_type {
-            // Authentication was successful.
-            0 => {
+        match AuthRequestType::try_from(message_type) {
+            Ok(AuthRequestType::Ok) => {
                 reader.finish()?;
                 Ok(Authentication::Ok)
             }
-            // SASL authentication.
-            10 => {
+            Ok(AuthRequestType::Gss) => {
+                reader.finish()?;
+                Ok(Authentication::Gss)
+            }
+            Ok(AuthRequestType::Sspi) => {
+                reader.finish()?;
+                Ok(Authentication::Sspi)
+            }
+            Ok(AuthRequestType::Sasl) => {
                 // Read the list of mechanisms (C strings).
                 let mut mechanisms = Vec::new();
 
@@ -197,22 +425,22 @@ impl<'a> TryFrom<Reader<'a>> for Authentication {
 
                 Ok(Authentication::Sasl(mechanisms))
             }
-            // SASL authentication is continuing.
-            11 => {
+            Ok(AuthRequestType::SaslContinue) => {
                 let data = reader.read_remaining_bytes()?;
                 Ok(Authentication::SaslContinue(data.to_owned()))
             }
-            // SASL authentication is complete.
-            12 => {
+            Ok(AuthRequestType::SaslFinal) => {
                 let data = reader.read_remaining_bytes()?;
                 Ok(Authentication::SaslFinal(data.to_owned()))
             }
-            // Unknown authentication response type.
-            otherwise => Err(DecodeError::UnexpectedValue(format!(
-                "unknown authentication response type: `{}`",
-                otherwise
-            ))
-            .into()),
+            // Other authentication methods, we don't know how to read their data
+            Ok(
+                AuthRequestType::KerberosV5
+                | AuthRequestType::CleartextPassword
+                | AuthRequestType::Md5Password
+                | AuthRequestType::GssContinue,
+            )
+            | Err(_) => Ok(Authentication::Unsupported(message_type)),
         }
     }
 }
@@ -224,17 +452,9 @@ impl<'a> TryFrom<Reader<'a>> for Error {
         // Ignore the length field.
         reader.skip(4)?;
 
-        let mut fields = HashMap::new();
-
-        // Read the fields and values
-        while reader.peek_u8()? != 0 {
-            let field = reader.read_u8()?;
-            let value = reader.read_cstring()?.to_owned();
-
-            fields.insert(field, value);
-        }
-
-        Ok(Error { fields })
+        Ok(Error {
+            fields: ResponseFields::try_from(reader)?,
+        })
     }
 }
 
@@ -245,16 +465,29 @@ impl<'a> TryFrom<Reader<'a>> for Notice {
         // Ignore the length field.
         reader.skip(4)?;
 
+        Ok(Notice {
+            fields: ResponseFields::try_from(reader)?,
+        })
+    }
+}
+
+impl<'a> TryFrom<Reader<'a>> for ResponseFields {
+    type Error = CodecError;
+
+    /// Read the fields after the length of an error or notice.
+    fn try_from(mut reader: Reader<'a>) -> Result<Self, Self::Error> {
         let mut fields = HashMap::new();
 
+        // Read the fields and values
         while reader.peek_u8()? != 0 {
             let field = reader.read_u8()?;
-            let value = reader.read_cstring()?.to_owned();
+            // A field in an unexpected encoding shouldn't cost the whole message
+            let value = String::from_utf8_lossy(reader.read_cstring_bytes()?).into_owned();
 
             fields.insert(field, value);
         }
 
-        Ok(Notice { fields })
+        Ok(ResponseFields { fields })
     }
 }
 
@@ -305,6 +538,58 @@ impl<'a> TryFrom<Reader<'a>> for CommandComplete {
     }
 }
 
+impl<'a> TryFrom<Reader<'a>> for CopyResponse {
+    type Error = CodecError;
+
+    fn try_from(mut reader: Reader<'a>) -> Result<Self, Self::Error> {
+        // Ignore the length field.
+        reader.skip(4)?;
+
+        // The overall format is a single byte, the column formats are 16-bit.
+        let format = match reader.read_u8()? {
+            0 => FormatCode::Text,
+            1 => FormatCode::Binary,
+            otherwise => {
+                return Err(DecodeError::UnexpectedValue(format!(
+                    "unknown copy format: `{}`",
+                    otherwise
+                ))
+                .into());
+            }
+        };
+
+        let column_count = reader.read_i16()?;
+
+        if column_count < 0 {
+            return Err(DecodeError::UnexpectedValue(
+                "negative number of columns in copy response".to_string(),
+            )
+            .into());
+        }
+
+        let mut column_formats = Vec::with_capacity(column_count as usize);
+
+        for _ in 0..column_count {
+            column_formats.push(match reader.read_i16()? {
+                0 => FormatCode::Text,
+                1 => FormatCode::Binary,
+                otherwise => {
+                    return Err(DecodeError::UnexpectedValue(format!(
+                        "unknown format code: `{}`",
+                        otherwise
+                    ))
+                    .into());
+                }
+            });
+        }
+
+        Ok(CopyResponse {
+            format,
+            column_formats,
+        })
+    }
+}
+
 impl<'a> TryFrom<Reader<'a>> for RowDescription {
     type Error = CodecError;
 
@@ -332,6 +617,33 @@ impl<'a> TryFrom<Reader<'a>> for RowDescription {
     }
 }
 
+impl<'a> TryFrom<Reader<'a>> for ParameterDescription {
+    type Error = CodecError;
+
+    fn try_from(mut reader: Reader<'a>) -> Result<Self, Self::Error> {
+        // Ignore the length field.
+        reader.skip(4)?;
+
+        // Read the number of parameters.
+        let parameter_count = reader.read_i16()?;
+
+        if parameter_count < 0 {
+            return Err(DecodeError::UnexpectedValue(
+                "negative number of parameters in parameter description".to_string(),
+            )
+            .into());
+        }
+
+        let mut types = Vec::with_capacity(parameter_count as usize);
+
+        for _ in 0..parameter_count {
+            types.push(reader.read_i32()?);
+        }
+
+        Ok(ParameterDescription { types })
+    }
+}
+
 impl<'a> TryFrom<Reader<'a>> for DataRow {
     type Error = CodecError;
 
@@ -351,27 +663,38 @@ impl<'a> TryFrom<Reader<'a>> for DataRow {
             }
         };
 
-        let mut fields = Vec::with_capacity(n);
-
+        // Check the fields first, so they can be collected straight into
+        // the shared slice without an intermediate `Vec`
+        let mut check = reader.clone();
         for _ in 0..n {
-            let field_size = reader.read_i32()?;
-            let field_size = match field_size {
-                1.. => field_size as usize,
-                _ => {
-                    fields.push(Data(Vec::new()));
-                    continue;
-                }
-            };
-
-            let bytes = reader.read_bytes(field_size)?.to_owned();
-
-            fields.push(Data(bytes));
+            read_field(&mut check)?;
         }
 
+        let fields = (0..n)
+            .map(|_| {
+                Data(
+                    read_field(&mut reader)
+                        .expect("field checked above")
+                        .map(<[u8]>::to_vec),
+                )
+            })
+            .collect();
+
         Ok(DataRow { fields })
     }
 }
 
+/// Read a field of a data row, `None` if it is `NULL`.
+fn read_field<'a>(reader: &mut Reader<'a>) -> Result<Option<&'a [u8]>, DecodeError> {
+    let field_size = reader.read_i32()?;
+
+    // A length of -1 marks a `NULL` value
+    match usize::try_from(field_size) {
+        Ok(field_size) => Ok(Some(reader.read_bytes(field_size)?)),
+        Err(_) => Ok(None),
+    }
+}
+
 impl<'a> TryFrom<&mut Reader<'a>> for FieldDescription {
     type Error = CodecError;
 
@@ -422,6 +745,248 @@ impl<'a> TryFrom<&mut Reader<'a>> for FieldDescription {
     }
 }
 
+impl Message {
+    /// Whether this is an error response.
+    pub fn is_error(&self) -> bool {
+        matches!(self, Message::Error(_))
+    }
+
+    /// Whether the server is ready for the next query.
+    pub fn is_ready_for_query(&self) -> bool {
+        matches!(self, Message::ReadyForQuery)
+    }
+
+    /// Whether the server may send this message at any time, independent of
+    /// any query (notices and parameter changes).
+    pub fn is_asynchronous(&self) -> bool {
+        matches!(self, Message::Notice(_) | Message::ParameterStatus(_))
+    }
+}
+
+impl ResponseFields {
+    /// All fields, keyed by their identifying byte.
+    ///
+    /// See the [documentation](https://www.postgresql.org/docs/current/protocol-error-fields.html)
+    /// for the meaning of the identifiers.
+    pub fn fields(&self) -> &HashMap<u8, String> {
+        &self.fields
+    }
+
+    /// The (possibly localized) severity, e.g. `ERROR`, `FATAL` or `WARNING`.
+    pub fn severity(&self) -> Option<&str> {
+        self.field(b'S')
+    }
+
+    /// The SQLSTATE code, e.g. `23505` for a unique violation or `01000` for a warning.
+    pub fn code(&self) -> Option<&str> {
+        self.field(b'C')
+    }
+
+    /// The primary, human-readable message.
+    pub fn message(&self) -> Option<&str> {
+        self.field(b'M')
+    }
+
+    /// The severity that is never localized, e.g. `ERROR`, `FATAL` or `WARNING`.
+    pub fn severity_nonlocalized(&self) -> Option<&str> {
+        self.field(b'V')
+    }
+
+    /// A secondary message with more detail about the problem.
+    pub fn detail(&self) -> Option<&str> {
+        self.field(b'D')
+    }
+
+    /// A suggestion what to do about the problem.
+    pub fn hint(&self) -> Option<&str> {
+        self.field(b'H')
+    }
+
+    /// The position in the query the message refers to, in characters starting at 1.
+    pub fn position(&self) -> Option<u32> {
+        self.field(b'P')?.parse().ok()
+    }
+
+    /// Like [`ResponseFields::position`], but for an internally generated query.
+    pub fn internal_position(&self) -> Option<u32> {
+        self.field(b'p')?.parse().ok()
+    }
+
+    /// The internally generated query the message is about, e.g. from a PL/pgSQL function.
+    pub fn internal_query(&self) -> Option<&str> {
+        self.field(b'q')
+    }
+
+    /// The context the message was raised in, e.g. a call stack of PL/pgSQL functions.
+    pub fn where_(&self) -> Option<&str> {
+        self.field(b'W')
+    }
+
+    /// The schema of the object the message is associated with.
+    pub fn schema_name(&self) -> Option<&str> {
+        self.field(b's')
+    }
+
+    /// The table the message is associated with.
+    pub fn table_name(&self) -> Option<&str> {
+        self.field(b't')
+    }
+
+    /// The column the message is associated with.
+    pub fn column_name(&self) -> Option<&str> {
+        self.field(b'c')
+    }
+
+    /// The data type the message is associated with.
+    pub fn data_type_name(&self) -> Option<&str> {
+        self.field(b'd')
+    }
+
+    /// The constraint the message is associated with, e.g. the one a unique violation violated.
+    pub fn constraint_name(&self) -> Option<&str> {
+        self.field(b'n')
+    }
+
+    /// The server source file the message was reported in.
+    pub fn file(&self) -> Option<&str> {
+        self.field(b'F')
+    }
+
+    /// The line in the server source file the message was reported in.
+    pub fn line(&self) -> Option<u32> {
+        self.field(b'L')?.parse().ok()
+    }
+
+    /// The server source routine the message was reported in.
+    pub fn routine(&self) -> Option<&str> {
+        self.field(b'R')
+    }
+
+    /// Get a field by its identifying byte.
+    fn field(&self, identifier: u8) -> Option<&str> {
+        self.fields.get(&identifier).map(String::as_str)
+    }
+}
+
+impl ParameterStatus {
+    /// The name of the parameter, e.g. `server_version`.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The current value of the parameter.
+    pub fn value(&self) -> &str {
+        &self.value
+    }
+}
+
+impl Notice {
+    /// The severity parsed from the field that is never localized, or from the
+    /// localized one for servers that don't send it (before PostgreSQL 9.6).
+    ///
+    /// Returns `None` if the severity is unknown.
+    pub fn severity_level(&self) -> Option<Severity> {
+        let severity = self.field(b'V').or_else(|| self.field(b'S'))?;
+
+        match severity {
+            "LOG" => Some(Severity::Log),
+            "INFO" => Some(Severity::Info),
+            "NOTICE" => Some(Severity::Notice),
+            "WARNING" => Some(Severity::Warning),
+            debug if debug.starts_with("DEBUG") => Some(Severity::Debug),
+            _ => None,
+        }
+    }
+}
+
+impl Deref for Error {
+    type Target = ResponseFields;
+
+    fn deref(&self) -> &Self::Target {
+        &self.fields
+    }
+}
+
+impl Deref for Notice {
+    type Target = ResponseFields;
+
+    fn deref(&self) -> &Self::Target {
+        &self.fields
+    }
+}
+
+impl KeyData {
+    /// The process ID of the server process that generated the key.
+    pub fn process_id(&self) -> i32 {
+        self.process_id
+    }
+
+    /// The secret key necessary for issuing cancel requests.
+    pub fn secret_key(&self) -> i32 {
+        self.secret_key
+    }
+}
+
+impl CommandComplete {
+    /// The command tag, usually the command name followed by the number of affected rows
+    /// (e.g. `INSERT 0 1` or `SELECT 5`).
+    pub fn tag(&self) -> &str {
+        &self.tag
+    }
+
+    /// The number of rows the command affected or returned, e.g. `1` for `INSERT 0 1`,
+    /// or `None` for commands without a count like `CREATE TABLE`.
+    pub fn rows(&self) -> Option<u64> {
+        let (command, count) = self.tag.rsplit_once(' ')?;
+
+        match command.split(' ').next()? {
+            "INSERT" | "DELETE" | "UPDATE" | "MERGE" | "SELECT" | "MOVE" | "FETCH" | "COPY" => {
+                count.parse().ok()
+            }
+            _ => None,
+        }
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}: {}: {}",
+            self.severity().unwrap_or("ERROR"),
+            self.code().unwrap_or("XX000"),
+            self.message().unwrap_or("unknown error")
+        )
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Displayed as `severity: message`, e.g. `WARNING: something happened`.
+impl Display for Notice {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}: {}",
+            self.severity().unwrap_or("NOTICE"),
+            self.message().unwrap_or("unknown notice")
+        )
+    }
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Severity::Debug => "DEBUG",
+            Severity::Log => "LOG",
+            Severity::Info => "INFO",
+            Severity::Notice => "NOTICE",
+            Severity::Warning => "WARNING",
+        };
+        f.write_str(name)
+    }
+}
+
 impl RowDescription {
     /// Get the index of a field by name.
     pub(crate) fn field_index(&self, name: &str) -> Option<usize> {
@@ -429,14 +994,76 @@ impl RowDescription {
     }
 }
 
+impl FieldDescription {
+    /// The name of the data type of the field, e.g. `int4` or `timestamptz`.
+    ///
+    /// Returns `None` for types that aren't built in, see [`oids::type_name`].
+    pub fn type_name(&self) -> Option<&'static str> {
+        oids::type_name(self.data_type_oid)
+    }
+
+    /// Whether values of the data type vary in length, e.g. `text` or `numeric`.
+    pub fn is_variable_length(&self) -> bool {
+        self.data_type_size < 0
+    }
+
+    /// The size of every value of the data type in bytes, e.g. `4` for `int4`,
+    /// or `None` if the type is variable-length.
+    pub fn fixed_size(&self) -> Option<u16> {
+        u16::try_from(self.data_type_size).ok()
+    }
+}
+
+impl DataRow {
+    /// The fields of the row, in the order of the [`RowDescription`].
+    pub fn iter(&self) -> impl Iterator<Item = &Data> {
+        self.fields.iter()
+    }
+
+    /// The number of fields in the row.
+    pub fn len(&self) -> usize {
+        self.fields.len()
+    }
+
+    /// Whether the row has no fields.
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+}
+
+impl<'a> IntoIterator for &'a DataRow {
+    type Item = &'a Data;
+    type IntoIter = std::slice::Iter<'a, Data>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.fields.iter()
+    }
+}
+
 impl<'a> Data {
+    /// The raw value in the format of its column, `None` if it is `NULL`.
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        self.0.as_deref()
+    }
+
+    /// Whether the value is `NULL`.
+    pub fn is_null(&self) -> bool {
+        self.0.is_none()
+    }
+
     /// Parse a value from text.
     pub(crate) fn parse_text<T: FromSql<'a>>(&'a self) -> Result<T, BoxError> {
-        T::from_text(&self.0)
+        match &self.0 {
+            Some(text) => T::from_text(text),
+            None => T::from_null(),
+        }
     }
 
     /// Parse a value from binary.
     pub(crate) fn parse_binary<T: FromSql<'a>>(&'a self) -> Result<T, BoxError> {
-        T::from_binary(&self.0)
+        match &self.0 {
+            Some(binary) => T::from_binary(binary),
+            None => T::from_null(),
+        }
     }
 }
@@ -1,5 +1,7 @@
 //! This module contains everything directly related to the PostgreSQL protocol.
 
 pub mod message;
+pub mod oids;
+pub mod types;
 
 use message::server::{DataRow, RowDescription};
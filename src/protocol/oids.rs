@@ -0,0 +1,273 @@
+//! Object IDs of built-in PostgreSQL types.
+
+/// The object ID of a type (or any other database object).
+pub type Oid = i32;
+
+/// `bool`
+pub const BOOL: Oid = 16;
+/// `bytea`
+pub const BYTEA: Oid = 17;
+/// `char` (the single-byte internal type)
+pub const CHAR: Oid = 18;
+/// `name`
+pub const NAME: Oid = 19;
+/// `int8` (`bigint`)
+pub const INT8: Oid = 20;
+/// `int2` (`smallint`)
+pub const INT2: Oid = 21;
+/// `int4` (`integer`)
+pub const INT4: Oid = 23;
+/// `text`
+pub const TEXT: Oid = 25;
+/// `oid`
+pub const OID: Oid = 26;
+/// `json`
+pub const JSON: Oid = 114;
+/// `xml`
+pub const XML: Oid = 142;
+/// `point`
+pub const POINT: Oid = 600;
+/// `cidr`
+pub const CIDR: Oid = 650;
+/// `float4` (`real`)
+pub const FLOAT4: Oid = 700;
+/// `float8` (`double precision`)
+pub const FLOAT8: Oid = 701;
+/// `unknown`, the type of untyped literals
+pub const UNKNOWN: Oid = 705;
+/// `money`
+pub const MONEY: Oid = 790;
+/// `macaddr`
+pub const MACADDR: Oid = 829;
+/// `inet`
+pub const INET: Oid = 869;
+/// `bpchar` (`character(n)`)
+pub const BPCHAR: Oid = 1042;
+/// `varchar`
+pub const VARCHAR: Oid = 1043;
+/// `date`
+pub const DATE: Oid = 1082;
+/// `time`
+pub const TIME: Oid = 1083;
+/// `timestamp`
+pub const TIMESTAMP: Oid = 1114;
+/// `timestamptz`
+pub const TIMESTAMPTZ: Oid = 1184;
+/// `interval`
+pub const INTERVAL: Oid = 1186;
+/// `timetz`
+pub const TIMETZ: Oid = 1266;
+/// `bit`
+pub const BIT: Oid = 1560;
+/// `varbit` (`bit varying`)
+pub const VARBIT: Oid = 1562;
+/// `numeric`
+pub const NUMERIC: Oid = 1700;
+/// `regclass`
+pub const REGCLASS: Oid = 2205;
+/// `record`
+pub const RECORD: Oid = 2249;
+/// `void`
+pub const VOID: Oid = 2278;
+/// `uuid`
+pub const UUID: Oid = 2950;
+/// `pg_lsn`
+pub const PG_LSN: Oid = 3220;
+/// `jsonb`
+pub const JSONB: Oid = 3802;
+
+/// `bool[]`
+pub const BOOL_ARRAY: Oid = 1000;
+/// `bytea[]`
+pub const BYTEA_ARRAY: Oid = 1001;
+/// `char[]`
+pub const CHAR_ARRAY: Oid = 1002;
+/// `name[]`
+pub const NAME_ARRAY: Oid = 1003;
+/// `int8[]`
+pub const INT8_ARRAY: Oid = 1016;
+/// `int2[]`
+pub const INT2_ARRAY: Oid = 1005;
+/// `int4[]`
+pub const INT4_ARRAY: Oid = 1007;
+/// `text[]`
+pub const TEXT_ARRAY: Oid = 1009;
+/// `oid[]`
+pub const OID_ARRAY: Oid = 1028;
+/// `json[]`
+pub const JSON_ARRAY: Oid = 199;
+/// `xml[]`
+pub const XML_ARRAY: Oid = 143;
+/// `point[]`
+pub const POINT_ARRAY: Oid = 1017;
+/// `cidr[]`
+pub const CIDR_ARRAY: Oid = 651;
+/// `float4[]`
+pub const FLOAT4_ARRAY: Oid = 1021;
+/// `float8[]`
+pub const FLOAT8_ARRAY: Oid = 1022;
+/// `money[]`
+pub const MONEY_ARRAY: Oid = 791;
+/// `macaddr[]`
+pub const MACADDR_ARRAY: Oid = 1040;
+/// `inet[]`
+pub const INET_ARRAY: Oid = 1041;
+/// `bpchar[]`
+pub const BPCHAR_ARRAY: Oid = 1014;
+/// `varchar[]`
+pub const VARCHAR_ARRAY: Oid = 1015;
+/// `date[]`
+pub const DATE_ARRAY: Oid = 1182;
+/// `time[]`
+pub const TIME_ARRAY: Oid = 1183;
+/// `timestamp[]`
+pub const TIMESTAMP_ARRAY: Oid = 1115;
+/// `timestamptz[]`
+pub const TIMESTAMPTZ_ARRAY: Oid = 1185;
+/// `interval[]`
+pub const INTERVAL_ARRAY: Oid = 1187;
+/// `timetz[]`
+pub const TIMETZ_ARRAY: Oid = 1270;
+/// `bit[]`
+pub const BIT_ARRAY: Oid = 1561;
+/// `varbit[]`
+pub const VARBIT_ARRAY: Oid = 1563;
+/// `numeric[]`
+pub const NUMERIC_ARRAY: Oid = 1231;
+/// `regclass[]`
+pub const REGCLASS_ARRAY: Oid = 2210;
+/// `record[]`
+pub const RECORD_ARRAY: Oid = 2287;
+/// `uuid[]`
+pub const UUID_ARRAY: Oid = 2951;
+/// `pg_lsn[]`
+pub const PG_LSN_ARRAY: Oid = 3221;
+/// `jsonb[]`
+pub const JSONB_ARRAY: Oid = 3807;
+
+/// The name of a built-in type, e.g. `int4` for [`INT4`].
+///
+/// Returns `None` for types that aren't listed in this module, look them
+/// up in `pg_type` instead.
+pub fn type_name(oid: Oid) -> Option<&'static str> {
+    let name = match oid {
+        BOOL => "bool",
+        BYTEA => "bytea",
+        CHAR => "char",
+        NAME => "name",
+        INT8 => "int8",
+        INT2 => "int2",
+        INT4 => "int4",
+        TEXT => "text",
+        OID => "oid",
+        JSON => "json",
+        XML => "xml",
+        POINT => "point",
+        CIDR => "cidr",
+        FLOAT4 => "float4",
+        FLOAT8 => "float8",
+        UNKNOWN => "unknown",
+        MONEY => "money",
+        MACADDR => "macaddr",
+        INET => "inet",
+        BPCHAR => "bpchar",
+        VARCHAR => "varchar",
+        DATE => "date",
+        TIME => "time",
+        TIMESTAMP => "timestamp",
+        TIMESTAMPTZ => "timestamptz",
+        INTERVAL => "interval",
+        TIMETZ => "timetz",
+        BIT => "bit",
+        VARBIT => "varbit",
+        NUMERIC => "numeric",
+        REGCLASS => "regclass",
+        RECORD => "record",
+        VOID => "void",
+        UUID => "uuid",
+        PG_LSN => "pg_lsn",
+        JSONB => "jsonb",
+        BOOL_ARRAY => "_bool",
+        BYTEA_ARRAY => "_bytea",
+        CHAR_ARRAY => "_char",
+        NAME_ARRAY => "_name",
+        INT8_ARRAY => "_int8",
+        INT2_ARRAY => "_int2",
+        INT4_ARRAY => "_int4",
+        TEXT_ARRAY => "_text",
+        OID_ARRAY => "_oid",
+        JSON_ARRAY => "_json",
+        XML_ARRAY => "_xml",
+        POINT_ARRAY => "_point",
+        CIDR_ARRAY => "_cidr",
+        FLOAT4_ARRAY => "_float4",
+        FLOAT8_ARRAY => "_float8",
+        MONEY_ARRAY => "_money",
+        MACADDR_ARRAY => "_macaddr",
+        INET_ARRAY => "_inet",
+        BPCHAR_ARRAY => "_bpchar",
+        VARCHAR_ARRAY => "_varchar",
+        DATE_ARRAY => "_date",
+        TIME_ARRAY => "_time",
+        TIMESTAMP_ARRAY => "_timestamp",
+        TIMESTAMPTZ_ARRAY => "_timestamptz",
+        INTERVAL_ARRAY => "_interval",
+        TIMETZ_ARRAY => "_timetz",
+        BIT_ARRAY => "_bit",
+        VARBIT_ARRAY => "_varbit",
+        NUMERIC_ARRAY => "_numeric",
+        REGCLASS_ARRAY => "_regclass",
+        RECORD_ARRAY => "_record",
+        UUID_ARRAY => "_uuid",
+        PG_LSN_ARRAY => "_pg_lsn",
+        JSONB_ARRAY => "_jsonb",
+        _ => return None,
+    };
+
+    Some(name)
+}
+
+/// The type of the elements of a built-in array type, e.g. [`INT4`] for [`INT4_ARRAY`].
+///
+/// Returns `None` for types that aren't arrays listed in this module.
+pub fn array_element_type(oid: Oid) -> Option<Oid> {
+    let element = match oid {
+        BOOL_ARRAY => BOOL,
+        BYTEA_ARRAY => BYTEA,
+        CHAR_ARRAY => CHAR,
+        NAME_ARRAY => NAME,
+        INT8_ARRAY => INT8,
+        INT2_ARRAY => INT2,
+        INT4_ARRAY => INT4,
+        TEXT_ARRAY => TEXT,
+        OID_ARRAY => OID,
+        JSON_ARRAY => JSON,
+        XML_ARRAY => XML,
+        POINT_ARRAY => POINT,
+        CIDR_ARRAY => CIDR,
+        FLOAT4_ARRAY => FLOAT4,
+        FLOAT8_ARRAY => FLOAT8,
+        MONEY_ARRAY => MONEY,
+        MACADDR_ARRAY => MACADDR,
+        INET_ARRAY => INET,
+        BPCHAR_ARRAY => BPCHAR,
+        VARCHAR_ARRAY => VARCHAR,
+        DATE_ARRAY => DATE,
+        TIME_ARRAY => TIME,
+        TIMESTAMP_ARRAY => TIMESTAMP,
+        TIMESTAMPTZ_ARRAY => TIMESTAMPTZ,
+        INTERVAL_ARRAY => INTERVAL,
+        TIMETZ_ARRAY => TIMETZ,
+        BIT_ARRAY => BIT,
+        VARBIT_ARRAY => VARBIT,
+        NUMERIC_ARRAY => NUMERIC,
+        REGCLASS_ARRAY => REGCLASS,
+        RECORD_ARRAY => RECORD,
+        UUID_ARRAY => UUID,
+        PG_LSN_ARRAY => PG_LSN,
+        JSONB_ARRAY => JSONB,
+        _ => return None,
+    };
+
+    Some(element)
+}
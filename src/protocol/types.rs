@@ -0,0 +1,682 @@
+//! Rust representations of PostgreSQL types that don't have
+//! a natural counterpart in the standard library.
+
+use std::{
+    fmt::{Display, Write},
+    num::ParseFloatError,
+    time::Duration,
+};
+
+use crate::util::{BoxError, Reader};
+
+use super::{
+    message::{parsing::FromSql, server::FormatCode},
+    oids::{self, Oid},
+};
+
+/// The default maximum size of a [`Json`] value in bytes (16 MiB).
+#[cfg(feature = "json")]
+pub const DEFAULT_MAX_JSON_SIZE: usize = 16 * 1024 * 1024;
+
+/// The version of the binary `jsonb` format, which precedes the JSON text.
+#[cfg(feature = "json")]
+const JSONB_VERSION: u8 = 1;
+
+/// Microseconds in a second.
+const MICROS_PER_SECOND: i64 = 1_000_000;
+/// Microseconds in a day, as `interval` counts days.
+const MICROS_PER_DAY: i64 = 24 * 3600 * MICROS_PER_SECOND;
+
+/// The day binary dates count from (2000-01-01).
+#[cfg(feature = "chrono")]
+const POSTGRES_EPOCH_DATE: chrono::NaiveDate = match chrono::NaiveDate::from_ymd_opt(2000, 1, 1) {
+    Some(date) => date,
+    None => unreachable!(),
+};
+
+/// The sign of a positive `numeric` in binary format.
+const NUMERIC_POSITIVE: u16 = 0x0000;
+/// The sign of a negative `numeric` in binary format.
+const NUMERIC_NEGATIVE: u16 = 0x4000;
+/// The sign of a `numeric` `NaN` in binary format.
+const NUMERIC_NAN: u16 = 0xC000;
+/// The sign of a positive infinite `numeric` in binary format.
+const NUMERIC_POSITIVE_INFINITY: u16 = 0xD000;
+/// The sign of a negative infinite `numeric` in binary format.
+const NUMERIC_NEGATIVE_INFINITY: u16 = 0xF000;
+
+/// A `time with time zone` (`timetz`) value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TimeTz {
+    /// The time of day in microseconds since midnight.
+    pub micros: i64,
+    /// The offset of the time zone from UTC in seconds.
+    ///
+    /// Like in the text representation, this is positive east of Greenwich.
+    pub zone_offset_secs: i32,
+}
+
+impl<'a> FromSql<'a> for TimeTz {
+    fn from_text(text: &'a [u8]) -> Result<Self, BoxError> {
+        let text = std::str::from_utf8(text)?;
+
+        // The time of day never contains a sign, so the first one starts the offset
+        let Some(sign_position) = text.find(['+', '-']) else {
+            return Err(format!("missing time zone offset in `{}`", text).into());
+        };
+        let (time, offset) = text.split_at(sign_position);
+
+        let micros = parse_time_of_day(time)?;
+
+        // The offset is `+HH`, `+HH:MM` or `+HH:MM:SS`
+        let sign = if offset.starts_with('-') { -1 } else { 1 };
+        let components = offset[1..].split(':').collect::<Vec<_>>();
+        if components.len() > 3 {
+            return Err(format!("invalid time zone offset `{}`", offset).into());
+        }
+
+        let mut zone_offset_secs = 0i32;
+        for (component, factor) in components.into_iter().zip([3600, 60, 1]) {
+            // `str::parse` would accept another sign, e.g. in `+-5`
+            if component.is_empty() || !component.bytes().all(|byte| byte.is_ascii_digit()) {
+                return Err(format!("invalid time zone offset `{}`", offset).into());
+            }
+            zone_offset_secs = component
+                .parse::<i32>()?
+                .checked_mul(factor)
+                .and_then(|secs| zone_offset_secs.checked_add(secs))
+                .ok_or_else(|| format!("time zone offset `{}` out of range", offset))?;
+        }
+
+        Ok(TimeTz {
+            micros,
+            zone_offset_secs: sign * zone_offset_secs,
+        })
+    }
+
+    fn from_binary(binary: &'a [u8]) -> Result<Self, BoxError> {
+        let mut reader = Reader::new(binary);
+
+        let micros = reader.read_i64()?;
+        // The server sends the offset in seconds west of Greenwich
+        let zone_offset_secs = reader.read_i32()?;
+        let Some(zone_offset_secs) = zone_offset_secs.checked_neg() else {
+            return Err(format!("time zone offset `{}` out of range", zone_offset_secs).into());
+        };
+        reader.finish()?;
+
+        Ok(TimeTz {
+            micros,
+            zone_offset_secs,
+        })
+    }
+}
+
+/// An `interval` value.
+///
+/// Like on the server, the months, days and time are kept apart, since
+/// months and days (across daylight saving changes) differ in length.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct PgInterval {
+    /// The number of months, a year is 12 months.
+    pub months: i32,
+    /// The number of days.
+    pub days: i32,
+    /// The time in microseconds.
+    pub micros: i64,
+}
+
+impl PgInterval {
+    /// The length of the interval, counting a day as 24 hours.
+    ///
+    /// Returns `None` if the interval has a month component, since months
+    /// don't have a fixed length, or if it is negative.
+    pub fn to_duration(&self) -> Option<Duration> {
+        if self.months != 0 {
+            return None;
+        }
+
+        let micros = (self.days as i64)
+            .checked_mul(MICROS_PER_DAY)?
+            .checked_add(self.micros)?;
+
+        Some(Duration::from_micros(u64::try_from(micros).ok()?))
+    }
+}
+
+impl<'a> FromSql<'a> for PgInterval {
+    /// Parses the default `postgres` interval style, e.g.
+    /// `1 year 2 mons -3 days +04:05:06.5`.
+    fn from_text(text: &'a [u8]) -> Result<Self, BoxError> {
+        let text = std::str::from_utf8(text)?;
+
+        let mut interval = PgInterval::default();
+        let mut tokens = text.split_whitespace();
+
+        while let Some(token) = tokens.next() {
+            // The time is the only token with colons, e.g. `-04:05:06`
+            if token.contains(':') {
+                let (sign, time) = match token.strip_prefix('-') {
+                    Some(time) => (-1, time),
+                    None => (1, token.strip_prefix('+').unwrap_or(token)),
+                };
+                interval.micros = parse_time_of_day(time)?
+                    .checked_mul(sign)
+                    .and_then(|micros| interval.micros.checked_add(micros))
+                    .ok_or_else(|| format!("interval `{}` out of range", text))?;
+                continue;
+            }
+
+            let amount = token.parse::<i32>()?;
+            let Some(unit) = tokens.next() else {
+                return Err(
+                    format!("missing unit after `{}` in interval `{}`", amount, text).into(),
+                );
+            };
+            let (field, amount) = match unit {
+                "year" | "years" => (&mut interval.months, amount.checked_mul(12)),
+                "mon" | "mons" => (&mut interval.months, Some(amount)),
+                "day" | "days" => (&mut interval.days, Some(amount)),
+                otherwise => {
+                    return Err(
+                        format!("unknown interval unit `{}` in `{}`", otherwise, text).into(),
+                    )
+                }
+            };
+            *field = amount
+                .and_then(|amount| field.checked_add(amount))
+                .ok_or_else(|| format!("interval `{}` out of range", text))?;
+        }
+
+        Ok(interval)
+    }
+
+    fn from_binary(binary: &'a [u8]) -> Result<Self, BoxError> {
+        let mut reader = Reader::new(binary);
+
+        let micros = reader.read_i64()?;
+        let days = reader.read_i32()?;
+        let months = reader.read_i32()?;
+        reader.finish()?;
+
+        Ok(PgInterval {
+            months,
+            days,
+            micros,
+        })
+    }
+}
+
+#[cfg(feature = "chrono")]
+impl<'a> FromSql<'a> for chrono::NaiveDate {
+    /// Parses the ISO date style, e.g. `2024-02-29` or `0044-03-15 BC`.
+    fn from_text(text: &'a [u8]) -> Result<Self, BoxError> {
+        use chrono::Datelike;
+
+        let text = std::str::from_utf8(text)?;
+
+        let (date, before_christ) = match text.strip_suffix(" BC") {
+            Some(date) => (date, true),
+            None => (text, false),
+        };
+        let date = chrono::NaiveDate::parse_from_str(date, "%Y-%m-%d")?;
+
+        // There is no year zero, so 1 BC is the year 0 in chrono
+        if before_christ {
+            return date
+                .with_year(1 - date.year())
+                .ok_or_else(|| format!("date `{}` out of range", text).into());
+        }
+
+        Ok(date)
+    }
+
+    fn from_binary(binary: &'a [u8]) -> Result<Self, BoxError> {
+        let mut reader = Reader::new(binary);
+
+        // The number of days since 2000-01-01, `infinity` is out of range
+        let days = reader.read_i32()?;
+        reader.finish()?;
+
+        POSTGRES_EPOCH_DATE
+            .checked_add_signed(chrono::Duration::days(days as i64))
+            .ok_or_else(|| format!("date {} days from 2000-01-01 out of range", days).into())
+    }
+}
+
+#[cfg(feature = "chrono")]
+impl<'a> FromSql<'a> for chrono::NaiveTime {
+    fn from_text(text: &'a [u8]) -> Result<Self, BoxError> {
+        micros_to_time(parse_time_of_day(std::str::from_utf8(text)?)?)
+    }
+
+    fn from_binary(binary: &'a [u8]) -> Result<Self, BoxError> {
+        let mut reader = Reader::new(binary);
+
+        let micros = reader.read_i64()?;
+        reader.finish()?;
+
+        micros_to_time(micros)
+    }
+}
+
+/// Convert microseconds since midnight to a time of day.
+#[cfg(feature = "chrono")]
+fn micros_to_time(micros: i64) -> Result<chrono::NaiveTime, BoxError> {
+    // `24:00:00` is a valid time on the server, but not in chrono
+    let seconds = u32::try_from(micros.div_euclid(MICROS_PER_SECOND))?;
+    let nanos = micros.rem_euclid(MICROS_PER_SECOND) as u32 * 1000;
+
+    chrono::NaiveTime::from_num_seconds_from_midnight_opt(seconds, nanos)
+        .ok_or_else(|| format!("time of day of {} microseconds out of range", micros).into())
+}
+
+/// A `pg_lsn` value, a position in the write-ahead log.
+///
+/// It is displayed like on the server, as two hexadecimal halves
+/// separated by a slash, e.g. `0/16B3748`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Default)]
+pub struct PgLsn(pub u64);
+
+impl Display for PgLsn {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:X}/{:X}", self.0 >> 32, self.0 as u32)
+    }
+}
+
+impl<'a> FromSql<'a> for PgLsn {
+    fn from_text(text: &'a [u8]) -> Result<Self, BoxError> {
+        let text = std::str::from_utf8(text)?;
+
+        let Some((high, low)) = text.split_once('/') else {
+            return Err(format!("missing slash in log sequence number `{}`", text).into());
+        };
+        let high = u32::from_str_radix(high, 16)?;
+        let low = u32::from_str_radix(low, 16)?;
+
+        Ok(PgLsn((high as u64) << 32 | low as u64))
+    }
+
+    fn from_binary(binary: &'a [u8]) -> Result<Self, BoxError> {
+        let mut reader = Reader::new(binary);
+
+        let lsn = reader.read_i64()?;
+        reader.finish()?;
+
+        Ok(PgLsn(lsn as u64))
+    }
+}
+
+/// A `bit(n)` or `bit varying` value, the bits in order.
+///
+/// It is displayed like on the server, as `0`s and `1`s, e.g. `10110`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+pub struct BitString(pub Vec<bool>);
+
+impl Display for BitString {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for bit in &self.0 {
+            f.write_char(if *bit { '1' } else { '0' })?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<'a> FromSql<'a> for BitString {
+    fn from_text(text: &'a [u8]) -> Result<Self, BoxError> {
+        text.iter()
+            .map(|byte| match byte {
+                b'0' => Ok(false),
+                b'1' => Ok(true),
+                otherwise => Err(format!(
+                    "invalid bit `{}` in bit string `{}`",
+                    otherwise.escape_ascii(),
+                    text.escape_ascii()
+                )
+                .into()),
+            })
+            .collect::<Result<_, BoxError>>()
+            .map(BitString)
+    }
+
+    fn from_binary(binary: &'a [u8]) -> Result<Self, BoxError> {
+        let mut reader = Reader::new(binary);
+
+        // The number of bits, followed by the bits packed into bytes, highest bit first
+        let length = reader.read_i32()?;
+        let length = usize::try_from(length)
+            .map_err(|_| format!("negative bit string length `{}`", length))?;
+        let bytes = reader.read_remaining_bytes_exact(length.div_ceil(8))?;
+
+        let bits = (0..length)
+            .map(|index| bytes[index / 8] & (0x80 >> (index % 8)) != 0)
+            .collect();
+
+        Ok(BitString(bits))
+    }
+}
+
+/// A value whose type is only known at runtime, from the type of its column.
+///
+/// See [`Row::get_value`](crate::connection::Row::get_value).
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum PgValue {
+    /// `NULL`, whatever the type of the column.
+    Null,
+    /// A `bool`.
+    Bool(bool),
+    /// An `int4`.
+    Int4(i32),
+    /// A `text`, `varchar`, `bpchar` or `name`.
+    Text(String),
+    /// A `bytea`.
+    Bytea(Vec<u8>),
+    /// A value of any other type, as it was received in the format of its column.
+    Unknown(Vec<u8>),
+}
+
+impl PgValue {
+    /// Decode a value of the type with the object ID `oid` in the given format.
+    pub fn decode(oid: Oid, format: FormatCode, data: Option<&[u8]>) -> Result<Self, BoxError> {
+        let Some(data) = data else {
+            return Ok(PgValue::Null);
+        };
+
+        let value = match (oid, format) {
+            (oids::BOOL, FormatCode::Text) => match data {
+                b"t" => PgValue::Bool(true),
+                b"f" => PgValue::Bool(false),
+                otherwise => {
+                    return Err(format!("invalid bool `{}`", otherwise.escape_ascii()).into())
+                }
+            },
+            (oids::BOOL, FormatCode::Binary) => match data {
+                [byte] => PgValue::Bool(*byte != 0),
+                _ => return Err(format!("invalid bool length `{}`", data.len()).into()),
+            },
+            (oids::INT4, FormatCode::Text) => PgValue::Int4(i32::from_text(data)?),
+            (oids::INT4, FormatCode::Binary) => PgValue::Int4(i32::from_binary(data)?),
+            // The binary format of text types is the text itself
+            (oids::TEXT | oids::VARCHAR | oids::BPCHAR | oids::NAME, _) => {
+                PgValue::Text(String::from_text(data)?)
+            }
+            (oids::BYTEA, FormatCode::Text) => PgValue::Bytea(decode_bytea_hex(data)?),
+            (oids::BYTEA, FormatCode::Binary) => PgValue::Bytea(data.to_vec()),
+            _ => PgValue::Unknown(data.to_vec()),
+        };
+
+        Ok(value)
+    }
+}
+
+/// Decode a `bytea` in the (default) hex text format, e.g. `\x00ff`.
+fn decode_bytea_hex(text: &[u8]) -> Result<Vec<u8>, BoxError> {
+    let Some(hex) = text.strip_prefix(b"\\x") else {
+        return Err("bytea isn't in hex format, set `bytea_output` to `hex`".into());
+    };
+
+    if hex.len() % 2 != 0 {
+        return Err(format!(
+            "odd number of hex digits in bytea `{}`",
+            text.escape_ascii()
+        )
+        .into());
+    }
+
+    hex.chunks_exact(2)
+        .map(|pair| {
+            let digits = std::str::from_utf8(pair)?;
+            Ok(u8::from_str_radix(digits, 16)?)
+        })
+        .collect()
+}
+
+/// A `money` value in the minor units of the currency, e.g. cents.
+///
+/// How many minor units make up a major one depends on the server's
+/// `lc_monetary` setting. The text format is formatted for that locale too
+/// (e.g. `$1,234.56` or `-1.234,56 €`), so it is only parsed on a best-effort
+/// basis: every digit counts, assuming the server prints all fraction digits
+/// as it does by default. Prefer the binary format, which is exact.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Default)]
+pub struct Money(pub i64);
+
+impl<'a> FromSql<'a> for Money {
+    fn from_text(text: &'a [u8]) -> Result<Self, BoxError> {
+        let text = std::str::from_utf8(text)?;
+
+        // Drop currency symbols and separators, negative amounts have
+        // a minus sign or parentheses depending on the locale
+        let digits = text
+            .chars()
+            .filter(char::is_ascii_digit)
+            .collect::<String>();
+        if digits.is_empty() {
+            return Err(format!("no digits in money `{}`", text).into());
+        }
+        let negative = text.contains(['-', '(']);
+
+        let minor_units = format!("{}{}", if negative { "-" } else { "" }, digits);
+        Ok(Money(minor_units.parse()?))
+    }
+
+    fn from_binary(binary: &'a [u8]) -> Result<Self, BoxError> {
+        Ok(Money(i64::from_be_bytes(binary.try_into()?)))
+    }
+}
+
+/// A `point` value, a position in the plane.
+///
+/// It is displayed like on the server, as the coordinates in parentheses,
+/// e.g. `(1.5,-2)`.
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Default)]
+pub struct PgPoint {
+    /// The horizontal coordinate.
+    pub x: f64,
+    /// The vertical coordinate.
+    pub y: f64,
+}
+
+impl Display for PgPoint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "({},{})", self.x, self.y)
+    }
+}
+
+impl<'a> FromSql<'a> for PgPoint {
+    fn from_text(text: &'a [u8]) -> Result<Self, BoxError> {
+        let text = std::str::from_utf8(text)?;
+
+        let coordinates = text
+            .trim()
+            .strip_prefix('(')
+            .and_then(|rest| rest.strip_suffix(')'))
+            .and_then(|coordinates| coordinates.split_once(','));
+        let Some((x, y)) = coordinates else {
+            return Err(format!("expected a point like `(x,y)`, got `{}`", text).into());
+        };
+
+        // Like `float8`, the coordinates may be `NaN` or infinite
+        Ok(PgPoint {
+            x: f64::from_text(x.trim().as_bytes())?,
+            y: f64::from_text(y.trim().as_bytes())?,
+        })
+    }
+
+    fn from_binary(binary: &'a [u8]) -> Result<Self, BoxError> {
+        let mut reader = Reader::new(binary);
+
+        let x = f64::from_be_bytes(*reader.read_bytes_const::<8>()?);
+        let y = f64::from_be_bytes(*reader.read_bytes_const::<8>()?);
+        reader.finish()?;
+
+        Ok(PgPoint { x, y })
+    }
+}
+
+/// An arbitrary precision `numeric` value, kept as its exact decimal text
+/// (e.g. `-12.340`, `NaN` or `Infinity`).
+///
+/// This avoids the precision loss of converting to a float. Use
+/// [`Numeric::as_str`] to hand the value to a decimal library, or convert
+/// it to an [`f64`] if some loss of precision is acceptable.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Numeric(String);
+
+impl Numeric {
+    /// The exact decimal text of the value.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl TryFrom<Numeric> for f64 {
+    type Error = ParseFloatError;
+
+    fn try_from(value: Numeric) -> Result<Self, Self::Error> {
+        value.0.parse()
+    }
+}
+
+impl<'a> FromSql<'a> for Numeric {
+    fn from_text(text: &'a [u8]) -> Result<Self, BoxError> {
+        Ok(Numeric(std::str::from_utf8(text)?.to_string()))
+    }
+
+    fn from_binary(binary: &'a [u8]) -> Result<Self, BoxError> {
+        let mut reader = Reader::new(binary);
+
+        // The digits are base 10000, `weight` is the power of the first one
+        let digit_count = reader.read_i16()?;
+        let weight = reader.read_i16()? as i32;
+        let sign = reader.read_i16()? as u16;
+        let scale = reader.read_i16()?;
+
+        if digit_count < 0 || scale < 0 {
+            return Err("negative digit count or scale in numeric".into());
+        }
+
+        let mut digits = Vec::with_capacity(digit_count as usize);
+        for _ in 0..digit_count {
+            match reader.read_i16()? {
+                digit @ 0..=9999 => digits.push(digit),
+                otherwise => return Err(format!("invalid numeric digit `{}`", otherwise).into()),
+            }
+        }
+        reader.finish()?;
+
+        let mut text = String::new();
+        match sign {
+            NUMERIC_POSITIVE => {}
+            NUMERIC_NEGATIVE => text.push('-'),
+            NUMERIC_NAN => return Ok(Numeric("NaN".to_string())),
+            NUMERIC_POSITIVE_INFINITY => return Ok(Numeric("Infinity".to_string())),
+            NUMERIC_NEGATIVE_INFINITY => return Ok(Numeric("-Infinity".to_string())),
+            otherwise => return Err(format!("invalid numeric sign `{:#x}`", otherwise).into()),
+        }
+
+        // Digits outside of the sent ones are zero
+        let digit = |index: i32| {
+            usize::try_from(index)
+                .ok()
+                .and_then(|index| digits.get(index).copied())
+                .unwrap_or(0)
+        };
+
+        // The integer part, without leading zeros
+        if weight < 0 {
+            text.push('0');
+        } else {
+            for index in 0..=weight {
+                if index == 0 {
+                    write!(text, "{}", digit(index))?;
+                } else {
+                    write!(text, "{:04}", digit(index))?;
+                }
+            }
+        }
+
+        // The fractional part, exactly `scale` decimal digits long
+        if scale > 0 {
+            let mut fraction = String::new();
+            let mut index = weight + 1;
+            while fraction.len() < scale as usize {
+                write!(fraction, "{:04}", digit(index))?;
+                index += 1;
+            }
+            fraction.truncate(scale as usize);
+
+            text.push('.');
+            text.push_str(&fraction);
+        }
+
+        Ok(Numeric(text))
+    }
+}
+
+/// A `json` or `jsonb` value deserialized with serde.
+///
+/// Values larger than `MAX_SIZE` bytes are rejected before they are parsed,
+/// so a huge value can't exhaust memory. Nesting is limited by serde_json's
+/// recursion limit.
+#[cfg(feature = "json")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Json<T, const MAX_SIZE: usize = DEFAULT_MAX_JSON_SIZE>(pub T);
+
+#[cfg(feature = "json")]
+impl<'a, T: serde::Deserialize<'a>, const MAX_SIZE: usize> FromSql<'a> for Json<T, MAX_SIZE> {
+    fn from_text(text: &'a [u8]) -> Result<Self, BoxError> {
+        if text.len() > MAX_SIZE {
+            return Err(format!(
+                "JSON value of {} bytes exceeds the limit of {} bytes",
+                text.len(),
+                MAX_SIZE
+            )
+            .into());
+        }
+
+        Ok(Json(serde_json::from_slice(text)?))
+    }
+
+    fn from_binary(binary: &'a [u8]) -> Result<Self, BoxError> {
+        // `jsonb` starts with a version byte, `json` is sent as plain text
+        match binary.split_first() {
+            Some((&JSONB_VERSION, text)) => Self::from_text(text),
+            _ => Self::from_text(binary),
+        }
+    }
+}
+
+/// Parse a time of day in the form `HH:MM:SS[.ffffff]` to microseconds since midnight.
+fn parse_time_of_day(text: &str) -> Result<i64, BoxError> {
+    let mut components = text.splitn(3, ':');
+    let (Some(hours), Some(minutes), Some(seconds)) =
+        (components.next(), components.next(), components.next())
+    else {
+        return Err(format!("invalid time of day `{}`", text).into());
+    };
+
+    let (seconds, fraction) = seconds.split_once('.').unwrap_or((seconds, ""));
+    if fraction.len() > 6 || !fraction.bytes().all(|byte| byte.is_ascii_digit()) {
+        return Err(format!("invalid fractional seconds in `{}`", text).into());
+    }
+
+    // Pad the fraction to microseconds, e.g. `.5` is 500000 microseconds
+    let fraction_micros = format!("{:0<6}", fraction).parse::<i64>()?;
+
+    let (hours, minutes, seconds) = (
+        hours.parse::<i64>()?,
+        minutes.parse::<i64>()?,
+        seconds.parse::<i64>()?,
+    );
+
+    hours
+        .checked_mul(3600)
+        .and_then(|total| total.checked_add(minutes.checked_mul(60)?))
+        .and_then(|total| total.checked_add(seconds))
+        .and_then(|total| total.checked_mul(MICROS_PER_SECOND))
+        .and_then(|total| total.checked_add(fraction_micros))
+        .ok_or_else(|| format!("time of day `{}` out of range", text).into())
+}
@@ -0,0 +1,380 @@
+//! Support for streaming replication, which sends write-ahead log (WAL) data
+//! over a copy both stream on a replication connection.
+//!
+//! Open the connection with [`ConnectionConfig::replication`], then start
+//! streaming with [`Connection::copy_both`]. The WAL data is passed on as it
+//! is: decoding the output of a logical decoding plugin is left to the caller.
+//!
+//! [`ConnectionConfig::replication`]: crate::config::ConnectionConfig::replication
+
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::{
+    connection::{Connection, ConnectionState, ProtocolError},
+    protocol::{
+        message::{
+            client,
+            server::{self, CopyResponse},
+        },
+        types::PgLsn,
+    },
+    util::{CodecError, DecodeError, Reader, Writer},
+    Error,
+};
+
+/// The seconds from the Unix epoch to 2000-01-01, which replication timestamps count from.
+const POSTGRES_EPOCH_SECS: u64 = 946_684_800;
+
+/// How often [`ReplicationStream`] sends a status update by default.
+const DEFAULT_STATUS_INTERVAL: Duration = Duration::from_secs(10);
+
+/// A message of the replication sub-protocol, sent by the server in copy data.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ReplicationMessage {
+    /// A chunk of WAL data.
+    XLogData(XLogData),
+    /// A keepalive, which may ask for a status update.
+    PrimaryKeepalive(PrimaryKeepalive),
+}
+
+/// A chunk of WAL data (`XLogData`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct XLogData {
+    /// The position of the start of the data in the WAL.
+    pub wal_start: PgLsn,
+    /// The current end of the WAL on the server.
+    pub wal_end: PgLsn,
+    /// When the server sent the message.
+    pub server_time: SystemTime,
+    /// The WAL data, e.g. the output of a logical decoding plugin.
+    pub data: Vec<u8>,
+}
+
+/// A keepalive of the server (`Primary keepalive message`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PrimaryKeepalive {
+    /// The current end of the WAL on the server.
+    pub wal_end: PgLsn,
+    /// When the server sent the message.
+    pub server_time: SystemTime,
+    /// Whether the server asks for a status update as soon as possible,
+    /// to avoid a timeout disconnect.
+    pub reply_requested: bool,
+}
+
+/// The progress of the client, reported to the server in copy data
+/// (`Standby status update`).
+///
+/// The positions are those of the last WAL byte plus one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StandbyStatusUpdate {
+    /// The position up to which WAL data was received.
+    pub written: PgLsn,
+    /// The position up to which WAL data was stored durably.
+    pub flushed: PgLsn,
+    /// The position up to which WAL data was applied.
+    pub applied: PgLsn,
+    /// When the client sent the message.
+    pub client_time: SystemTime,
+    /// Whether the client asks the server to reply with a keepalive immediately.
+    pub reply_requested: bool,
+}
+
+/// Streams WAL data from the server, started by [`Connection::copy_both`].
+///
+/// Read the data with [`ReplicationStream::next`] and report what was
+/// processed with [`ReplicationStream::acknowledge`]. Status updates are sent
+/// when the server asks for one and otherwise every 10 seconds, checked
+/// whenever a message arrives.
+///
+/// The connection can't be used for anything else while the stream is alive.
+/// End the stream with [`ReplicationStream::finish`]. If it is dropped instead,
+/// it is ended before the next message is sent.
+pub struct ReplicationStream<'a> {
+    /// The connection the stream runs over.
+    connection: &'a mut Connection,
+    /// The format the server announced for the copy.
+    format: CopyResponse,
+    /// The position up to which WAL data was received.
+    written: PgLsn,
+    /// The position up to which the caller acknowledged the WAL data.
+    flushed: PgLsn,
+    /// How often a status update is sent.
+    status_interval: Duration,
+    /// When the last status update was sent.
+    last_status: Instant,
+    /// Whether the server ended the stream.
+    done: bool,
+}
+
+impl Connection {
+    /// Run a query that starts a copy both stream, e.g. `START_REPLICATION`
+    /// on a replication connection, and return the stream.
+    pub async fn copy_both(&mut self, query: &str) -> Result<ReplicationStream<'_>, Error> {
+        let query_message = client::Query::new(query.to_string());
+        self.send_message(&query_message).await?;
+
+        let mut error = None;
+
+        // Read messages until the server starts the copy
+        loop {
+            let response = self.read_message().await?;
+
+            tracing::debug!(response=?&response, "Received message from server");
+
+            match response {
+                server::Message::CopyBothResponse(format) => {
+                    return Ok(ReplicationStream {
+                        connection: self,
+                        format,
+                        written: PgLsn::default(),
+                        flushed: PgLsn::default(),
+                        status_interval: DEFAULT_STATUS_INTERVAL,
+                        last_status: Instant::now(),
+                        done: false,
+                    });
+                }
+                // The query failed, the server will skip to ready for query
+                server::Message::Error(response) => {
+                    tracing::error!(error=?response, "Copy error");
+                    error = Some(response);
+                }
+                // The query is done without ever starting a copy
+                server::Message::ReadyForQuery => break,
+                otherwise => self.buffer_message(otherwise)?,
+            }
+        }
+
+        match error {
+            Some(error) => Err(Error::QueryError(error)),
+            None => Err(ProtocolError::MissingCopyResponse.into()),
+        }
+    }
+
+    /// End a copy both stream whose [`ReplicationStream`] was dropped,
+    /// since the server won't listen to anything else until then.
+    pub(crate) async fn end_copy_both(&mut self) -> Result<(), Error> {
+        if self.state != ConnectionState::CopyBoth {
+            return Ok(());
+        }
+
+        tracing::warn!("Ending an unfinished copy both stream");
+
+        if let Err(error) = self.finish_copy_both().await {
+            tracing::warn!(error=?error, "Abandoned copy both stream failed");
+        }
+
+        Ok(())
+    }
+
+    /// End a copy both stream from our side and skip the rest of the server's
+    /// data, up to the point where it is ready for the next query.
+    async fn finish_copy_both(&mut self) -> Result<(), Error> {
+        if self.state == ConnectionState::CopyBoth {
+            self.write_message(&client::CopyDone);
+            self.send_write_buffer().await?;
+            self.state = ConnectionState::AwaitingResponse;
+        }
+
+        let mut error = None;
+
+        loop {
+            let response = self.read_message().await?;
+
+            match response {
+                server::Message::ReadyForQuery => break,
+                server::Message::CopyData(_)
+                | server::Message::CopyDone
+                | server::Message::CommandComplete(_)
+                | server::Message::RowDescription(_)
+                | server::Message::DataRow(_) => {}
+                server::Message::Error(response) => error = Some(response),
+                otherwise => self.buffer_message(otherwise)?,
+            }
+        }
+
+        match error {
+            Some(error) => Err(Error::QueryError(error)),
+            None => Ok(()),
+        }
+    }
+}
+
+impl ReplicationStream<'_> {
+    /// The format the server announced for the copy.
+    pub fn format(&self) -> &CopyResponse {
+        &self.format
+    }
+
+    /// Set how often a status update is sent (every 10 seconds by default).
+    ///
+    /// It should be well below the server's `wal_sender_timeout`.
+    pub fn status_interval(mut self, interval: Duration) -> Self {
+        self.status_interval = interval;
+        self
+    }
+
+    /// Read the next chunk of WAL data, or `None` once the server ended the stream.
+    ///
+    /// Keepalives are answered along the way.
+    pub async fn next(&mut self) -> Result<Option<XLogData>, Error> {
+        while !self.done {
+            if self.last_status.elapsed() >= self.status_interval {
+                self.send_status_update(false).await?;
+            }
+
+            let response = self.connection.read_message().await?;
+
+            match response {
+                server::Message::CopyData(data) => {
+                    match ReplicationMessage::try_from(data.as_slice())? {
+                        ReplicationMessage::XLogData(xlog_data) => {
+                            let end = xlog_data
+                                .wal_start
+                                .0
+                                .checked_add(xlog_data.data.len() as u64)
+                                .ok_or_else(|| {
+                                    CodecError::from(DecodeError::UnexpectedValue(format!(
+                                        "WAL data at {} runs past the last log sequence number",
+                                        xlog_data.wal_start
+                                    )))
+                                })?;
+                            self.written = self.written.max(PgLsn(end));
+                            return Ok(Some(xlog_data));
+                        }
+                        ReplicationMessage::PrimaryKeepalive(keepalive) => {
+                            tracing::trace!(keepalive=?keepalive, "Received keepalive");
+                            if keepalive.reply_requested {
+                                self.send_status_update(false).await?;
+                            }
+                        }
+                    }
+                }
+                // The server ended the stream, e.g. at the end of a timeline
+                server::Message::CopyDone => self.done = true,
+                server::Message::Error(response) => {
+                    tracing::error!(error=?response, "Replication error");
+                    self.done = true;
+                    // The copy is over, the server skips to ready for query
+                    self.connection.finish_copy_both().await?;
+                    return Err(Error::QueryError(response));
+                }
+                otherwise => self.connection.buffer_message(otherwise)?,
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Report that the WAL data up to `lsn` (the last processed byte plus one)
+    /// was processed, so the server may discard it.
+    ///
+    /// It is sent with the next status update.
+    pub fn acknowledge(&mut self, lsn: PgLsn) {
+        self.flushed = self.flushed.max(lsn);
+    }
+
+    /// Send a status update with the received and acknowledged positions now,
+    /// optionally asking the server to reply with a keepalive.
+    pub async fn send_status_update(&mut self, reply_requested: bool) -> Result<(), Error> {
+        let update = StandbyStatusUpdate {
+            written: self.written,
+            flushed: self.flushed,
+            applied: self.flushed,
+            client_time: SystemTime::now(),
+            reply_requested,
+        };
+        tracing::trace!(update=?update, "Sending status update");
+
+        let payload = update.encode();
+        self.connection
+            .write_message(&client::CopyData::new(&payload));
+        self.connection.send_write_buffer().await?;
+        self.last_status = Instant::now();
+
+        Ok(())
+    }
+
+    /// End the stream and wait until the server is ready for the next query.
+    pub async fn finish(self) -> Result<(), Error> {
+        self.connection.finish_copy_both().await
+    }
+}
+
+impl TryFrom<&[u8]> for ReplicationMessage {
+    type Error = CodecError;
+
+    fn try_from(data: &[u8]) -> Result<Self, Self::Error> {
+        let mut reader = Reader::new(data);
+
+        let message = match reader.read_u8()? {
+            b'w' => ReplicationMessage::XLogData(XLogData {
+                wal_start: PgLsn(reader.read_i64()? as u64),
+                wal_end: PgLsn(reader.read_i64()? as u64),
+                server_time: from_postgres_time(reader.read_i64()?)?,
+                data: reader.read_remaining_bytes()?.to_vec(),
+            }),
+            b'k' => {
+                let keepalive = PrimaryKeepalive {
+                    wal_end: PgLsn(reader.read_i64()? as u64),
+                    server_time: from_postgres_time(reader.read_i64()?)?,
+                    reply_requested: reader.read_bool()?,
+                };
+                reader.finish()?;
+                ReplicationMessage::PrimaryKeepalive(keepalive)
+            }
+            otherwise => {
+                return Err(DecodeError::UnexpectedValue(format!(
+                    "unknown replication message type `{}`",
+                    otherwise.escape_ascii()
+                ))
+                .into())
+            }
+        };
+
+        Ok(message)
+    }
+}
+
+impl StandbyStatusUpdate {
+    /// Encode the update as the payload of a copy data message.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut writer = Writer::with_capacity(1 + 4 * 8 + 1);
+
+        writer.write_u8(b'r');
+        writer.write_i64(self.written.0 as i64);
+        writer.write_i64(self.flushed.0 as i64);
+        writer.write_i64(self.applied.0 as i64);
+        writer.write_i64(to_postgres_time(self.client_time));
+        writer.write_bool(self.reply_requested);
+
+        writer.finish()
+    }
+}
+
+/// Convert microseconds since 2000-01-01 to a point in time.
+fn from_postgres_time(micros: i64) -> Result<SystemTime, DecodeError> {
+    let epoch = UNIX_EPOCH + Duration::from_secs(POSTGRES_EPOCH_SECS);
+    let offset = Duration::from_micros(micros.unsigned_abs());
+
+    let time = if micros >= 0 {
+        epoch.checked_add(offset)
+    } else {
+        epoch.checked_sub(offset)
+    };
+
+    time.ok_or_else(|| DecodeError::UnexpectedValue(format!("timestamp `{}` out of range", micros)))
+}
+
+/// Convert a point in time to microseconds since 2000-01-01.
+fn to_postgres_time(time: SystemTime) -> i64 {
+    let epoch = UNIX_EPOCH + Duration::from_secs(POSTGRES_EPOCH_SECS);
+
+    match time.duration_since(epoch) {
+        Ok(since) => i64::try_from(since.as_micros()).unwrap_or(i64::MAX),
+        Err(error) => i64::try_from(error.duration().as_micros())
+            .map(|micros| -micros)
+            .unwrap_or(i64::MIN),
+    }
+}
@@ -0,0 +1,81 @@
+//! Logging of the raw messages sent and received, enabled by the
+//! `trace-protocol` feature.
+
+use std::fmt::Write;
+
+use crate::config::ProtocolTrace;
+
+/// The message types that carry passwords or SASL exchanges,
+/// sent by the client and the server respectively.
+const AUTHENTICATION_MESSAGE_TYPES: [u8; 2] = [b'p', b'R'];
+
+/// Log every message in the buffer with `tracing::trace!`.
+///
+/// The buffer may contain several messages, as sent by pipelines.
+pub(crate) fn trace_messages(direction: &str, mut buf: &[u8], protocol_trace: ProtocolTrace) {
+    while !buf.is_empty() {
+        // Message types are never zero, while the first byte of the length of the untyped
+        // startup messages always is, since they are much shorter than 16 MiB
+        let (message_type, length_offset) = match buf[0] {
+            0 => (None, 0),
+            message_type => (Some(message_type), 1),
+        };
+
+        let Some(length) = buf
+            .get(length_offset..length_offset + 4)
+            .map(|length| i32::from_be_bytes(length.try_into().unwrap()))
+        else {
+            tracing::trace!(direction, "Truncated message");
+            return;
+        };
+
+        // Don't read past the buffer if the length is garbage
+        let size = usize::try_from(length)
+            .map_or(buf.len(), |length| length + length_offset)
+            .min(buf.len());
+        let (message, rest) = buf.split_at(size);
+        buf = rest;
+
+        let redacted = protocol_trace == ProtocolTrace::Redacted
+            && message_type
+                .is_some_and(|message_type| AUTHENTICATION_MESSAGE_TYPES.contains(&message_type));
+        let message_type = message_type.map(char::from);
+
+        match protocol_trace {
+            ProtocolTrace::Headers => {
+                tracing::trace!(direction, ?message_type, length, "Protocol message");
+            }
+            _ if redacted => {
+                tracing::trace!(
+                    direction,
+                    ?message_type,
+                    length,
+                    payload = "<redacted>",
+                    "Protocol message"
+                );
+            }
+            _ => {
+                tracing::trace!(
+                    direction,
+                    ?message_type,
+                    length,
+                    payload = %hex_dump(message),
+                    "Protocol message"
+                );
+            }
+        }
+    }
+}
+
+/// Format bytes as space-separated pairs of hex digits.
+fn hex_dump(bytes: &[u8]) -> String {
+    let mut dump = String::with_capacity(bytes.len() * 3);
+    for (index, byte) in bytes.iter().enumerate() {
+        if index > 0 {
+            dump.push(' ');
+        }
+        // Writing to a string can't fail
+        let _ = write!(dump, "{:02x}", byte);
+    }
+    dump
+}
@@ -29,6 +29,7 @@ pub enum CodecError {
 #[allow(missing_docs)]
 #[apply(err_enum)]
 #[derive(Debug, Clone)]
+#[non_exhaustive]
 pub enum DecodeError {
     #[error("encountered unexpected EOF: buffer shorter than expected")]
     UnexpectedEof,
@@ -36,6 +37,12 @@ pub enum DecodeError {
     UnexpectedByte { expected: u8, got: u8 },
     #[error("encountered unexpected value: {0}")]
     UnexpectedValue(String),
+    #[error("length mismatch: expected {expected} bytes, got {actual}")]
+    LengthMismatch { expected: usize, actual: usize },
+    #[error("invalid message length {0}, it includes its own 4 bytes")]
+    InvalidMessageLength(i32),
+    #[error("missing the null terminator of a string")]
+    MissingNulTerminator,
     #[error("encountered non-UTF-8 string")]
     Utf8Error(FromUtf8Error),
 }
@@ -49,8 +56,17 @@ pub enum EncodeError {
     UnexpectedEof,
 }
 
+/// Quote a string as an SQL identifier, e.g. a table or statement name.
+///
+/// The result is wrapped in double quotes and embedded double quotes are doubled,
+/// so it can be safely interpolated into a query.
+pub fn quote_identifier(identifier: &str) -> String {
+    format!("\"{}\"", identifier.replace('"', "\"\""))
+}
+
 /// A helper struct to read values from a byte buffer
 /// in a safe manner.
+#[derive(Clone)]
 pub struct Reader<'a> {
     buffer: &'a [u8],
     position: usize,
@@ -83,9 +99,17 @@ impl<'a> Reader<'a> {
         }
     }
 
+    /// The number of bytes left to read.
+    ///
+    /// Comparing against this instead of adding to the position can't
+    /// overflow, however large a length read from the buffer is.
+    fn remaining_len(&self) -> usize {
+        self.buffer.len() - self.position
+    }
+
     /// Skip the next `n` bytes in the buffer.
     pub fn skip(&mut self, n: usize) -> Result<(), DecodeError> {
-        if self.position + n > self.buffer.len() {
+        if n > self.remaining_len() {
             return Err(DecodeError::UnexpectedEof);
         }
 
@@ -95,7 +119,7 @@ impl<'a> Reader<'a> {
 
     /// Returns an error if the buffer does not contain at least `n` more bytes.
     pub fn ensure_remaining(&self, n: usize) -> Result<(), DecodeError> {
-        if self.position + n > self.buffer.len() {
+        if n > self.remaining_len() {
             return Err(DecodeError::UnexpectedEof);
         }
 
@@ -104,8 +128,11 @@ impl<'a> Reader<'a> {
 
     /// Returns an error if the buffer does not contain exactly `n` more bytes.
     pub fn ensure_remaining_exact(&self, n: usize) -> Result<(), DecodeError> {
-        if self.position + n != self.buffer.len() {
-            return Err(DecodeError::UnexpectedEof);
+        if n != self.remaining_len() {
+            return Err(DecodeError::LengthMismatch {
+                expected: n,
+                actual: self.remaining_len(),
+            });
         }
 
         Ok(())
@@ -113,7 +140,7 @@ impl<'a> Reader<'a> {
 
     /// Peek at the next byte in the buffer without advancing the position.
     pub fn peek_u8(&self) -> Result<u8, DecodeError> {
-        if self.position + 1 > self.buffer.len() {
+        if self.remaining_len() < 1 {
             return Err(DecodeError::UnexpectedEof);
         }
 
@@ -122,13 +149,9 @@ impl<'a> Reader<'a> {
 
     /// Peek at the next `N` bytes in the buffer without advancing the position.
     pub fn peek_bytes<const N: usize>(&self) -> Result<&'a [u8; N], DecodeError> {
-        if self.position + N > self.buffer.len() {
-            return Err(DecodeError::UnexpectedEof);
-        }
-
-        Ok(self.buffer[self.position..self.position + N]
-            .try_into()
-            .expect("slice to be `N` bytes long"))
+        self.buffer[self.position..]
+            .first_chunk::<N>()
+            .ok_or(DecodeError::UnexpectedEof)
     }
 
     /// Returns an error if the next byte in the buffer is not equal to the
@@ -143,7 +166,7 @@ impl<'a> Reader<'a> {
 
     /// Read a single byte from the buffer.
     pub fn read_u8(&mut self) -> Result<u8, DecodeError> {
-        if self.position + 1 > self.buffer.len() {
+        if self.remaining_len() < 1 {
             return Err(DecodeError::UnexpectedEof);
         }
 
@@ -152,9 +175,21 @@ impl<'a> Reader<'a> {
         Ok(value)
     }
 
+    /// Read a boolean encoded as a single `0` or `1` byte from the buffer.
+    pub fn read_bool(&mut self) -> Result<bool, DecodeError> {
+        match self.read_u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            otherwise => Err(DecodeError::UnexpectedValue(format!(
+                "expected boolean byte `0` or `1`, got `{}`",
+                otherwise
+            ))),
+        }
+    }
+
     /// Read a slice of `n` bytes from the buffer.
     pub fn read_bytes(&mut self, n: usize) -> Result<&'a [u8], DecodeError> {
-        if self.position + n > self.buffer.len() {
+        if n > self.remaining_len() {
             return Err(DecodeError::UnexpectedEof);
         }
 
@@ -163,15 +198,18 @@ impl<'a> Reader<'a> {
         Ok(value)
     }
 
+    /// Return a reader over the next `n` bytes and advance past them.
+    ///
+    /// Nested decoders can use it to read a length-prefixed structure
+    /// without reading into the next one, and call [`Reader::finish`] to
+    /// check they consumed all of it.
+    pub fn sub_reader(&mut self, n: usize) -> Result<Reader<'a>, DecodeError> {
+        Ok(Reader::new(self.read_bytes(n)?))
+    }
+
     /// Read a slice of `N` bytes from the buffer.
     pub fn read_bytes_const<const N: usize>(&mut self) -> Result<&'a [u8; N], DecodeError> {
-        if self.position + N > self.buffer.len() {
-            return Err(DecodeError::UnexpectedEof);
-        }
-
-        let value = self.buffer[self.position..self.position + N]
-            .try_into()
-            .expect("slice to be `N` bytes long");
+        let value = self.peek_bytes::<N>()?;
         self.position += N;
         Ok(value)
     }
@@ -186,13 +224,10 @@ impl<'a> Reader<'a> {
     /// Read exactly `length` bytes from the buffer and throw an error
     /// if the buffer contains more or less bytes than that.
     pub fn read_remaining_bytes_exact(&mut self, length: usize) -> Result<&'a [u8], DecodeError> {
-        if self.position + length != self.buffer.len() {
-            return Err(DecodeError::UnexpectedEof);
-        }
+        self.ensure_remaining_exact(length)?;
         let value = &self.buffer[self.position..self.position + length];
 
         self.position += length;
-        self.ensure_remaining_exact(0)?;
 
         Ok(value)
     }
@@ -215,6 +250,12 @@ impl<'a> Reader<'a> {
         Ok(i32::from_be_bytes(*bytes))
     }
 
+    /// Read a 64-bit integer from the buffer in big-endian (network) order.
+    pub fn read_i64(&mut self) -> Result<i64, DecodeError> {
+        let bytes = self.read_bytes_const::<8>()?;
+        Ok(i64::from_be_bytes(*bytes))
+    }
+
     /// Returns an error if the next 32-bit integer in the buffer is not equal to
     /// the expected value or the buffer does not contain enough bytes to contain a
     /// 32-bit integer.
@@ -228,6 +269,19 @@ impl<'a> Reader<'a> {
 
     /// Read a C string (null-terminated) from the buffer.
     pub fn read_cstring(&mut self) -> Result<&'a str, DecodeError> {
+        let start = self.position;
+        let bytes = self.read_cstring_bytes()?;
+
+        // Don't consume the string if it's not valid
+        std::str::from_utf8(bytes).map_err(|_| {
+            self.position = start;
+            DecodeError::UnexpectedEof
+        })
+    }
+
+    /// Read the bytes of a C string (null-terminated) from the buffer,
+    /// without the terminator and without checking that they are valid UTF-8.
+    pub fn read_cstring_bytes(&mut self) -> Result<&'a [u8], DecodeError> {
         // Find the position of the null terminator
         let null_position = self.buffer[self.position..]
             .iter()
@@ -235,15 +289,13 @@ impl<'a> Reader<'a> {
             .map(|byte| byte + self.position); // This is the null terminator's absolute position
 
         let Some(null_position) = null_position else {
-            return Err(DecodeError::UnexpectedEof);
+            return Err(DecodeError::MissingNulTerminator);
         };
 
-        // Read the string from the buffer
-        let string = std::str::from_utf8(&self.buffer[self.position..null_position])
-            .map_err(|_| DecodeError::UnexpectedEof)?;
+        let bytes = &self.buffer[self.position..null_position];
 
-        self.position += string.len() + 1; // +1 for the null terminator
-        Ok(string)
+        self.position = null_position + 1; // +1 for the null terminator
+        Ok(bytes)
     }
 
     /// Backtrack the last `n` bytes in the buffer, but at most the length of
@@ -268,11 +320,24 @@ impl Writer {
         Self { buffer: Vec::new() }
     }
 
+    /// Create a new writer with room for `capacity` bytes.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            buffer: Vec::with_capacity(capacity),
+        }
+    }
+
+    /// Reserve room for at least `additional` more bytes.
+    ///
+    /// Encoders call this with the size of the message, so encoding
+    /// it doesn't reallocate repeatedly.
+    pub fn reserve(&mut self, additional: usize) {
+        self.buffer.reserve(additional);
+    }
+
     /// Skip the next `n` bytes in the buffer.
     pub fn skip(&mut self, n: usize) {
-        for _ in 0..n {
-            self.buffer.push(0);
-        }
+        self.buffer.resize(self.buffer.len() + n, 0);
     }
 
     /// Write a single byte to the buffer.
@@ -280,6 +345,11 @@ impl Writer {
         self.buffer.push(value);
     }
 
+    /// Write a boolean as a single `0` or `1` byte to the buffer.
+    pub fn write_bool(&mut self, value: bool) {
+        self.buffer.push(value as u8);
+    }
+
     /// Write a slice of bytes to the buffer.
     pub fn write_bytes(&mut self, bytes: &[u8]) {
         self.buffer.extend_from_slice(bytes);
@@ -295,15 +365,24 @@ impl Writer {
         self.buffer.extend_from_slice(&value.to_be_bytes());
     }
 
+    /// Write a 64-bit integer to the buffer in big-endian (network) order.
+    pub fn write_i64(&mut self, value: i64) {
+        self.buffer.extend_from_slice(&value.to_be_bytes());
+    }
+
     /// Write a 32-bit integer to the buffer in big-endian (network) order at a specific position.
     pub fn write_i32_at(&mut self, value: i32, position: usize) -> Result<(), EncodeError> {
         // Check that the buffer is long enough to contain the value
-        if self.buffer.len() < position + 4 {
+        let Some(bytes) = self
+            .buffer
+            .get_mut(position..)
+            .and_then(|rest| rest.first_chunk_mut::<4>())
+        else {
             return Err(EncodeError::UnexpectedEof);
-        }
+        };
 
         // Write the value to the buffer at the position
-        self.buffer[position..position + 4].copy_from_slice(&value.to_be_bytes());
+        *bytes = value.to_be_bytes();
 
         Ok(())
     }
@@ -336,6 +415,22 @@ impl Writer {
         self.buffer.len()
     }
 
+    /// Return the bytes written so far.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.buffer
+    }
+
+    /// Remove everything written so far, keeping the allocated memory for reuse.
+    pub fn clear(&mut self) {
+        self.buffer.clear();
+    }
+
+    /// Shrink the capacity of the underlying buffer to `capacity` bytes,
+    /// but not below its current length.
+    pub fn shrink_to(&mut self, capacity: usize) {
+        self.buffer.shrink_to(capacity);
+    }
+
     /// Finish the writer and return the underlying buffer.
     pub fn finish(self) -> Vec<u8> {
         self.buffer
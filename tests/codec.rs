@@ -0,0 +1,310 @@
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    cell::Cell,
+};
+
+use smol_pg::{
+    protocol::message::{
+        client::{Bind, CopyData, Encode, Parse, Query, Startup},
+        server::{AuthRequestType, Authentication, Message},
+    },
+    util::{CodecError, DecodeError, Reader, Writer},
+};
+
+/// An allocator counting the allocations of each thread, since tests run in parallel.
+struct CountingAllocator;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        // The counter may already be gone while the thread shuts down
+        let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+#[test]
+fn test_bool_round_trip() {
+    let mut writer = Writer::new();
+    writer.write_bool(true);
+    writer.write_bool(false);
+    let buf = writer.finish();
+    assert_eq!(buf, [1, 0]);
+
+    let mut reader = Reader::new(&buf);
+    assert!(reader.read_bool().unwrap());
+    assert!(!reader.read_bool().unwrap());
+    reader.finish().unwrap();
+}
+
+#[test]
+fn test_read_bool_rejects_other_bytes() {
+    let mut reader = Reader::new(&[2]);
+    assert!(matches!(
+        reader.read_bool(),
+        Err(DecodeError::UnexpectedValue(_))
+    ));
+}
+
+#[test]
+fn test_encoding_reuses_writer() {
+    let mut writer = Writer::with_capacity(64);
+
+    Query::new("SELECT 1".to_string()).encode(&mut writer);
+    assert_eq!(
+        writer.as_bytes(),
+        Vec::from(&Query::new("SELECT 1".to_string()))
+    );
+    let allocation = writer.as_bytes().as_ptr();
+
+    writer.clear();
+    Query::new("SELECT 2".to_string()).encode(&mut writer);
+    assert_eq!(writer.as_bytes(), b"Q\0\0\0\x0dSELECT 2\0");
+    assert_eq!(writer.as_bytes().as_ptr(), allocation);
+
+    // Messages appended to each other get their own lengths
+    Query::new("SELECT 3".to_string()).encode(&mut writer);
+    assert_eq!(&writer.as_bytes()[14..], b"Q\0\0\0\x0dSELECT 3\0");
+}
+
+#[test]
+fn test_decode_unknown_message() {
+    let message = Message::try_from(Reader::new(&[b'?', 0, 0, 0, 6, 1, 2])).unwrap();
+    let Message::Unknown { type_byte, body } = message else {
+        panic!("unexpected message {message:?}");
+    };
+    assert_eq!(type_byte, b'?');
+    assert_eq!(body, [1, 2]);
+}
+
+#[test]
+fn test_sub_reader() {
+    let mut reader = Reader::new(&[0, 0, 0, 1, 0, 2, 9]);
+
+    let mut sub_reader = reader.sub_reader(6).unwrap();
+    assert_eq!(sub_reader.read_i32().unwrap(), 1);
+    assert_eq!(sub_reader.read_i16().unwrap(), 2);
+    // The sub reader ends before the rest of the buffer
+    assert!(matches!(
+        sub_reader.read_u8(),
+        Err(DecodeError::UnexpectedEof)
+    ));
+    sub_reader.finish().unwrap();
+
+    assert_eq!(reader.read_u8().unwrap(), 9);
+    reader.finish().unwrap();
+
+    assert!(matches!(
+        Reader::new(&[1]).sub_reader(2),
+        Err(DecodeError::UnexpectedEof)
+    ));
+}
+
+#[test]
+fn test_encoding_reserves_message_size() {
+    let query = "SELECT 1 WHERE $1 = 'some longer text'".to_string();
+    let bind = Bind::new(
+        String::new(),
+        "statement".to_string(),
+        vec![Some(b"some longer text".to_vec()), None],
+        Vec::new(),
+    );
+    let copy_data = CopyData::new(&[7; 100]);
+    let parse = Parse::new("statement".to_string(), query.clone(), vec![25]);
+    let query = Query::new(query);
+
+    // Encoding into an empty buffer allocates exactly once, for the whole message
+    let encoders: [&dyn Fn() -> Vec<u8>; 4] = [
+        &|| Vec::from(&query),
+        &|| Vec::from(&parse),
+        &|| Vec::from(&bind),
+        &|| Vec::from(&copy_data),
+    ];
+    for encode in encoders {
+        let allocations = ALLOCATIONS.with(Cell::get);
+        let message = encode();
+        assert_eq!(ALLOCATIONS.with(Cell::get) - allocations, 1);
+        assert!(!message.is_empty());
+    }
+}
+
+#[test]
+fn test_decode_message_from_slice() {
+    let message = Message::try_from(&b"C\0\0\0\x0dSELECT 1\0"[..]).unwrap();
+    let Message::CommandComplete(complete) = message else {
+        panic!("unexpected message {message:?}");
+    };
+    assert_eq!(complete.tag(), "SELECT 1");
+    assert_eq!(complete.rows(), Some(1));
+
+    // The length has to match the slice exactly
+    assert!(matches!(
+        Message::try_from(&b"C\0\0\0\x0dSELECT 1\0\0"[..]),
+        Err(CodecError::Decode(DecodeError::LengthMismatch {
+            expected: 13,
+            actual: 14
+        }))
+    ));
+    assert!(Message::try_from(&b"C\0\0\0\x0dSELECT"[..]).is_err());
+    assert!(Message::try_from(&b"C\0\0"[..]).is_err());
+    assert!(matches!(
+        Message::try_from(&b"C\xff\xff\xff\xfe"[..]),
+        Err(CodecError::Decode(DecodeError::InvalidMessageLength(-2)))
+    ));
+}
+
+#[test]
+fn test_read_cstring_bytes() {
+    let mut reader = Reader::new(b"caf\xe9\0ok\0");
+
+    // Invalid UTF-8 isn't consumed as a string, but can be read as bytes
+    assert!(reader.read_cstring().is_err());
+    assert_eq!(reader.read_cstring_bytes().unwrap(), b"caf\xe9");
+    assert_eq!(reader.read_cstring().unwrap(), "ok");
+    reader.finish().unwrap();
+
+    let mut reader = Reader::new(b"no terminator");
+    assert!(matches!(
+        reader.read_cstring_bytes(),
+        Err(DecodeError::MissingNulTerminator)
+    ));
+    assert!(matches!(
+        reader.read_cstring(),
+        Err(DecodeError::MissingNulTerminator)
+    ));
+    // Nothing was consumed
+    assert_eq!(reader.read_bytes(2).unwrap(), b"no");
+
+    // An error field in another encoding doesn't lose the whole error
+    let message = Message::try_from(&b"E\0\0\0\x19SERROR\0C22021\0Mcaf\xe9\0\0"[..]).unwrap();
+    let Message::Error(error) = message else {
+        panic!("unexpected message {message:?}");
+    };
+    assert_eq!(error.code(), Some("22021"));
+    assert_eq!(error.message(), Some("caf\u{fffd}"));
+}
+
+#[test]
+fn test_reader_lengths_do_not_overflow() {
+    let mut reader = Reader::new(b"abc");
+    reader.skip(1).unwrap();
+
+    // Lengths read from a hostile message may be anything
+    assert!(matches!(
+        reader.skip(usize::MAX),
+        Err(DecodeError::UnexpectedEof)
+    ));
+    assert!(reader.read_bytes(usize::MAX).is_err());
+    assert!(reader.sub_reader(usize::MAX).is_err());
+    assert!(reader.ensure_remaining(usize::MAX).is_err());
+    assert!(reader.read_remaining_bytes_exact(usize::MAX).is_err());
+    assert!(reader.read_i64().is_err());
+    assert_eq!(reader.read_bytes(2).unwrap(), b"bc");
+
+    let mut writer = Writer::new();
+    writer.write_i32(0);
+    assert!(writer.write_i32_at(1, usize::MAX).is_err());
+    assert!(writer.write_i32_at(1, 1).is_err());
+}
+
+#[test]
+fn test_decode_garbage_does_not_panic() {
+    // A fixed xorshift sequence, so failures are reproducible,
+    // see the `decode_message` fuzz target for a thorough search
+    let mut state = 0x2545_f491_4f6c_dd1d_u64;
+    let mut next = move || {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        state
+    };
+
+    for _ in 0..100_000 {
+        let message_type = b"RESKCNTDtHGdZ?"[next() as usize % 14];
+        // Favour bytes that make counts and lengths zero, negative or small
+        let body = (0..next() % 48)
+            .map(|_| match next() % 4 {
+                0 => 0,
+                1 => 0xff,
+                2 => next() as u8 % 4,
+                _ => next() as u8,
+            })
+            .collect::<Vec<_>>();
+
+        let mut frame = vec![message_type];
+        frame.extend_from_slice(&(body.len() as i32 + 4).to_be_bytes());
+        frame.extend_from_slice(&body);
+
+        let _ = Message::try_from(&frame[..]);
+        let _ = Message::try_from(Reader::new(&frame));
+        let _ = Message::try_from(&frame[..frame.len() / 2]);
+    }
+}
+
+#[test]
+fn test_length_mismatch() {
+    let mut reader = Reader::new(&[1, 2, 3]);
+    assert!(matches!(
+        reader.ensure_remaining_exact(2),
+        Err(DecodeError::LengthMismatch {
+            expected: 2,
+            actual: 3
+        })
+    ));
+    let error = reader.read_remaining_bytes_exact(4).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "length mismatch: expected 4 bytes, got 3"
+    );
+    // Nothing was consumed by the failed reads
+    assert_eq!(reader.read_remaining_bytes_exact(3).unwrap(), [1, 2, 3]);
+}
+
+#[test]
+fn test_startup_debug_redacts_password() {
+    let startup = Startup::new("alice".into(), Some("shop".into()), None)
+        .parameter("password".into(), "hunter2".into());
+
+    let debug = format!("{startup:?}");
+    assert!(!debug.contains("hunter2"));
+    assert!(debug.contains(r#""password": "<redacted>""#));
+    assert!(debug.contains(r#""database": "shop""#));
+    assert!(debug.contains(r#"user: "alice""#));
+}
+
+#[test]
+fn test_auth_request_types() {
+    assert_eq!(
+        AuthRequestType::try_from(10).unwrap(),
+        AuthRequestType::Sasl
+    );
+    assert_eq!(
+        AuthRequestType::try_from(5).unwrap(),
+        AuthRequestType::Md5Password
+    );
+    assert!(matches!(
+        AuthRequestType::try_from(42),
+        Err(DecodeError::UnexpectedValue(_))
+    ));
+
+    // Known and unknown types without support still decode
+    for request_type in [5, 42] {
+        let mut frame = vec![b'R', 0, 0, 0, 8];
+        frame.extend_from_slice(&i32::to_be_bytes(request_type));
+        let message = Message::try_from(&frame[..]).unwrap();
+        assert!(matches!(
+            message,
+            Message::Authentication(Authentication::Unsupported(t)) if t == request_type
+        ));
+    }
+}
@@ -0,0 +1,339 @@
+use std::{sync::Mutex, time::Duration};
+
+use smol_pg::{
+    config::{AddressFamily, ConnectionConfig, TargetSessionAttrs},
+    connection::Connection,
+    protocol::message::server::{FormatCode, Severity},
+    Error,
+};
+use socket2::SockRef;
+
+/// The variables read by `ConnectionConfig::from_env`.
+const VARIABLES: [&str; 6] = [
+    "PGHOST",
+    "PGPORT",
+    "PGUSER",
+    "PGPASSWORD",
+    "PGDATABASE",
+    "PGAPPNAME",
+];
+
+/// Tests changing the environment must not run at the same time.
+static ENVIRONMENT: Mutex<()> = Mutex::new(());
+
+/// Run `f` with the given environment variables set and all others of
+/// [`VARIABLES`] unset, restoring the environment afterwards.
+fn with_env<T>(variables: &[(&str, &str)], f: impl FnOnce() -> T) -> T {
+    let _guard = ENVIRONMENT
+        .lock()
+        .unwrap_or_else(|error| error.into_inner());
+
+    let previous = VARIABLES.map(|name| (name, std::env::var(name).ok()));
+    for name in VARIABLES {
+        std::env::remove_var(name);
+    }
+    for (name, value) in variables {
+        std::env::set_var(name, value);
+    }
+
+    let result = f();
+
+    for (name, value) in previous {
+        match value {
+            Some(value) => std::env::set_var(name, value),
+            None => std::env::remove_var(name),
+        }
+    }
+
+    result
+}
+
+#[test]
+fn test_from_env_defaults() {
+    let config = with_env(&[], || ConnectionConfig::from_env().unwrap());
+
+    assert_eq!(
+        format!("{config:?}"),
+        format!("{:?}", ConnectionConfig::new())
+    );
+}
+
+#[test]
+fn test_from_env() {
+    let config = with_env(
+        &[
+            ("PGHOST", "127.0.0.1"),
+            ("PGPORT", "5432"),
+            ("PGUSER", "postgres"),
+            ("PGDATABASE", "template1"),
+            ("PGAPPNAME", "from-env"),
+        ],
+        || ConnectionConfig::from_env().unwrap(),
+    );
+
+    smol::block_on(async {
+        let mut connection = Connection::connect(config.clone()).await.unwrap();
+        let rows = connection
+            .query("SELECT current_database() AS database, current_setting('application_name') AS name")
+            .await
+            .unwrap();
+        assert_eq!(
+            rows[0].get_and_parse::<&str>("database").unwrap(),
+            "template1"
+        );
+        assert_eq!(rows[0].get_and_parse::<&str>("name").unwrap(), "from-env");
+
+        // Explicit settings take precedence
+        let config = config.database("postgres").application_name("explicit");
+        let mut connection = Connection::connect(config).await.unwrap();
+        let rows = connection
+            .query("SELECT current_database() AS database, current_setting('application_name') AS name")
+            .await
+            .unwrap();
+        assert_eq!(
+            rows[0].get_and_parse::<&str>("database").unwrap(),
+            "postgres"
+        );
+        assert_eq!(rows[0].get_and_parse::<&str>("name").unwrap(), "explicit");
+    });
+
+    let error = with_env(&[("PGPORT", "not a port")], || {
+        ConnectionConfig::from_env().unwrap_err()
+    });
+    assert_eq!(
+        error.to_string(),
+        "invalid value `not a port` for environment variable `PGPORT`"
+    );
+}
+
+#[test]
+fn test_keepalives() {
+    smol::block_on(async {
+        let config = ConnectionConfig::new().host("127.0.0.1");
+
+        let stream = Connection::connect_tcp(&config.clone().keepalives(false))
+            .await
+            .unwrap();
+        assert!(!SockRef::from(&stream).keepalive().unwrap());
+
+        let config = config
+            .keepalives_idle(Duration::from_secs(60))
+            .keepalives_interval(Duration::from_secs(10));
+        let stream = Connection::connect_tcp(&config).await.unwrap();
+        let socket = SockRef::from(&stream);
+        assert!(socket.keepalive().unwrap());
+        assert_eq!(
+            socket.tcp_keepalive_time().unwrap(),
+            Duration::from_secs(60)
+        );
+        assert_eq!(
+            socket.tcp_keepalive_interval().unwrap(),
+            Duration::from_secs(10)
+        );
+
+        let mut connection = Connection::connect(config).await.unwrap();
+        assert_eq!(connection.query("SELECT 1").await.unwrap().len(), 1);
+    });
+}
+
+#[test]
+fn test_address_family() {
+    smol::block_on(async {
+        let config = ConnectionConfig::new().host("127.0.0.1");
+
+        let mut connection = Connection::connect(config.clone().address_family(AddressFamily::V4))
+            .await
+            .unwrap();
+        assert_eq!(connection.query("SELECT 1").await.unwrap().len(), 1);
+
+        // An IPv4 address has no IPv6 address to connect to
+        let result = Connection::connect(config.address_family(AddressFamily::V6)).await;
+        assert!(matches!(result, Err(Error::NetworkError(_))));
+    });
+}
+
+#[test]
+fn test_target_session_attrs() {
+    smol::block_on(async {
+        // Nothing listens on the first port, the local server is a primary
+        let hosts = vec![
+            ("127.0.0.1".to_string(), 1),
+            ("127.0.0.1".to_string(), 5432),
+        ];
+        let config = ConnectionConfig::new().hosts(hosts);
+
+        let mut connection = Connection::connect(
+            config
+                .clone()
+                .target_session_attrs(TargetSessionAttrs::ReadWrite),
+        )
+        .await
+        .unwrap();
+        assert_eq!(connection.query("SELECT 1").await.unwrap().len(), 1);
+
+        let result =
+            Connection::connect(config.target_session_attrs(TargetSessionAttrs::ReadOnly)).await;
+        assert!(matches!(
+            result,
+            Err(Error::NoMatchingServer(TargetSessionAttrs::ReadOnly))
+        ));
+    });
+}
+
+#[test]
+fn test_default_result_format() {
+    smol::block_on(async {
+        let config = ConnectionConfig::new()
+            .host("127.0.0.1")
+            .default_result_format(FormatCode::Binary);
+        let mut connection = Connection::connect(config).await.unwrap();
+
+        let mut pipeline = connection.pipeline();
+        pipeline.push(
+            "SELECT 42::int4 AS n, 'pg_class'::regclass::oid AS oid, 'hi'::text AS text",
+            &[],
+        );
+        let results = pipeline.run().await.unwrap();
+        let row = &results[0][0];
+
+        // The raw value is the big-endian integer, not its text
+        assert_eq!(row.get_and_parse::<[u8; 4]>("n").unwrap(), [0, 0, 0, 42]);
+        assert_eq!(row.get_and_parse::<i32>("n").unwrap(), 42);
+        assert_eq!(row.get_and_parse::<u32>("oid").unwrap(), 1259);
+        assert_eq!(row.get_and_parse::<&str>("text").unwrap(), "hi");
+    });
+}
+
+#[test]
+fn test_max_rows_buffered() {
+    smol::block_on(async {
+        let config = ConnectionConfig::new()
+            .host("127.0.0.1")
+            .max_rows_buffered(3);
+        let mut connection = Connection::connect(config).await.unwrap();
+
+        let rows = connection
+            .query("SELECT generate_series(1, 3)")
+            .await
+            .unwrap();
+        assert_eq!(rows.len(), 3);
+
+        let result = connection.query("SELECT generate_series(1, 10)").await;
+        assert!(matches!(
+            result,
+            Err(Error::TooManyRows {
+                limit: 3,
+                received: 10
+            })
+        ));
+
+        // The rest of the result was read, the connection is still usable
+        assert_eq!(connection.query_scalar::<i32>("SELECT 1").await.unwrap(), 1);
+    });
+}
+
+#[test]
+fn test_with_stream() {
+    smol::block_on(async {
+        // E.g. a stream through a tunnel the caller set up
+        let stream = async_net::TcpStream::connect("127.0.0.1:5432")
+            .await
+            .unwrap();
+
+        let config = ConnectionConfig::new().application_name("with_stream");
+        let mut connection = Connection::with_stream(stream, config).await.unwrap();
+
+        let rows = connection
+            .query("SELECT current_setting('application_name') AS name")
+            .await
+            .unwrap();
+        assert_eq!(
+            rows[0].get_and_parse::<&str>("name").unwrap(),
+            "with_stream"
+        );
+    });
+}
+
+#[test]
+fn test_max_notices() {
+    smol::block_on(async {
+        let config = ConnectionConfig::new().host("127.0.0.1").max_notices(10);
+        let mut connection = Connection::connect(config).await.unwrap();
+
+        // Every query raises a notice, but none are taken in between
+        for i in 0..100 {
+            let query = format!("DO $$ BEGIN RAISE NOTICE 'notice {i}'; END $$");
+            connection.batch_execute(&query).await.unwrap();
+        }
+
+        let messages = connection
+            .take_notices()
+            .iter()
+            .map(|notice| notice.message().unwrap().to_string())
+            .collect::<Vec<_>>();
+        let expected = (90..100).map(|i| format!("notice {i}")).collect::<Vec<_>>();
+        assert_eq!(messages, expected);
+    });
+}
+
+#[test]
+fn test_min_notice_severity() {
+    smol::block_on(async {
+        let script = "SET client_min_messages = debug1;
+                      DO $$ BEGIN
+                          RAISE DEBUG 'debug';
+                          RAISE INFO 'info';
+                          RAISE NOTICE 'notice';
+                          RAISE WARNING 'warning';
+                      END $$;";
+
+        let mut connection = Connection::connect(ConnectionConfig::new().host("127.0.0.1"))
+            .await
+            .unwrap();
+        connection.batch_execute(script).await.unwrap();
+        let messages = connection
+            .take_notices()
+            .iter()
+            .map(|notice| notice.message().unwrap().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(messages, ["debug", "info", "notice", "warning"]);
+        assert!(connection.take_notices().is_empty());
+
+        let config = ConnectionConfig::new()
+            .host("127.0.0.1")
+            .min_notice_severity(Severity::Notice);
+        let mut connection = Connection::connect(config).await.unwrap();
+        connection.batch_execute(script).await.unwrap();
+        let severities = connection
+            .take_notices()
+            .iter()
+            .map(|notice| notice.severity_level())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            severities,
+            [Some(Severity::Notice), Some(Severity::Warning)]
+        );
+    });
+}
+
+#[test]
+fn test_debug_redacts_password() {
+    let config = ConnectionConfig::new().user("app").password("hunter2");
+
+    // Clones share the settings, e.g. for a pool
+    let clone = config.clone().database("other");
+    for config in [&config, &clone] {
+        let debug = format!("{config:?}");
+        assert!(debug.contains(r#"user: "app""#), "{debug}");
+        assert!(debug.contains(r#"password: Some("***")"#), "{debug}");
+        assert!(!debug.contains("hunter2"), "{debug}");
+    }
+
+    let config = with_env(&[("PGPASSWORD", "from-env")], || {
+        ConnectionConfig::from_env().unwrap()
+    });
+    assert!(!format!("{config:?}").contains("from-env"));
+
+    let debug = format!("{:?}", ConnectionConfig::new());
+    assert!(debug.contains("password: None"), "{debug}");
+}
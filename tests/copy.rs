@@ -0,0 +1,397 @@
+use std::{
+    io,
+    net::Ipv4Addr,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use async_net::TcpStream;
+use futures_lite::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use smol_pg::{
+    config::ConnectionConfig,
+    connection::{Connection, ConnectionState},
+    Error,
+};
+
+async fn connect() -> Connection {
+    Connection::create(std::net::IpAddr::V4(Ipv4Addr::LOCALHOST), None)
+        .await
+        .unwrap()
+}
+
+#[test]
+fn test_copy_out_csv_with_header() {
+    smol::block_on(async {
+        let mut connection = connect().await;
+
+        let mut reader = connection
+            .copy_out(
+                r#"COPY (SELECT * FROM (VALUES (1, 'plain'), (2, 'a, "quoted" one')) AS t(id, label))
+                TO STDOUT WITH (FORMAT csv, HEADER)"#,
+            )
+            .await
+            .unwrap()
+            .with_csv_header()
+            .await
+            .unwrap();
+
+        assert_eq!(reader.header(), ["id", "label"]);
+
+        let mut rows = Vec::new();
+        while let Some(row) = reader.next_row().await.unwrap() {
+            rows.push(row);
+        }
+        assert_eq!(rows, [["1", "plain"], ["2", r#"a, "quoted" one"#]]);
+
+        // The connection is usable again after the copy is drained
+        assert_eq!(connection.query("SELECT 1").await.unwrap().len(), 1);
+    });
+}
+
+#[test]
+fn test_query_after_dropping_copy_out_reader() {
+    smol::block_on(async {
+        let mut connection = connect().await;
+
+        let mut reader = connection
+            .copy_out("COPY (SELECT generate_series(1, 1000)) TO STDOUT")
+            .await
+            .unwrap();
+        assert_eq!(reader.read().await.unwrap().unwrap(), b"1\n");
+        drop(reader);
+
+        // The rest of the copy is skipped
+        let rows = connection.query("SELECT 2 AS n").await.unwrap();
+        assert_eq!(rows[0].get_and_parse::<i32>("n").unwrap(), 2);
+    });
+}
+
+#[test]
+fn test_copy_in() {
+    smol::block_on(async {
+        let mut connection = connect().await;
+
+        connection
+            .query_raw("CREATE TEMPORARY TABLE copy_in (id int, label text)")
+            .await
+            .unwrap();
+
+        let mut writer = connection
+            .copy_in("COPY copy_in FROM STDIN WITH (FORMAT csv)")
+            .await
+            .unwrap();
+        writer.write(b"1,one\n").await.unwrap();
+        writer.write(b"2,\"two, quoted\"\n").await.unwrap();
+        assert_eq!(writer.finish().await.unwrap(), 2);
+
+        let rows = connection
+            .query("SELECT label FROM copy_in ORDER BY id")
+            .await
+            .unwrap();
+        assert_eq!(
+            rows[1].get_and_parse::<&str>("label").unwrap(),
+            "two, quoted"
+        );
+    });
+}
+
+#[test]
+fn test_copy_in_fail() {
+    smol::block_on(async {
+        let mut connection = connect().await;
+
+        connection
+            .query_raw("CREATE TEMPORARY TABLE copy_fail (id int)")
+            .await
+            .unwrap();
+
+        let mut writer = connection
+            .copy_in("COPY copy_fail FROM STDIN")
+            .await
+            .unwrap();
+        writer.write(b"1\n").await.unwrap();
+        writer.fail("the input was invalid").await.unwrap();
+
+        // Nothing was copied and the connection is usable
+        let rows = connection
+            .query("SELECT count(*)::int4 AS n FROM copy_fail")
+            .await
+            .unwrap();
+        assert_eq!(rows[0].get_and_parse::<i32>("n").unwrap(), 0);
+
+        // A dropped writer aborts the copy as well
+        let mut writer = connection
+            .copy_in("COPY copy_fail FROM STDIN")
+            .await
+            .unwrap();
+        writer.write(b"2\n").await.unwrap();
+        drop(writer);
+        assert_eq!(connection.state(), ConnectionState::CopyIn);
+
+        let rows = connection
+            .query("SELECT count(*)::int4 AS n FROM copy_fail")
+            .await
+            .unwrap();
+        assert_eq!(rows[0].get_and_parse::<i32>("n").unwrap(), 0);
+        assert_eq!(connection.state(), ConnectionState::ReadyIdle);
+    });
+}
+
+#[test]
+fn test_binary_copy_round_trip() {
+    smol::block_on(async {
+        let mut connection = connect().await;
+
+        connection
+            .query_raw("CREATE TEMPORARY TABLE copy_binary (id int4, label text)")
+            .await
+            .unwrap();
+
+        // The signature, flags, header extension, two rows and the trailer
+        let mut data = b"PGCOPY\n\xff\r\n\0".to_vec();
+        data.extend_from_slice(&[0; 8]);
+        for (id, label) in [(1i32, Some("one")), (2, None)] {
+            data.extend_from_slice(&2i16.to_be_bytes());
+            data.extend_from_slice(&4i32.to_be_bytes());
+            data.extend_from_slice(&id.to_be_bytes());
+            match label {
+                Some(label) => {
+                    data.extend_from_slice(&(label.len() as i32).to_be_bytes());
+                    data.extend_from_slice(label.as_bytes());
+                }
+                None => data.extend_from_slice(&(-1i32).to_be_bytes()),
+            }
+        }
+        data.extend_from_slice(&(-1i16).to_be_bytes());
+
+        let mut writer = connection
+            .copy_in("COPY copy_binary FROM STDIN WITH (FORMAT binary)")
+            .await
+            .unwrap();
+        // Split the data so rows don't line up with the messages
+        for chunk in data.chunks(7) {
+            writer.write(chunk).await.unwrap();
+        }
+        assert_eq!(writer.finish().await.unwrap(), 2);
+
+        let mut reader = connection
+            .copy_out("COPY copy_binary TO STDOUT WITH (FORMAT binary)")
+            .await
+            .unwrap()
+            .with_binary_header()
+            .await
+            .unwrap();
+
+        let mut rows = Vec::new();
+        while let Some(row) = reader.next_row().await.unwrap() {
+            rows.push(row);
+        }
+        assert_eq!(
+            rows,
+            [
+                vec![Some(1i32.to_be_bytes().to_vec()), Some(b"one".to_vec())],
+                vec![Some(2i32.to_be_bytes().to_vec()), None],
+            ]
+        );
+
+        // The copy is complete and the connection is usable
+        assert_eq!(connection.query("SELECT 1").await.unwrap().len(), 1);
+    });
+}
+
+/// A reader that fails after returning some data.
+struct FailingReader {
+    /// The data returned before failing.
+    data: Option<&'static [u8]>,
+}
+
+impl AsyncRead for FailingReader {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        match self.data.take() {
+            Some(data) => {
+                buf[..data.len()].copy_from_slice(data);
+                Poll::Ready(Ok(data.len()))
+            }
+            None => Poll::Ready(Err(io::Error::other("disk on fire"))),
+        }
+    }
+}
+
+#[test]
+fn test_copy_in_from_reader() {
+    smol::block_on(async {
+        let mut connection = connect().await;
+
+        connection
+            .query_raw("CREATE TEMPORARY TABLE copy_reader (id int, label text)")
+            .await
+            .unwrap();
+
+        // More data than fits in a single message
+        let data = (0..10_000)
+            .map(|id| format!("{id},label number {id}\n"))
+            .collect::<String>();
+        assert!(data.len() > 64 * 1024);
+
+        let rows = connection
+            .copy_in_from_reader(
+                "COPY copy_reader FROM STDIN WITH (FORMAT csv)",
+                futures_lite::io::Cursor::new(data.into_bytes()),
+            )
+            .await
+            .unwrap();
+        assert_eq!(rows, 10_000);
+
+        let result = connection
+            .copy_in_from_reader(
+                "COPY copy_reader FROM STDIN WITH (FORMAT csv)",
+                FailingReader {
+                    data: Some(b"10000,partial\n"),
+                },
+            )
+            .await;
+        assert!(matches!(result, Err(Error::CopySourceError(_))));
+
+        // Nothing of the failed copy arrived and the connection is usable
+        let rows = connection
+            .query("SELECT count(*)::int4 AS n FROM copy_reader")
+            .await
+            .unwrap();
+        assert_eq!(rows[0].get_and_parse::<i32>("n").unwrap(), 10_000);
+    });
+}
+
+#[test]
+fn test_copy_with_async_read_and_write() {
+    smol::block_on(async {
+        let mut connection = connect().await;
+
+        connection
+            .query_raw("CREATE TEMPORARY TABLE copy_io (id int, label text)")
+            .await
+            .unwrap();
+
+        // More data than fits in the write buffer, in a single write
+        let data = (0..5_000)
+            .map(|id| format!("{id}\tlabel {id}\n"))
+            .collect::<String>();
+
+        let mut writer = connection.copy_in("COPY copy_io FROM STDIN").await.unwrap();
+        let copied = futures_lite::io::copy(data.as_bytes(), &mut writer)
+            .await
+            .unwrap();
+        assert_eq!(copied, data.len() as u64);
+        writer.close().await.unwrap();
+        // Closing ended the copy already
+        assert!(writer.write_all(b"5000\tlate\n").await.is_err());
+        assert_eq!(writer.finish().await.unwrap(), 5_000);
+
+        let mut reader = connection.copy_out("COPY copy_io TO STDOUT").await.unwrap();
+        // The first row through `read`, the rest as a byte stream
+        let first = reader.read().await.unwrap().unwrap();
+        assert_eq!(first, b"0\tlabel 0\n");
+        let mut rest = Vec::new();
+        reader.read_to_end(&mut rest).await.unwrap();
+        assert_eq!([first, rest].concat(), data.as_bytes());
+
+        // Small reads split the chunks
+        let mut reader = connection
+            .copy_out("COPY (SELECT 'abcdef') TO STDOUT")
+            .await
+            .unwrap();
+        let mut part = [0; 4];
+        reader.read_exact(&mut part).await.unwrap();
+        assert_eq!(&part, b"abcd");
+        assert_eq!(reader.read().await.unwrap().unwrap(), b"ef\n");
+        assert_eq!(reader.read().await.unwrap(), None);
+
+        assert_eq!(connection.state(), ConnectionState::ReadyIdle);
+    });
+}
+
+/// A stream to the server whose every other write isn't ready right away,
+/// like a socket whose send buffer is full.
+struct StallingStream {
+    /// The stream to the server.
+    inner: TcpStream,
+    /// Whether the next write stalls.
+    stall: bool,
+}
+
+impl AsyncRead for StallingStream {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for StallingStream {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        self.stall = !self.stall;
+        if self.stall {
+            cx.waker().wake_by_ref();
+            return Poll::Pending;
+        }
+
+        Pin::new(&mut self.inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_close(cx)
+    }
+}
+
+#[test]
+fn test_dropping_async_writer_with_full_buffer() {
+    smol::block_on(async {
+        let stream = StallingStream {
+            inner: TcpStream::connect("127.0.0.1:5432").await.unwrap(),
+            stall: false,
+        };
+        let config = ConnectionConfig::new().write_buffer_capacity(1024);
+        let mut connection = Connection::with_stream(stream, config).await.unwrap();
+
+        connection
+            .query_raw("CREATE TEMPORARY TABLE copy_stall (label text)")
+            .await
+            .unwrap();
+
+        // Every write after the first overflows the buffer and sends it
+        let line = format!("{}\n", "x".repeat(599));
+        let mut writer = connection
+            .copy_in("COPY copy_stall FROM STDIN")
+            .await
+            .unwrap();
+        for _ in 0..2 {
+            AsyncWriteExt::write_all(&mut writer, line.as_bytes())
+                .await
+                .unwrap();
+        }
+        // Nothing is half sent, so the copy is aborted cleanly
+        drop(writer);
+
+        assert_eq!(
+            connection
+                .query_scalar::<i32>("SELECT count(*)::int4 FROM copy_stall")
+                .await
+                .unwrap(),
+            0
+        );
+        assert!(!connection.is_closed());
+    });
+}
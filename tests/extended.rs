@@ -0,0 +1,167 @@
+use std::{
+    io,
+    net::Ipv4Addr,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+};
+
+use futures_lite::{AsyncRead, AsyncWrite};
+use smol::net::TcpStream;
+
+use smol_pg::{
+    config::ConnectionConfig,
+    connection::Connection,
+    protocol::message::{
+        client::{Bind, Execute, Parse},
+        server::{FormatCode, Message},
+    },
+};
+
+#[test]
+fn test_manual_extended_protocol() {
+    smol::block_on(async {
+        let mut connection = Connection::create(std::net::IpAddr::V4(Ipv4Addr::LOCALHOST), None)
+            .await
+            .unwrap();
+
+        let parse = Parse::new(String::new(), "SELECT $1::int4 * 2".to_string(), Vec::new());
+        connection.send_client_message(&parse).await.unwrap();
+        let bind = Bind::new(
+            String::new(),
+            String::new(),
+            vec![Some(b"21".to_vec())],
+            Vec::new(),
+        );
+        connection.send_client_message(&bind).await.unwrap();
+        let execute = Execute::new(String::new(), 0);
+        connection.send_client_message(&execute).await.unwrap();
+
+        // The server answers a flush without waiting for the end of the batch
+        connection.flush().await.unwrap();
+        let mut messages = Vec::new();
+        for _ in 0..4 {
+            messages.push(connection.read_server_message().await.unwrap());
+        }
+        let [Message::ParseComplete, Message::BindComplete, Message::DataRow(_), Message::CommandComplete(complete)] =
+            messages.as_slice()
+        else {
+            panic!("unexpected messages {messages:?}");
+        };
+        assert_eq!(complete.tag(), "SELECT 1");
+
+        connection.sync().await.unwrap();
+        assert!(connection
+            .read_server_message()
+            .await
+            .unwrap()
+            .is_ready_for_query());
+
+        // The connection is in sync for the next query
+        assert_eq!(connection.query("SELECT 1").await.unwrap().len(), 1);
+    });
+}
+
+#[test]
+fn test_query_binary() {
+    smol::block_on(async {
+        let mut connection = Connection::create(std::net::IpAddr::V4(Ipv4Addr::LOCALHOST), None)
+            .await
+            .unwrap();
+
+        let rows = connection
+            .query_binary("SELECT $1::int4 * 2 AS n, 'text' AS label", &[&21])
+            .await
+            .unwrap();
+        for column in rows[0].columns() {
+            assert_eq!(column.format_code, FormatCode::Binary);
+        }
+        assert_eq!(rows[0]["n"].as_bytes(), Some(&42i32.to_be_bytes()[..]));
+        assert_eq!(rows[0].get_and_parse::<i32>("n").unwrap(), 42);
+        assert_eq!(rows[0].get_and_parse::<&str>("label").unwrap(), "text");
+
+        // The simple query protocol only returns text
+        let rows = connection.query("SELECT 42 AS n").await.unwrap();
+        assert_eq!(rows[0].columns()[0].format_code, FormatCode::Text);
+    });
+}
+
+/// A stream that counts the writes to it.
+struct CountingStream {
+    /// The stream to the server.
+    inner: TcpStream,
+    /// The number of writes so far.
+    writes: Arc<AtomicUsize>,
+}
+
+impl AsyncRead for CountingStream {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for CountingStream {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        self.writes.fetch_add(1, Ordering::Relaxed);
+        Pin::new(&mut self.inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_close(cx)
+    }
+}
+
+#[test]
+fn test_messages_are_sent_in_one_write() {
+    smol::block_on(async {
+        let writes = Arc::new(AtomicUsize::new(0));
+        let stream = CountingStream {
+            inner: TcpStream::connect("127.0.0.1:5432").await.unwrap(),
+            writes: writes.clone(),
+        };
+        let mut connection = Connection::with_stream(stream, ConnectionConfig::new())
+            .await
+            .unwrap();
+
+        // Parse, bind, describe, execute and sync
+        writes.store(0, Ordering::Relaxed);
+        let rows = connection
+            .query_typed("SELECT $1::int4 AS n", &[(&1, 23)])
+            .await
+            .unwrap();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(writes.load(Ordering::Relaxed), 1);
+
+        writes.store(0, Ordering::Relaxed);
+        let parse = Parse::new(String::new(), "SELECT 1".to_string(), Vec::new());
+        connection.queue_client_message(&parse);
+        let bind = Bind::new(String::new(), String::new(), Vec::new(), Vec::new());
+        connection.queue_client_message(&bind);
+        connection.queue_client_message(&Execute::new(String::new(), 0));
+        connection.sync().await.unwrap();
+        assert_eq!(writes.load(Ordering::Relaxed), 1);
+
+        while !connection
+            .read_server_message()
+            .await
+            .unwrap()
+            .is_ready_for_query()
+        {}
+        assert_eq!(connection.query("SELECT 1").await.unwrap().len(), 1);
+    });
+}
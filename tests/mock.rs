@@ -0,0 +1,551 @@
+#![cfg(feature = "test-util")]
+
+use std::time::{Duration, UNIX_EPOCH};
+
+use smol_pg::{
+    config::ConnectionConfig,
+    connection::{Connection, ConnectionState, FieldNotFound, ProtocolError, SchemaMismatch},
+    mock::MockStream,
+    protocol::{
+        message::{client::Query, server::Message},
+        types::PgLsn,
+    },
+    util::{CodecError, DecodeError},
+    Error,
+};
+
+/// Encode the fields of an error response.
+fn error_fields(code: &str, message: &str) -> Vec<u8> {
+    let mut body = Vec::new();
+    for (field, value) in [(b'S', "ERROR"), (b'C', code), (b'M', message)] {
+        body.push(field);
+        body.extend_from_slice(value.as_bytes());
+        body.push(0);
+    }
+    body.push(0);
+    body
+}
+
+/// Encode a row description of `int4` columns in text format.
+fn int4_columns(names: &[&str]) -> Vec<u8> {
+    let mut body = (names.len() as i16).to_be_bytes().to_vec();
+    for name in names {
+        body.extend_from_slice(name.as_bytes());
+        body.push(0);
+        body.extend_from_slice(&0i32.to_be_bytes());
+        body.extend_from_slice(&0i16.to_be_bytes());
+        body.extend_from_slice(&23i32.to_be_bytes());
+        body.extend_from_slice(&4i16.to_be_bytes());
+        body.extend_from_slice(&(-1i32).to_be_bytes());
+        body.extend_from_slice(&0i16.to_be_bytes());
+    }
+    body
+}
+
+#[test]
+fn test_startup() {
+    smol::block_on(async {
+        let stream = MockStream::new()
+            .startup()
+            .message(b'T', &int4_columns(&["n"]))
+            .message(b'D', &[0, 1, 0, 0, 0, 1, b'7'])
+            .message(b'C', b"SELECT 1\0")
+            .message(b'Z', b"I");
+        let sent = stream.sent();
+
+        let mut connection = Connection::with_stream(stream, ConnectionConfig::new().user("mock"))
+            .await
+            .unwrap();
+
+        // The startup message names the user
+        let startup = sent.lock().unwrap().clone();
+        assert!(startup.windows(10).any(|window| window == b"user\0mock\0"));
+
+        let rows = connection.query("SELECT 7 AS n").await.unwrap();
+        assert_eq!(rows[0].get_and_parse::<i32>("n").unwrap(), 7);
+
+        // The query followed the startup message
+        let sent = sent.lock().unwrap();
+        assert_eq!(sent[startup.len()], b'Q');
+    });
+}
+
+#[test]
+fn test_startup_error() {
+    smol::block_on(async {
+        let stream = MockStream::new().message(
+            b'E',
+            &error_fields("28P01", "password authentication failed"),
+        );
+
+        let result = Connection::with_stream(stream, ConnectionConfig::new()).await;
+        let Err(Error::QueryError(error)) = result else {
+            panic!("unexpected result");
+        };
+        assert_eq!(error.code(), Some("28P01"));
+    });
+}
+
+#[test]
+fn test_gss_and_sspi_authentication() {
+    smol::block_on(async {
+        for (request_type, expected) in [
+            (7, "GSSAPI authentication is not supported"),
+            (9, "SSPI authentication is not supported"),
+        ] {
+            let stream = MockStream::new().message(b'R', &i32::to_be_bytes(request_type));
+
+            let result = Connection::with_stream(stream, ConnectionConfig::new()).await;
+            let Err(Error::AuthenticationError(error)) = result else {
+                panic!("unexpected result");
+            };
+            assert_eq!(error.to_string(), expected);
+        }
+    });
+}
+
+#[test]
+fn test_null_and_unknown_messages() {
+    smol::block_on(async {
+        let stream = MockStream::new()
+            .startup()
+            .message(b'T', &int4_columns(&["a", "b"]))
+            // A message type this client doesn't know is skipped
+            .message(b'~', b"ignored")
+            .message(b'D', &[0, 2, 255, 255, 255, 255, 0, 0, 0, 1, b'2'])
+            .message(b'C', b"SELECT 1\0")
+            .message(b'Z', b"I");
+
+        let mut connection = Connection::with_stream(stream, ConnectionConfig::new())
+            .await
+            .unwrap();
+
+        let rows = connection
+            .query("SELECT NULL::int4 AS a, 2 AS b")
+            .await
+            .unwrap();
+        assert_eq!(rows[0].get_and_parse::<Option<i32>>("a").unwrap(), None);
+        assert!(rows[0].get_and_parse::<i32>("a").is_err());
+        assert_eq!(rows[0].get_and_parse::<i32>("b").unwrap(), 2);
+    });
+}
+
+#[test]
+fn test_server_closes_connection() {
+    smol::block_on(async {
+        // The server goes away in the middle of a message
+        let stream = MockStream::new().startup().bytes(&[b'T', 0, 0]);
+
+        let mut connection = Connection::with_stream(stream, ConnectionConfig::new())
+            .await
+            .unwrap();
+
+        let result = connection.query("SELECT 1").await;
+        assert!(matches!(result, Err(Error::NetworkError(_))));
+        assert!(connection.is_closed());
+
+        let result = connection.query("SELECT 1").await;
+        assert!(matches!(result, Err(Error::ConnectionClosed)));
+    });
+}
+
+#[test]
+fn test_message_length_too_small() {
+    smol::block_on(async {
+        let stream = MockStream::new().startup().bytes(&[b'T', 0, 0, 0, 2]);
+
+        let mut connection = Connection::with_stream(stream, ConnectionConfig::new())
+            .await
+            .unwrap();
+
+        let result = connection.query("SELECT 1").await;
+        assert!(matches!(
+            result,
+            Err(Error::CodecError(CodecError::Decode(
+                DecodeError::InvalidMessageLength(2)
+            )))
+        ));
+
+        // The rest of the message can't be found anymore
+        assert!(connection.is_closed());
+        let result = connection.query("SELECT 1").await;
+        assert!(matches!(result, Err(Error::ConnectionClosed)));
+    });
+}
+
+#[test]
+fn test_server_closes_connection_between_messages() {
+    smol::block_on(async {
+        let stream = MockStream::new().startup();
+
+        let mut connection = Connection::with_stream(stream, ConnectionConfig::new())
+            .await
+            .unwrap();
+        assert!(!connection.is_closed());
+
+        let result = connection.query("SELECT 1").await;
+        assert!(matches!(result, Err(Error::ConnectionClosed)));
+        assert!(connection.is_closed());
+
+        // Nothing is sent over a closed connection
+        let result = connection.query("SELECT 1").await;
+        assert!(matches!(result, Err(Error::ConnectionClosed)));
+    });
+}
+
+#[test]
+fn test_connection_state() {
+    smol::block_on(async {
+        let connection = Connection::new(MockStream::new());
+        assert_eq!(connection.state(), ConnectionState::Startup);
+
+        let stream = MockStream::new()
+            .startup()
+            .message(b'C', b"BEGIN\0")
+            .message(b'Z', b"T")
+            .message(b'E', &error_fields("22012", "division by zero"))
+            .message(b'Z', b"E");
+
+        let mut connection = Connection::with_stream(stream, ConnectionConfig::new())
+            .await
+            .unwrap();
+        assert_eq!(connection.state(), ConnectionState::ReadyIdle);
+
+        // A response that wasn't read completely blocks the next query
+        connection
+            .send_client_message(&Query::new("BEGIN".to_string()))
+            .await
+            .unwrap();
+        assert_eq!(connection.state(), ConnectionState::AwaitingResponse);
+        let result = connection.query("SELECT 1").await;
+        assert!(matches!(
+            result,
+            Err(Error::ProtocolError(ProtocolError::NotReady(
+                ConnectionState::AwaitingResponse
+            )))
+        ));
+
+        while !matches!(
+            connection.read_server_message().await.unwrap(),
+            Message::ReadyForQuery
+        ) {}
+        assert_eq!(connection.state(), ConnectionState::ReadyInTransaction);
+
+        assert!(connection.batch_execute("SELECT 1/0").await.is_err());
+        assert_eq!(
+            connection.state(),
+            ConnectionState::ReadyInFailedTransaction
+        );
+    });
+}
+
+#[test]
+fn test_data_row_without_row_description() {
+    smol::block_on(async {
+        let stream = MockStream::new()
+            .startup()
+            .message(b'D', &[0, 1, 0, 0, 0, 1, b'7'])
+            .message(b'C', b"SELECT 1\0")
+            .message(b'Z', b"I");
+
+        let mut connection = Connection::with_stream(stream, ConnectionConfig::new())
+            .await
+            .unwrap();
+
+        let result = connection.query("SELECT 7").await;
+        assert!(matches!(
+            result,
+            Err(Error::ProtocolError(ProtocolError::MissingRowDescription))
+        ));
+    });
+}
+
+#[test]
+fn test_unexpected_messages() {
+    smol::block_on(async {
+        let stream = MockStream::new()
+            .startup()
+            .message(b'n', b"")
+            .message(b'n', b"")
+            .message(b'C', b"SET\0")
+            .message(b'Z', b"I")
+            .message(b'C', b"SET\0")
+            .message(b'Z', b"I");
+
+        let mut connection = Connection::with_stream(stream, ConnectionConfig::new())
+            .await
+            .unwrap();
+
+        connection.query("SET a.b = 1").await.unwrap();
+        assert_eq!(connection.unexpected_messages().count(), 2);
+        assert!(connection
+            .unexpected_messages()
+            .all(|message| matches!(message, Message::NoData)));
+
+        // The next operation starts with an empty buffer
+        connection.query("SET a.b = 2").await.unwrap();
+        assert_eq!(connection.unexpected_messages().count(), 0);
+    });
+}
+
+#[test]
+fn test_too_many_unexpected_messages() {
+    smol::block_on(async {
+        let mut stream = MockStream::new().startup();
+        for _ in 0..2000 {
+            stream = stream.message(b'n', b"");
+        }
+        let stream = stream.message(b'C', b"SET\0").message(b'Z', b"I");
+
+        let mut connection = Connection::with_stream(stream, ConnectionConfig::new())
+            .await
+            .unwrap();
+
+        let result = connection.query("SET a.b = 1").await;
+        assert!(matches!(
+            result,
+            Err(Error::ProtocolError(
+                ProtocolError::TooManyUnexpectedMessages(_)
+            ))
+        ));
+    });
+}
+
+#[test]
+fn test_binary_copy_negative_field_count() {
+    smol::block_on(async {
+        let mut data = b"PGCOPY\n\xff\r\n\0".to_vec();
+        data.extend_from_slice(&[0; 8]);
+        data.extend_from_slice(&(-2i16).to_be_bytes());
+
+        let stream = MockStream::new()
+            .startup()
+            .message(b'H', &[1, 0, 0])
+            .message(b'd', &data);
+
+        let mut connection = Connection::with_stream(stream, ConnectionConfig::new())
+            .await
+            .unwrap();
+
+        let mut reader = connection
+            .copy_out("COPY t TO STDOUT WITH (FORMAT binary)")
+            .await
+            .unwrap()
+            .with_binary_header()
+            .await
+            .unwrap();
+        let error = reader.next_row().await.unwrap_err();
+        assert!(matches!(
+            error,
+            Error::CodecError(CodecError::Decode(DecodeError::UnexpectedValue(_)))
+        ));
+    });
+}
+
+#[test]
+fn test_short_data_row() {
+    smol::block_on(async {
+        let stream = MockStream::new()
+            .startup()
+            .message(b'T', &int4_columns(&["a", "b"]))
+            .message(b'D', &[0, 1, 0, 0, 0, 1, b'1'])
+            .message(b'C', b"SELECT 1\0")
+            .message(b'Z', b"I");
+
+        let mut connection = Connection::with_stream(stream, ConnectionConfig::new())
+            .await
+            .unwrap();
+
+        let rows = connection.query("SELECT 1 AS a, 2 AS b").await.unwrap();
+        assert_eq!(rows[0].get_raw("a"), Some(&b"1"[..]));
+        // The column exists, but the row doesn't have a field for it
+        assert_eq!(rows[0].get_raw("b"), None);
+        assert_eq!(rows[0].to_vec_strings(), ["1", "NULL"]);
+        assert!(rows[0].get_value(0).is_ok());
+        let missing = rows[0].get_value(1).unwrap_err();
+        assert_eq!(missing.downcast::<FieldNotFound>().unwrap().name(), "b");
+    });
+}
+
+#[test]
+fn test_long_data_row() {
+    smol::block_on(async {
+        let stream = MockStream::new()
+            .startup()
+            .message(b'T', &int4_columns(&["a"]))
+            .message(b'D', &[0, 2, 0, 0, 0, 1, b'1', 0, 0, 0, 1, b'2'])
+            .message(b'C', b"SELECT 1\0")
+            .message(b'Z', b"I");
+
+        let mut connection = Connection::with_stream(stream, ConnectionConfig::new())
+            .await
+            .unwrap();
+
+        // The field without a column is left out
+        let rows = connection.query("SELECT 1 AS a").await.unwrap();
+        assert_eq!(rows[0].to_vec_strings(), ["1"]);
+    });
+}
+
+#[test]
+fn test_query_scalar_long_data_row() {
+    smol::block_on(async {
+        let stream = MockStream::new()
+            .startup()
+            .message(b'T', &int4_columns(&["a"]))
+            .message(b'D', &[0, 2, 0, 0, 0, 1, b'1', 0, 0, 0, 1, b'2'])
+            .message(b'C', b"SELECT 1\0")
+            .message(b'Z', b"I");
+
+        let mut connection = Connection::with_stream(stream, ConnectionConfig::new())
+            .await
+            .unwrap();
+
+        // The description has one column, but the row has two fields
+        assert!(matches!(
+            connection.query_scalar::<i32>("SELECT 1 AS a").await,
+            Err(Error::SchemaMismatch(SchemaMismatch::ColumnCount {
+                expected: 1,
+                actual: 2
+            }))
+        ));
+    });
+}
+
+#[test]
+fn test_server_version_num() {
+    smol::block_on(async {
+        for (version, expected) in [
+            ("16.2", Some(160002)),
+            ("10.23", Some(100023)),
+            ("9.6.24", Some(90624)),
+            ("17beta1 (Debian 17~beta1-1)", Some(170000)),
+            ("16.2 (Ubuntu 16.2-1)", Some(160002)),
+            ("unknown", None),
+            ("4294967295.0", None),
+            ("16.4294967295", None),
+            ("9.42949673.0", None),
+        ] {
+            let mut status = b"server_version\0".to_vec();
+            status.extend_from_slice(version.as_bytes());
+            status.push(0);
+
+            let stream = MockStream::new()
+                .message(b'R', &0i32.to_be_bytes())
+                .message(b'S', &status)
+                .message(b'Z', b"I");
+
+            let connection = Connection::with_stream(stream, ConnectionConfig::new())
+                .await
+                .unwrap();
+            assert_eq!(connection.parameter("server_version"), Some(version));
+            assert_eq!(connection.server_version_num(), expected, "{version}");
+        }
+
+        // Without the parameter, the version is unknown
+        let connection =
+            Connection::with_stream(MockStream::new().startup(), ConnectionConfig::new())
+                .await
+                .unwrap();
+        assert_eq!(connection.server_version_num(), None);
+    });
+}
+
+/// Encode the beginning of a copy data message with a standby status update.
+fn status_update_prefix(written: u64, flushed: u64) -> Vec<u8> {
+    let mut prefix = vec![b'd'];
+    prefix.extend_from_slice(&38i32.to_be_bytes());
+    prefix.push(b'r');
+    for lsn in [written, flushed, flushed] {
+        prefix.extend_from_slice(&lsn.to_be_bytes());
+    }
+    prefix
+}
+
+#[test]
+fn test_copy_both_replication_stream() {
+    smol::block_on(async {
+        let mut keepalive = vec![b'k'];
+        keepalive.extend_from_slice(&0x100u64.to_be_bytes());
+        keepalive.extend_from_slice(&0i64.to_be_bytes());
+        keepalive.push(1);
+
+        let mut xlog_data = vec![b'w'];
+        xlog_data.extend_from_slice(&0x100u64.to_be_bytes());
+        xlog_data.extend_from_slice(&0x104u64.to_be_bytes());
+        xlog_data.extend_from_slice(&1_000_000i64.to_be_bytes());
+        xlog_data.extend_from_slice(b"wal!");
+
+        let stream = MockStream::new()
+            .startup()
+            .message(b'W', &[0, 0, 0])
+            .message(b'd', &keepalive)
+            .message(b'd', &xlog_data)
+            .message(b'c', b"")
+            .message(b'C', b"START_REPLICATION\0")
+            .message(b'Z', b"I");
+        let sent = stream.sent();
+
+        let mut connection = Connection::with_stream(stream, ConnectionConfig::new())
+            .await
+            .unwrap();
+
+        let mut stream = connection
+            .copy_both("START_REPLICATION 0/100 PHYSICAL")
+            .await
+            .unwrap();
+
+        let data = stream.next().await.unwrap().unwrap();
+        assert_eq!(data.wal_start, PgLsn(0x100));
+        assert_eq!(data.wal_end, PgLsn(0x104));
+        assert_eq!(
+            data.server_time,
+            UNIX_EPOCH + Duration::from_secs(946_684_801)
+        );
+        assert_eq!(data.data, b"wal!");
+
+        stream.acknowledge(PgLsn(0x104));
+        stream.send_status_update(false).await.unwrap();
+        assert!(stream.next().await.unwrap().is_none());
+        stream.finish().await.unwrap();
+        assert_eq!(connection.state(), ConnectionState::ReadyIdle);
+
+        let sent = sent.lock().unwrap();
+        let contains = |bytes: &[u8]| sent.windows(bytes.len()).any(|window| window == bytes);
+        // The keepalive asked for a reply before any data arrived
+        assert!(contains(&status_update_prefix(0, 0)));
+        assert!(contains(&status_update_prefix(0x104, 0x104)));
+        assert!(sent.ends_with(&[b'c', 0, 0, 0, 4]));
+    });
+}
+
+#[test]
+fn test_replication_stream_wal_overflow() {
+    smol::block_on(async {
+        let mut xlog_data = vec![b'w'];
+        xlog_data.extend_from_slice(&u64::MAX.to_be_bytes());
+        xlog_data.extend_from_slice(&u64::MAX.to_be_bytes());
+        xlog_data.extend_from_slice(&0i64.to_be_bytes());
+        xlog_data.extend_from_slice(b"wal!");
+
+        let stream = MockStream::new()
+            .startup()
+            .message(b'W', &[0, 0, 0])
+            .message(b'd', &xlog_data);
+
+        let mut connection = Connection::with_stream(stream, ConnectionConfig::new())
+            .await
+            .unwrap();
+
+        let mut stream = connection
+            .copy_both("START_REPLICATION 0/100 PHYSICAL")
+            .await
+            .unwrap();
+        assert!(matches!(
+            stream.next().await,
+            Err(Error::CodecError(CodecError::Decode(
+                DecodeError::UnexpectedValue(_)
+            )))
+        ));
+    });
+}
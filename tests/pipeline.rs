@@ -0,0 +1,107 @@
+use std::net::Ipv4Addr;
+
+use smol_pg::{connection::Connection, Error};
+
+#[test]
+fn test_pipeline() {
+    smol::block_on(async {
+        let mut connection = Connection::create(std::net::IpAddr::V4(Ipv4Addr::LOCALHOST), None)
+            .await
+            .unwrap();
+
+        let mut pipeline = connection.pipeline();
+        pipeline
+            .push("SELECT $1::int4 + 1 AS n", &[&41])
+            .push("SET application_name = 'pipeline'", &[])
+            .push("SELECT * FROM generate_series(1, $1) AS n", &[&3]);
+        let results = pipeline.run().await.unwrap();
+
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[0][0].get_and_parse::<i32>("n").unwrap(), 42);
+        assert!(results[1].is_empty());
+        assert_eq!(results[2].len(), 3);
+
+        // An error in the middle aborts the batch but leaves the connection usable
+        let mut pipeline = connection.pipeline();
+        pipeline
+            .push("SELECT 1", &[])
+            .push("SELECT 1 / $1::int4", &[&0])
+            .push("SELECT 3", &[]);
+        let error = pipeline.run().await.unwrap_err();
+        assert!(matches!(error, Error::QueryError(_)));
+
+        assert_eq!(connection.query("SELECT 1").await.unwrap().len(), 1);
+    });
+}
+
+#[test]
+fn test_batch_keeps_transaction_open_until_sync() {
+    smol::block_on(async {
+        let connect = || Connection::create(std::net::IpAddr::V4(Ipv4Addr::LOCALHOST), None);
+        let mut connection = connect().await.unwrap();
+        let mut observer = connect().await.unwrap();
+
+        connection
+            .batch_execute(
+                "CREATE TABLE IF NOT EXISTS batch_flush (id int4);
+                 TRUNCATE batch_flush;",
+            )
+            .await
+            .unwrap();
+        let count = "SELECT count(*)::int4 FROM batch_flush";
+        connection.take_notices();
+
+        let mut batch = connection.batch();
+        batch
+            .execute("INSERT INTO batch_flush VALUES ($1)", &[&1])
+            .await
+            .unwrap();
+        let rows = batch.execute(count, &[]).await.unwrap();
+        assert_eq!(rows[0].try_get::<i32>(0).unwrap(), 1);
+
+        // Nothing is committed before the sync
+        assert_eq!(observer.query_scalar::<i32>(count).await.unwrap(), 0);
+        batch.sync().await.unwrap();
+        assert_eq!(observer.query_scalar::<i32>(count).await.unwrap(), 1);
+
+        // A failing query rolls back the transaction, the batch goes on
+        batch
+            .execute("INSERT INTO batch_flush VALUES ($1)", &[&2])
+            .await
+            .unwrap();
+        let result = batch.execute("SELECT 1 / 0", &[]).await;
+        assert!(matches!(result, Err(Error::QueryError(_))));
+        batch
+            .execute("INSERT INTO batch_flush VALUES ($1)", &[&3])
+            .await
+            .unwrap();
+
+        // A dropped batch is rolled back when the connection is used again
+        let rows = connection
+            .query("SELECT id FROM batch_flush ORDER BY id")
+            .await
+            .unwrap();
+        let ids = rows
+            .iter()
+            .map(|row| row.try_get::<i32>("id").unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(ids, [1]);
+
+        // Even if the next thing is another batch, which starts over, and the
+        // results of the last query weren't read
+        let mut batch = connection.batch();
+        batch
+            .execute("INSERT INTO batch_flush VALUES ($1)", &[&4])
+            .await
+            .unwrap();
+        let slow = batch.execute("SELECT 5 FROM pg_sleep(0.1)", &[]);
+        assert!(futures_lite::future::poll_once(slow).await.is_none());
+
+        let mut batch = connection.batch();
+        let rows = batch.execute("SELECT 6", &[]).await.unwrap();
+        assert_eq!(rows[0].try_get::<i32>(0).unwrap(), 6);
+        batch.sync().await.unwrap();
+        assert_eq!(observer.query_scalar::<i32>(count).await.unwrap(), 1);
+        assert!(connection.take_notices().is_empty());
+    });
+}
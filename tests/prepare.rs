@@ -0,0 +1,235 @@
+use std::net::Ipv4Addr;
+
+use smol_pg::{
+    config::{ConnectionConfig, StatementLimitPolicy},
+    connection::{Connection, Row, SchemaMismatch},
+    protocol::oids,
+    util::quote_identifier,
+    Error,
+};
+
+#[test]
+fn test_prepare_typed_detects_mismatch() {
+    smol::block_on(async {
+        let mut connection = Connection::create(std::net::IpAddr::V4(Ipv4Addr::LOCALHOST), None)
+            .await
+            .unwrap();
+
+        let query = "SELECT 1::int4 AS n, 'x'::text AS s WHERE $1::int4 > 0";
+
+        let statement = connection.prepare_typed(query, &[23, 25]).await.unwrap();
+        assert_eq!(statement.parameter_types(), &[23]);
+        assert_eq!(statement.columns()[1].name, "s");
+
+        let error = connection
+            .prepare_typed(query, &[20, 25])
+            .await
+            .unwrap_err();
+        let Error::SchemaMismatch(SchemaMismatch::ColumnType {
+            column,
+            expected,
+            actual,
+        }) = error
+        else {
+            panic!("expected a column type mismatch, got {error:?}");
+        };
+        assert_eq!((column.as_str(), expected, actual), ("n", 20, 23));
+        let error = connection.prepare_typed(query, &[23]).await.unwrap_err();
+        assert!(matches!(
+            error,
+            Error::SchemaMismatch(SchemaMismatch::ColumnCount { .. })
+        ));
+
+        // The mismatching statements were closed and the connection is still usable
+        let count = "SELECT count(*)::int4 FROM pg_prepared_statements";
+        assert_eq!(connection.query_scalar::<i32>(count).await.unwrap(), 1);
+    });
+}
+
+#[test]
+fn test_describe_without_executing() {
+    smol::block_on(async {
+        let mut connection = Connection::create(std::net::IpAddr::V4(Ipv4Addr::LOCALHOST), None)
+            .await
+            .unwrap();
+
+        let (parameters, columns) = connection
+            .describe("SELECT $1::text AS name, 2::int8 AS n")
+            .await
+            .unwrap();
+        assert_eq!(parameters.types, [25]);
+        assert_eq!(columns.fields.len(), 2);
+        assert_eq!(columns.fields[1].name, "n");
+        assert_eq!(columns.fields[1].data_type_oid, 20);
+
+        // Statements without a result set are described as having no columns
+        let (_, columns) = connection
+            .describe("SET search_path = public")
+            .await
+            .unwrap();
+        assert!(columns.fields.is_empty());
+    });
+}
+
+#[test]
+fn test_prepared_statement_limit() {
+    smol::block_on(async {
+        let config =
+            ConnectionConfig::new().max_prepared_statements(2, StatementLimitPolicy::Evict);
+        let mut connection = Connection::connect(config).await.unwrap();
+
+        let first = connection.prepare("SELECT 1").await.unwrap();
+        let second = connection.prepare("SELECT 2").await.unwrap();
+        let third = connection.prepare("SELECT 3").await.unwrap();
+
+        // The oldest statement was closed to make room for the third one
+        let rows = connection
+            .query("SELECT name FROM pg_prepared_statements ORDER BY name")
+            .await
+            .unwrap();
+        let names = rows
+            .iter()
+            .map(|row| row.get_and_parse::<String>("name").unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(names, [second.name(), third.name()]);
+        assert!(!names.contains(&first.name().to_string()));
+
+        let config =
+            ConnectionConfig::new().max_prepared_statements(2, StatementLimitPolicy::Error);
+        let mut connection = Connection::connect(config).await.unwrap();
+
+        connection.prepare("SELECT 1").await.unwrap();
+        connection.prepare("SELECT 2").await.unwrap();
+        let error = connection.prepare("SELECT 3").await.unwrap_err();
+        assert!(matches!(error, Error::StatementLimitReached(2)));
+
+        // Cached statements count towards the limit and are closed first
+        let config = ConnectionConfig::new()
+            .max_prepared_statements(3, StatementLimitPolicy::Error)
+            .statement_cache_size(2);
+        let mut connection = Connection::connect(config).await.unwrap();
+
+        let cached = connection.prepare_cached("SELECT 1").await.unwrap();
+        connection.prepare_cached("SELECT 2").await.unwrap();
+        let mut prepared = Vec::new();
+        for query in ["SELECT 3", "SELECT 4", "SELECT 5"] {
+            prepared.push(connection.prepare(query).await.unwrap());
+
+            let rows = connection
+                .query("SELECT name FROM pg_prepared_statements ORDER BY name")
+                .await
+                .unwrap();
+            assert!(rows.len() <= 3);
+            assert!(!rows.is_empty());
+        }
+        let rows = connection
+            .query("SELECT name FROM pg_prepared_statements ORDER BY name")
+            .await
+            .unwrap();
+        let names = rows
+            .iter()
+            .map(|row| row.get_and_parse::<String>("name").unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            prepared
+                .iter()
+                .map(|statement| statement.name())
+                .collect::<Vec<_>>()
+        );
+        assert!(!names.contains(&cached.name().to_string()));
+
+        // Once only prepared statements are left, the policy applies
+        let error = connection.prepare("SELECT 6").await.unwrap_err();
+        assert!(matches!(error, Error::StatementLimitReached(3)));
+        let error = connection.prepare_cached("SELECT 6").await.unwrap_err();
+        assert!(matches!(error, Error::StatementLimitReached(3)));
+
+        // A pipeline without room in the cache parses its queries unnamed
+        let mut pipeline = connection.pipeline();
+        pipeline.push("SELECT 7", &[]).push("SELECT 8", &[]);
+        assert_eq!(pipeline.run().await.unwrap().len(), 2);
+    });
+}
+
+#[test]
+fn test_deallocate() {
+    assert_eq!(quote_identifier("s0"), r#""s0""#);
+    assert_eq!(quote_identifier(r#"we"ird"#), r#""we""ird""#);
+
+    smol::block_on(async {
+        let config =
+            ConnectionConfig::new().max_prepared_statements(1, StatementLimitPolicy::Error);
+        let mut connection = Connection::connect(config).await.unwrap();
+
+        let statement = connection.prepare("SELECT 1").await.unwrap();
+        connection.deallocate(statement.name()).await.unwrap();
+
+        let rows = connection
+            .query("SELECT count(*) AS n FROM pg_prepared_statements")
+            .await
+            .unwrap();
+        assert_eq!(rows[0].get_and_parse::<String>("n").unwrap(), "0");
+
+        // The statement no longer counts towards the limit
+        connection.prepare("SELECT 2").await.unwrap();
+
+        // Deallocating an unknown statement is an error
+        let error = connection.deallocate("unknown").await.unwrap_err();
+        assert!(matches!(error, Error::QueryError(_)));
+    });
+}
+
+#[test]
+fn test_statement_cache() {
+    smol::block_on(async {
+        let config = ConnectionConfig::new().statement_cache_size(2);
+        let mut connection = Connection::connect(config).await.unwrap();
+
+        let first = connection.prepare_cached("SELECT 1").await.unwrap();
+        assert_eq!(connection.prepare_cached("SELECT 1").await.unwrap(), first);
+
+        // Queries reuse the statements of the cache
+        for n in [1, 2] {
+            let rows = connection
+                .query_typed("SELECT $1 AS n", &[(&n, oids::INT4)])
+                .await
+                .unwrap();
+            assert_eq!(rows[0].get_and_parse::<i32>("n").unwrap(), n);
+        }
+
+        let statement_names = |rows: Vec<Row>| {
+            rows.iter()
+                .map(|row| row.get_and_parse::<String>("name").unwrap())
+                .collect::<Vec<_>>()
+        };
+        let names = statement_names(
+            connection
+                .query("SELECT name FROM pg_prepared_statements ORDER BY name")
+                .await
+                .unwrap(),
+        );
+        assert_eq!(names.len(), 2);
+        assert!(names.contains(&first.name().to_string()));
+
+        // The least recently used statement is closed to make room
+        let last = connection.prepare_cached("SELECT 3").await.unwrap();
+        let names = statement_names(
+            connection
+                .query("SELECT name FROM pg_prepared_statements ORDER BY name")
+                .await
+                .unwrap(),
+        );
+        assert_eq!(names.len(), 2);
+        assert!(!names.contains(&first.name().to_string()));
+        assert!(names.contains(&last.name().to_string()));
+
+        // A batch with more distinct queries than fit in the cache still runs
+        let mut pipeline = connection.pipeline();
+        pipeline
+            .push("SELECT 4", &[])
+            .push("SELECT 5", &[])
+            .push("SELECT 6", &[]);
+        assert_eq!(pipeline.run().await.unwrap().len(), 3);
+    });
+}
@@ -0,0 +1,726 @@
+use std::{
+    net::Ipv4Addr,
+    time::{Duration, Instant},
+};
+
+use futures_lite::StreamExt;
+
+use smol_pg::{
+    connection::{Connection, ConnectionState, FieldNotFound, SchemaMismatch},
+    protocol::{
+        message::server::{Message, TransactionStatus},
+        oids,
+    },
+    Error,
+};
+
+async fn connect() -> Connection {
+    Connection::create(std::net::IpAddr::V4(Ipv4Addr::LOCALHOST), None)
+        .await
+        .unwrap()
+}
+
+#[test]
+fn test_query_raw_returns_messages_in_order() {
+    smol::block_on(async {
+        let mut connection = connect().await;
+
+        let messages = connection
+            .query_raw("SELECT * FROM generate_series(1, 2); SELECT 1/0")
+            .await
+            .unwrap();
+
+        assert!(matches!(
+            messages.as_slice(),
+            [
+                Message::RowDescription(_),
+                Message::DataRow(_),
+                Message::DataRow(_),
+                Message::CommandComplete(_),
+                Message::Error(_),
+            ]
+        ));
+    });
+}
+
+#[test]
+fn test_query_error_is_displayed() {
+    smol::block_on(async {
+        let mut connection = connect().await;
+
+        let error = connection.query("SELECT 1/0").await.unwrap_err();
+        let smol_pg::Error::QueryError(response) = &error else {
+            panic!("expected a query error, got {error:?}");
+        };
+        assert_eq!(response.code(), Some("22012"));
+        assert_eq!(response.to_string(), "ERROR: 22012: division by zero");
+        assert_eq!(
+            error.to_string(),
+            "the server responded with an error: ERROR: 22012: division by zero"
+        );
+    });
+}
+
+#[test]
+fn test_query_typed_with_explicit_parameter_types() {
+    smol::block_on(async {
+        let mut connection = connect().await;
+
+        let rows = connection
+            .query_typed(
+                "SELECT $1 + $2 AS n, $3 AS t",
+                &[(&40, oids::INT4), (&2, oids::INT4), (&"hi", oids::TEXT)],
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(rows[0].get_and_parse::<i32>("n").unwrap(), 42);
+        assert_eq!(rows[0].get_and_parse::<String>("t").unwrap(), "hi");
+
+        // Without the types, the server can't pick an operator
+        let mut pipeline = connection.pipeline();
+        pipeline.push("SELECT $1 + $2 AS n", &[&40, &2]);
+        assert!(pipeline.run().await.is_err());
+    });
+}
+
+#[test]
+fn test_query_raw_frames_in_order() {
+    smol::block_on(async {
+        let mut connection = connect().await;
+
+        let frames = connection
+            .query_raw_frames("SELECT * FROM generate_series(1, 2)")
+            .collect::<Vec<_>>()
+            .await;
+        let message_types = frames
+            .into_iter()
+            .map(|frame| frame.unwrap().0)
+            .collect::<Vec<_>>();
+        assert_eq!(message_types, b"TDDCZ");
+
+        // The connection is ready for the next query
+        let rows = connection.query("SELECT 1 AS n").await.unwrap();
+        assert_eq!(rows.len(), 1);
+    });
+}
+
+#[test]
+fn test_query_raw_frames_updates_parameters() {
+    smol::block_on(async {
+        let mut connection = connect().await;
+        connection.query("SET TimeZone = 'UTC'").await.unwrap();
+        assert_eq!(connection.parameter("TimeZone"), Some("UTC"));
+
+        let frames = connection
+            .query_raw_frames("SET TimeZone = 'Europe/Berlin'")
+            .collect::<Vec<_>>()
+            .await;
+        let message_types = frames
+            .into_iter()
+            .map(|frame| frame.unwrap().0)
+            .collect::<Vec<_>>();
+        assert_eq!(message_types, b"CSZ");
+
+        // The raw frame was still applied
+        assert_eq!(connection.parameter("TimeZone"), Some("Europe/Berlin"));
+    });
+}
+
+#[test]
+fn test_row_index() {
+    smol::block_on(async {
+        let mut connection = connect().await;
+
+        let rows = connection.query("SELECT 1 AS a, 2 AS b").await.unwrap();
+
+        assert_eq!(&rows[0]["b"], rows[0].get("b").unwrap());
+        assert_eq!(&rows[0][0], rows[0].get("a").unwrap());
+
+        let missing = std::panic::catch_unwind(|| rows[0]["c"].clone());
+        assert!(missing.is_err());
+
+        // A missing field converts into the crate's error
+        let missing = rows[0].get_and_parse::<i32>("c").unwrap_err();
+        let missing = *missing.downcast::<FieldNotFound>().unwrap();
+        assert_eq!(missing.name(), "c");
+        let error = Error::from(missing);
+        assert_eq!(error.to_string(), "row doesn't contain field `c`");
+        assert!(matches!(error, Error::FieldNotFound(_)));
+    });
+}
+
+#[test]
+fn test_error_fields_of_unique_violation() {
+    smol::block_on(async {
+        let mut connection = connect().await;
+
+        connection
+            .query_raw(
+                "CREATE TEMPORARY TABLE users (name text CONSTRAINT users_name_key UNIQUE); \
+                 INSERT INTO users VALUES ('alice')",
+            )
+            .await
+            .unwrap();
+
+        let error = connection
+            .query("INSERT INTO users VALUES ('alice') RETURNING name")
+            .await
+            .unwrap_err();
+        let Error::QueryError(error) = error else {
+            panic!("expected a query error, got {error:?}");
+        };
+
+        assert_eq!(error.code(), Some("23505"));
+        assert_eq!(error.severity_nonlocalized(), Some("ERROR"));
+        assert_eq!(error.constraint_name(), Some("users_name_key"));
+        assert_eq!(error.table_name(), Some("users"));
+        assert!(error.schema_name().unwrap().starts_with("pg_temp"));
+        assert_eq!(error.detail(), Some("Key (name)=(alice) already exists."));
+        assert!(error.file().is_some() && error.line().is_some() && error.routine().is_some());
+
+        let error = connection.query("SELECT nope").await.unwrap_err();
+        let Error::QueryError(error) = error else {
+            panic!("expected a query error, got {error:?}");
+        };
+        assert_eq!(error.position(), Some(8));
+    });
+}
+
+#[test]
+fn test_batch_execute() {
+    smol::block_on(async {
+        let mut connection = connect().await;
+
+        connection
+            .batch_execute(
+                "CREATE TEMPORARY TABLE batch (id int PRIMARY KEY);
+                 INSERT INTO batch VALUES (1), (2);
+                 DO $$ BEGIN RAISE NOTICE 'migrated'; END $$;
+                 SELECT * FROM batch;",
+            )
+            .await
+            .unwrap();
+
+        let rows = connection
+            .query("SELECT count(*)::int4 AS n FROM batch")
+            .await
+            .unwrap();
+        assert_eq!(rows[0].get_and_parse::<i32>("n").unwrap(), 2);
+
+        // The failing statement stops the script and rolls it back
+        let result = connection
+            .batch_execute("INSERT INTO batch VALUES (3); INSERT INTO batch VALUES (1); INSERT INTO batch VALUES (4);")
+            .await;
+        let Err(Error::QueryError(error)) = result else {
+            panic!("unexpected result {result:?}");
+        };
+        assert_eq!(error.code(), Some("23505"));
+
+        let rows = connection
+            .query("SELECT count(*)::int4 AS n FROM batch")
+            .await
+            .unwrap();
+        assert_eq!(rows[0].get_and_parse::<i32>("n").unwrap(), 2);
+    });
+}
+
+#[test]
+fn test_set_role() {
+    smol::block_on(async {
+        let mut connection = connect().await;
+
+        connection
+            .batch_execute(
+                r#"DO $$ BEGIN
+                    CREATE ROLE "Tenant Role";
+                EXCEPTION WHEN duplicate_object THEN NULL;
+                END $$"#,
+            )
+            .await
+            .unwrap();
+
+        connection.set_role("Tenant Role").await.unwrap();
+        let user = connection
+            .query_scalar::<String>("SELECT current_user::text")
+            .await
+            .unwrap();
+        assert_eq!(user, "Tenant Role");
+
+        connection.reset_role().await.unwrap();
+        let user = connection
+            .query_scalar::<String>("SELECT current_user::text")
+            .await
+            .unwrap();
+        assert_eq!(user, "postgres");
+
+        assert!(matches!(
+            connection.set_role("").await,
+            Err(Error::InvalidIdentifier(_))
+        ));
+        assert!(matches!(
+            connection.set_role("no such role").await,
+            Err(Error::QueryError(_))
+        ));
+
+        // Resetting the connection drops the role as well
+        connection.set_role("Tenant Role").await.unwrap();
+        connection.reset().await.unwrap();
+        let user = connection
+            .query_scalar::<String>("SELECT current_user::text")
+            .await
+            .unwrap();
+        assert_eq!(user, "postgres");
+    });
+}
+
+#[test]
+fn test_set_search_path() {
+    smol::block_on(async {
+        let mut connection = connect().await;
+
+        connection
+            .batch_execute(
+                r#"CREATE SCHEMA IF NOT EXISTS "Tenant ""A""";
+                   CREATE TABLE IF NOT EXISTS "Tenant ""A""".tenant_table (id int);"#,
+            )
+            .await
+            .unwrap();
+
+        connection
+            .set_search_path(&["Tenant \"A\"", "public"])
+            .await
+            .unwrap();
+        let rows = connection
+            .query("SELECT current_setting('search_path') AS path")
+            .await
+            .unwrap();
+        assert_eq!(
+            rows[0].get_and_parse::<&str>("path").unwrap(),
+            r#""Tenant ""A""", public"#
+        );
+        // Unqualified names resolve in the first schema
+        let rows = connection
+            .query("SELECT * FROM tenant_table")
+            .await
+            .unwrap();
+        assert!(rows.is_empty());
+
+        assert!(matches!(
+            connection.set_search_path(&["public", ""]).await,
+            Err(Error::InvalidIdentifier(_))
+        ));
+
+        connection.set_search_path(&[]).await.unwrap();
+        let rows = connection
+            .query("SELECT current_setting('search_path') AS path")
+            .await
+            .unwrap();
+        assert_eq!(rows[0].get_and_parse::<&str>("path").unwrap(), "\"\"");
+    });
+}
+
+#[test]
+fn test_data_row_fields() {
+    smol::block_on(async {
+        let mut connection = connect().await;
+
+        let messages = connection
+            .query_raw("SELECT 1 AS a, NULL AS b, 'c' AS c")
+            .await
+            .unwrap();
+        let Some(Message::DataRow(row)) = messages.get(1) else {
+            panic!("unexpected messages {messages:?}");
+        };
+
+        assert_eq!(row.len(), 3);
+        assert!(!row.is_empty());
+        let fields = row.iter().map(|field| field.as_bytes()).collect::<Vec<_>>();
+        assert_eq!(fields, [Some(&b"1"[..]), None, Some(&b"c"[..])]);
+        for (index, field) in row.into_iter().enumerate() {
+            assert_eq!(field.is_null(), index == 1);
+        }
+    });
+}
+
+#[test]
+fn test_terminated_backend() {
+    smol::block_on(async {
+        let mut connection = connect().await;
+
+        let result = connection
+            .query("SELECT pg_terminate_backend(pg_backend_pid())")
+            .await;
+        assert!(result.is_err());
+        assert!(connection.is_closed());
+        assert!(matches!(
+            connection.query("SELECT 1").await,
+            Err(Error::ConnectionClosed)
+        ));
+    });
+}
+
+#[test]
+fn test_reset() {
+    smol::block_on(async {
+        let mut connection = connect().await;
+        assert_eq!(connection.transaction_status(), TransactionStatus::Idle);
+
+        connection
+            .batch_execute(
+                "SET application_name = 'dirty';
+                 CREATE TEMPORARY TABLE reset_me (id int);
+                 BEGIN;",
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            connection.transaction_status(),
+            TransactionStatus::InTransaction
+        );
+        let statement = connection.prepare("SELECT 1").await.unwrap();
+        assert!(connection.query("SELECT 1/0").await.is_err());
+        assert_eq!(connection.transaction_status(), TransactionStatus::Failed);
+
+        connection.reset().await.unwrap();
+        assert_eq!(connection.transaction_status(), TransactionStatus::Idle);
+
+        let rows = connection
+            .query(
+                "SELECT current_setting('application_name') AS name, \
+                 to_regclass('reset_me') IS NULL AS dropped, \
+                 (SELECT count(*)::int4 FROM pg_prepared_statements) AS statements",
+            )
+            .await
+            .unwrap();
+        assert_eq!(rows[0].get_and_parse::<&str>("name").unwrap(), "");
+        assert_eq!(rows[0].get_and_parse::<&str>("dropped").unwrap(), "t");
+        assert_eq!(rows[0].get_and_parse::<i32>("statements").unwrap(), 0);
+
+        // The statement is gone, so it can't be closed
+        assert!(connection.deallocate(statement.name()).await.is_err());
+    });
+}
+
+#[test]
+fn test_query_scalar() {
+    smol::block_on(async {
+        let mut connection = connect().await;
+
+        let count = connection
+            .query_scalar::<i32>("SELECT count(*)::int4 FROM generate_series(1, 3)")
+            .await
+            .unwrap();
+        assert_eq!(count, 3);
+        let name = connection
+            .query_scalar::<String>("SELECT current_user")
+            .await
+            .unwrap();
+        assert_eq!(name, "postgres");
+
+        assert!(matches!(
+            connection
+                .query_scalar::<i32>("SELECT * FROM generate_series(1, 2)")
+                .await,
+            Err(Error::SchemaMismatch(SchemaMismatch::RowCount {
+                expected: 1,
+                actual: 2
+            }))
+        ));
+        assert!(matches!(
+            connection.query_scalar::<i32>("SELECT 1, 2").await,
+            Err(Error::SchemaMismatch(SchemaMismatch::ColumnCount {
+                expected: 1,
+                actual: 2
+            }))
+        ));
+        assert!(matches!(
+            connection.query_scalar::<i32>("SELECT 'x'").await,
+            Err(Error::FromRowError(_))
+        ));
+    });
+}
+
+#[test]
+fn test_notice_fields() {
+    smol::block_on(async {
+        let mut connection = connect().await;
+
+        connection
+            .batch_execute(
+                "DO $$ BEGIN
+                     RAISE WARNING 'disk almost full' USING DETAIL = '95% used', HINT = 'Clean up';
+                 END $$;",
+            )
+            .await
+            .unwrap();
+
+        let notices = connection.take_notices();
+        let [notice] = notices.as_slice() else {
+            panic!("unexpected notices {notices:?}");
+        };
+        assert_eq!(notice.to_string(), "WARNING: disk almost full");
+        assert_eq!(notice.code(), Some("01000"));
+        assert_eq!(notice.detail(), Some("95% used"));
+        assert_eq!(notice.hint(), Some("Clean up"));
+        assert_eq!(notice.severity_level().unwrap().to_string(), "WARNING");
+    });
+}
+
+#[test]
+fn test_interleaved_parameter_status_and_notices() {
+    smol::block_on(async {
+        let mut connection = connect().await;
+
+        // The parameter status arrives in the middle of the response
+        let rows = connection
+            .query("SET TimeZone = 'Asia/Tokyo'; SELECT 1 AS n")
+            .await
+            .unwrap();
+        assert_eq!(rows[0].get_and_parse::<i32>("n").unwrap(), 1);
+        assert_eq!(connection.parameter("TimeZone"), Some("Asia/Tokyo"));
+
+        // Notices of any kind of query can be taken afterwards
+        let mut pipeline = connection.pipeline();
+        pipeline
+            .push("DO $$ BEGIN RAISE NOTICE 'first'; END $$", &[])
+            .push("SELECT 2", &[]);
+        pipeline.run().await.unwrap();
+        let mut batch = connection.batch();
+        batch
+            .execute("DO $$ BEGIN RAISE NOTICE 'second'; END $$", &[])
+            .await
+            .unwrap();
+        batch.sync().await.unwrap();
+
+        let messages = connection
+            .take_notices()
+            .iter()
+            .map(|notice| notice.message().unwrap_or_default().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(messages, ["first", "second"]);
+        assert!(connection.take_notices().is_empty());
+    });
+}
+
+#[test]
+fn test_query_without_rows() {
+    smol::block_on(async {
+        let mut connection = connect().await;
+
+        for statement in [
+            "CREATE TEMPORARY TABLE no_rows (id int)",
+            "INSERT INTO no_rows VALUES (1)",
+            "SET application_name = 'no rows'",
+            "SELECT * FROM no_rows WHERE id = 2",
+            "",
+        ] {
+            let rows = connection.query(statement).await.unwrap();
+            assert!(rows.is_empty(), "{statement}");
+        }
+    });
+}
+
+#[test]
+fn test_server_version_num() {
+    smol::block_on(async {
+        let mut connection = connect().await;
+
+        let rows = connection
+            .query("SELECT current_setting('server_version_num')::int4 AS n")
+            .await
+            .unwrap();
+        let expected = rows[0].get_and_parse::<i32>("n").unwrap();
+
+        assert_eq!(connection.server_version_num(), Some(expected as u32));
+    });
+}
+
+#[test]
+fn test_rows_without_columns() {
+    smol::block_on(async {
+        let mut connection = connect().await;
+
+        let rows = connection
+            .query("SELECT FROM generate_series(1, 3)")
+            .await
+            .unwrap();
+        assert_eq!(rows.len(), 3);
+        for row in &rows {
+            assert!(row.columns().is_empty());
+            assert!(row.get("generate_series").is_none());
+            assert!(row.get_and_parse::<i32>("generate_series").is_err());
+        }
+
+        let result = connection
+            .query_scalar::<i32>("SELECT FROM generate_series(1, 1)")
+            .await;
+        assert!(matches!(
+            result,
+            Err(Error::SchemaMismatch(SchemaMismatch::ColumnCount {
+                expected: 1,
+                actual: 0
+            }))
+        ));
+    });
+}
+
+#[test]
+fn test_null_parameters() {
+    smol::block_on(async {
+        let mut connection = connect().await;
+
+        connection
+            .query_raw("CREATE TEMPORARY TABLE null_parameters (id int4, n int4)")
+            .await
+            .unwrap();
+
+        let mut pipeline = connection.pipeline();
+        pipeline
+            .push(
+                "INSERT INTO null_parameters VALUES ($1, $2)",
+                &[&1, &None::<i32>],
+            )
+            .push(
+                "INSERT INTO null_parameters VALUES ($1, $2)",
+                &[&2, &Some(42)],
+            );
+        pipeline.run().await.unwrap();
+
+        let rows = connection
+            .query_typed(
+                "SELECT n FROM null_parameters WHERE id = $1 OR $2::int4 IS NULL ORDER BY id",
+                &[(&0, oids::INT4), (&None::<i32>, oids::INT4)],
+            )
+            .await
+            .unwrap();
+        assert_eq!(rows[0].get_and_parse::<Option<i32>>("n").unwrap(), None);
+        assert_eq!(rows[1].get_and_parse::<Option<i32>>("n").unwrap(), Some(42));
+    });
+}
+
+#[test]
+fn test_dropped_query_future() {
+    smol::block_on(async {
+        let mut connection = connect().await;
+
+        // Poll the query once, which sends it, and drop it before the response
+        let slow = connection.query("SELECT n FROM pg_sleep(0.2), generate_series(1, 10000) AS n");
+        assert!(futures_lite::future::poll_once(slow).await.is_none());
+        assert_eq!(connection.state(), ConnectionState::AwaitingResponse);
+
+        // This one is dropped while waiting for the response, before it is sent
+        let mut pipeline = connection.pipeline();
+        pipeline.push("SELECT pg_sleep(0.2)", &[]);
+        assert!(futures_lite::future::poll_once(pipeline.run())
+            .await
+            .is_none());
+
+        // The rest of the response is skipped
+        let rows = connection.query("SELECT 2 AS n").await.unwrap();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].get_and_parse::<i32>("n").unwrap(), 2);
+        assert!(!connection.is_closed());
+
+        // An abandoned copy is ended as well
+        let copy = connection
+            .query("COPY (SELECT n FROM pg_sleep(0.2), generate_series(1, 10000) AS n) TO STDOUT");
+        assert!(futures_lite::future::poll_once(copy).await.is_none());
+        assert_eq!(connection.query_scalar::<i32>("SELECT 3").await.unwrap(), 3);
+    });
+}
+
+#[test]
+fn test_query_until() {
+    smol::block_on(async {
+        let mut connection = connect().await;
+
+        // A query that finishes first returns its rows
+        let rows = connection
+            .query_until("SELECT 1 AS n", futures_lite::future::pending::<()>())
+            .await
+            .unwrap();
+        assert_eq!(rows[0].get_and_parse::<i32>("n").unwrap(), 1);
+
+        // A query that doesn't is cancelled on the server instead of running on
+        let start = Instant::now();
+        let result = connection
+            .query_until(
+                "SELECT pg_sleep(30)",
+                smol::Timer::after(Duration::from_millis(100)),
+            )
+            .await;
+        assert!(matches!(result, Err(Error::Cancelled)));
+        assert!(start.elapsed() < Duration::from_secs(10));
+        assert_eq!(connection.state(), ConnectionState::ReadyIdle);
+
+        assert_eq!(connection.query_scalar::<i32>("SELECT 2").await.unwrap(), 2);
+    });
+}
+
+#[test]
+fn test_cancel_token() {
+    smol::block_on(async {
+        let mut connection = connect().await;
+        let token = connection.cancel_token().unwrap();
+
+        // E.g. a shutdown handler cancels the query while it runs
+        let (result, cancelled) =
+            futures_lite::future::zip(connection.query("SELECT pg_sleep(30)"), async {
+                smol::Timer::after(Duration::from_millis(100)).await;
+                token.cancel().await
+            })
+            .await;
+        cancelled.unwrap();
+        let Err(Error::QueryError(error)) = result else {
+            panic!("unexpected result {result:?}");
+        };
+        assert_eq!(error.code(), Some("57014"));
+
+        assert_eq!(connection.query_scalar::<i32>("SELECT 1").await.unwrap(), 1);
+    });
+}
+
+#[test]
+fn test_returning_rows_and_count() {
+    smol::block_on(async {
+        let mut connection = connect().await;
+
+        connection
+            .query("CREATE TEMPORARY TABLE returned (id serial, label text)")
+            .await
+            .unwrap();
+
+        // The returned rows are rows, not an error
+        let rows = connection
+            .query("INSERT INTO returned (label) VALUES ('one') RETURNING id")
+            .await
+            .unwrap();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].get_and_parse::<i32>("id").unwrap(), 1);
+
+        let (rows, count) = connection
+            .query_with_count("INSERT INTO returned (label) VALUES ('two'), ('three') RETURNING id")
+            .await
+            .unwrap();
+        let ids = rows
+            .iter()
+            .map(|row| row.try_get::<i32>("id").unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(ids, [2, 3]);
+        assert_eq!(count, Some(2));
+
+        let (rows, count) = connection
+            .query_with_count("UPDATE returned SET label = upper(label)")
+            .await
+            .unwrap();
+        assert!(rows.is_empty());
+        assert_eq!(count, Some(3));
+
+        let (_, count) = connection
+            .query_with_count("CREATE TEMPORARY TABLE no_count (id int)")
+            .await
+            .unwrap();
+        assert_eq!(count, None);
+    });
+}
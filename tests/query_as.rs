@@ -0,0 +1,132 @@
+use std::net::Ipv4Addr;
+
+use smol_pg::{
+    config::ConnectionConfig,
+    connection::{ColumnError, Connection, FromRow, Row},
+    protocol::message::server::FormatCode,
+    Error,
+};
+
+#[derive(Debug)]
+struct Product {
+    name: String,
+    price: i32,
+}
+
+impl FromRow for Product {
+    fn from_row(row: &Row) -> Result<Self, ColumnError> {
+        Ok(Product {
+            name: row.try_get("name")?,
+            price: row.try_get("price")?,
+        })
+    }
+}
+
+#[test]
+fn test_query_as_names_row_and_column() {
+    smol::block_on(async {
+        let mut connection = Connection::create(std::net::IpAddr::V4(Ipv4Addr::LOCALHOST), None)
+            .await
+            .unwrap();
+
+        let products = connection
+            .query_as::<Product>(
+                "SELECT * FROM (VALUES ('apple', '1'), ('pear', '2')) AS t(name, price)",
+            )
+            .await
+            .unwrap();
+        assert_eq!(products[1].name, "pear");
+        assert_eq!(products[1].price, 2);
+
+        let error = connection
+            .query_as::<Product>(
+                "SELECT * FROM (VALUES ('apple', '1'), ('pear', '2'), ('plum', 'x')) AS t(name, price)",
+            )
+            .await
+            .unwrap_err();
+
+        let Error::FromRowError(error) = error else {
+            panic!("expected a row conversion error, got {error:?}");
+        };
+        assert_eq!(error.row(), 2);
+        assert_eq!(error.column(), "price");
+        assert!(error
+            .to_string()
+            .starts_with("row 2, column `price`: invalid digit"));
+    });
+}
+
+#[test]
+fn test_rows_as_tuples_and_strings() {
+    smol::block_on(async {
+        let mut connection = Connection::create(std::net::IpAddr::V4(Ipv4Addr::LOCALHOST), None)
+            .await
+            .unwrap();
+
+        let query =
+            r"SELECT 1::int4 AS id, 'one' AS label, NULL::int4 AS missing, '\x0aff'::bytea AS raw";
+        let rows = connection.query(query).await.unwrap();
+        let row = &rows[0];
+
+        assert_eq!(row.try_get::<i32>(0).unwrap(), 1);
+        assert_eq!(row.try_get::<String>("label").unwrap(), "one");
+        let column = "label".to_string();
+        assert_eq!(row.try_get::<&str>(&column).unwrap(), "one");
+        assert_eq!(row.try_get::<&str>(column).unwrap(), "one");
+        let (id, label, missing) = row.try_into_tuple::<(i32, String, Option<i32>)>().unwrap();
+        assert_eq!((id, label.as_str(), missing), (1, "one", None));
+        assert_eq!(row.to_vec_strings(), ["1", "one", "NULL", r"\x0aff"]);
+        assert_eq!(row.get_raw(1), Some(&b"one"[..]));
+        assert_eq!(row.get_raw("missing"), None);
+        assert_eq!(row.get_raw(4), None);
+
+        // Clones share the fields instead of copying them
+        let clone = row.clone();
+        assert!(std::ptr::eq(
+            clone.get_raw(1).unwrap(),
+            row.get_raw(1).unwrap()
+        ));
+
+        // Errors name the column, whether it was picked by name or position
+        let error = row.try_get::<i32>(1).unwrap_err();
+        assert_eq!(error.column(), "label");
+        let error = row.try_get::<i32>(4).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "column `4`: row doesn't contain field `4`"
+        );
+
+        let products = connection
+            .query_as::<(String, i32)>("SELECT 'apple', 1::int4")
+            .await
+            .unwrap();
+        assert_eq!(products, [("apple".to_string(), 1)]);
+
+        // Borrowing tuples read text without allocating
+        let rows = connection
+            .query("SELECT 'pear', 2::int4 UNION ALL SELECT 'plum', 3")
+            .await
+            .unwrap();
+        let borrowed = rows
+            .iter()
+            .map(|row| row.try_get_tuple::<(&str, i32)>())
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(borrowed, [("pear", 2), ("plum", 3)]);
+        assert!(std::ptr::eq(
+            borrowed[0].0.as_bytes(),
+            rows[0].get_raw(0).unwrap()
+        ));
+        let error = rows[0].try_get_tuple::<(i32, &str)>().unwrap_err();
+        assert_eq!(error.column(), "?column?");
+
+        // Binary values are rendered like the server renders text
+        let config = ConnectionConfig::new().default_result_format(FormatCode::Binary);
+        let mut connection = Connection::connect(config).await.unwrap();
+        let rows = connection.query_typed(query, &[]).await.unwrap();
+        assert_eq!(rows[0].columns()[0].format_code, FormatCode::Binary);
+        assert_eq!(rows[0].get_raw("id"), Some(&1i32.to_be_bytes()[..]));
+        assert_eq!(rows[0].get_raw("raw"), Some(&[0x0a, 0xff][..]));
+        assert_eq!(rows[0].to_vec_strings(), ["1", "one", "NULL", r"\x0aff"]);
+    });
+}
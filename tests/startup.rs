@@ -0,0 +1,42 @@
+use futures_lite::{AsyncReadExt, AsyncWriteExt};
+use smol::net::TcpListener;
+use smol_pg::{
+    config::ConnectionConfig,
+    connection::{AuthenticationError, Connection},
+    Error,
+};
+
+#[test]
+fn test_unsupported_sasl_mechanism() {
+    smol::block_on(async {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        // A fake server that only offers a SASL mechanism we don't know
+        let server = smol::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+
+            let mut length = [0; 4];
+            stream.read_exact(&mut length).await.unwrap();
+            let mut startup = vec![0; i32::from_be_bytes(length) as usize - 4];
+            stream.read_exact(&mut startup).await.unwrap();
+
+            let mut response = vec![b'R'];
+            response.extend_from_slice(&23i32.to_be_bytes());
+            response.extend_from_slice(&10i32.to_be_bytes());
+            response.extend_from_slice(b"SCRAM-SHA-512\0\0");
+            stream.write_all(&response).await.unwrap();
+        });
+
+        let config = ConnectionConfig::new().host("127.0.0.1").port(port);
+        let error = Connection::connect(config).await.err().unwrap();
+        server.await;
+
+        let Error::AuthenticationError(AuthenticationError::NoSupportedSaslMechanism(offered)) =
+            &error
+        else {
+            panic!("expected an authentication error, got {error:?}");
+        };
+        assert_eq!(offered, &["SCRAM-SHA-512"]);
+    });
+}
@@ -0,0 +1,55 @@
+#![cfg(feature = "trace-protocol")]
+
+use std::{
+    io::Write,
+    sync::{Arc, Mutex},
+};
+
+use smol_pg::{
+    config::{ConnectionConfig, ProtocolTrace},
+    connection::Connection,
+};
+
+/// Collects everything the subscriber writes.
+#[derive(Clone, Default)]
+struct Output(Arc<Mutex<Vec<u8>>>);
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn test_protocol_trace_redacts_authentication() {
+    let output = Output::default();
+    let writer = output.clone();
+    let subscriber = tracing_subscriber::fmt()
+        .with_max_level(tracing::Level::TRACE)
+        .with_ansi(false)
+        .with_writer(move || writer.clone())
+        .finish();
+
+    tracing::subscriber::with_default(subscriber, || {
+        smol::block_on(async {
+            let config = ConnectionConfig::new()
+                .host("127.0.0.1")
+                .protocol_trace(ProtocolTrace::Redacted);
+            let mut connection = Connection::connect(config).await.unwrap();
+
+            connection.query("SELECT 1").await.unwrap();
+        })
+    });
+
+    let output = String::from_utf8(output.0.lock().unwrap().clone()).unwrap();
+
+    // The query is dumped: `Q`, the length and `SELECT 1`
+    assert!(output.contains("payload=51 00 00 00 0d 53 45 4c 45 43 54 20 31 00"));
+    // The server's authentication response is not
+    assert!(output.contains("message_type=Some('R') length=8 payload=\"<redacted>\""));
+}
@@ -0,0 +1,870 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use smol_pg::{
+    config::ConnectionConfig,
+    connection::Connection,
+    protocol::{
+        message::{parsing::FromSql, server::FormatCode},
+        oids,
+        types::{BitString, Money, Numeric, PgInterval, PgLsn, PgPoint, PgValue, TimeTz},
+    },
+};
+
+#[test]
+fn test_timetz_text() {
+    let value = TimeTz::from_text(b"12:34:56.5+02").unwrap();
+    assert_eq!(
+        value.micros,
+        (12 * 3600 + 34 * 60 + 56) * 1_000_000 + 500_000
+    );
+    assert_eq!(value.zone_offset_secs, 2 * 3600);
+
+    let value = TimeTz::from_text(b"01:00:00-05:30").unwrap();
+    assert_eq!(value.zone_offset_secs, -(5 * 3600 + 30 * 60));
+
+    for malformed in [
+        &b"12:00:00+999999999"[..],
+        b"12:00:00+1:99999999",
+        b"12:00:00+-5",
+        b"12:00:00-+5",
+        b"12:00:00+05:",
+    ] {
+        assert!(TimeTz::from_text(malformed).is_err());
+    }
+}
+
+#[test]
+fn test_timetz_binary() {
+    let mut binary = Vec::new();
+    binary.extend_from_slice(&45_296_000_000i64.to_be_bytes());
+    // The server counts seconds west of Greenwich
+    binary.extend_from_slice(&(-7200i32).to_be_bytes());
+
+    let value = TimeTz::from_binary(&binary).unwrap();
+    assert_eq!(value.micros, 45_296_000_000);
+    assert_eq!(value.zone_offset_secs, 7200);
+
+    let mut binary = Vec::new();
+    binary.extend_from_slice(&0i64.to_be_bytes());
+    binary.extend_from_slice(&i32::MIN.to_be_bytes());
+    assert!(TimeTz::from_binary(&binary).is_err());
+}
+
+#[test]
+fn test_timetz_from_server() {
+    smol::block_on(async {
+        let mut connection = Connection::create(std::net::IpAddr::V4(Ipv4Addr::LOCALHOST), None)
+            .await
+            .unwrap();
+
+        let rows = connection
+            .query("SELECT '12:34:56+02'::timetz AS t")
+            .await
+            .unwrap();
+        let value = rows[0].get_and_parse::<TimeTz>("t").unwrap();
+        assert_eq!(value.zone_offset_secs, 7200);
+    });
+}
+
+#[test]
+fn test_inet() {
+    let address = IpAddr::from_text(b"192.168.0.0/24").unwrap();
+    assert_eq!(address, IpAddr::V4(Ipv4Addr::new(192, 168, 0, 0)));
+    assert_eq!(IpAddr::from_text(b"::1").unwrap(), Ipv6Addr::LOCALHOST);
+
+    let binary = [2, 32, 0, 4, 10, 0, 0, 1];
+    assert_eq!(
+        IpAddr::from_binary(&binary).unwrap(),
+        Ipv4Addr::new(10, 0, 0, 1)
+    );
+
+    let mut binary = vec![3, 128, 0, 16];
+    binary.extend_from_slice(&Ipv6Addr::LOCALHOST.octets());
+    assert_eq!(IpAddr::from_binary(&binary).unwrap(), Ipv6Addr::LOCALHOST);
+
+    assert!(IpAddr::from_binary(&[7, 32, 0, 4, 10, 0, 0, 1]).is_err());
+    // The address must be exactly as long as its family and the length say
+    assert!(IpAddr::from_binary(&[2, 32, 0, 5, 10, 0, 0, 1, 0]).is_err());
+    assert!(IpAddr::from_binary(&[2, 32, 0, 4, 10, 0, 0, 1, 0]).is_err());
+}
+
+/// Encode a `numeric` in binary format.
+fn numeric_binary(digits: &[i16], weight: i16, sign: u16, scale: i16) -> Vec<u8> {
+    let mut binary = Vec::new();
+    binary.extend_from_slice(&(digits.len() as i16).to_be_bytes());
+    binary.extend_from_slice(&weight.to_be_bytes());
+    binary.extend_from_slice(&sign.to_be_bytes());
+    binary.extend_from_slice(&scale.to_be_bytes());
+    for digit in digits {
+        binary.extend_from_slice(&digit.to_be_bytes());
+    }
+    binary
+}
+
+#[test]
+fn test_numeric() {
+    let value = Numeric::from_binary(&numeric_binary(&[1, 2345, 6780], 1, 0, 3)).unwrap();
+    assert_eq!(value.as_str(), "12345.678");
+
+    let value = Numeric::from_binary(&numeric_binary(&[12], -1, 0x4000, 4)).unwrap();
+    assert_eq!(value.as_str(), "-0.0012");
+
+    let value = Numeric::from_binary(&numeric_binary(&[1], 2, 0, 0)).unwrap();
+    assert_eq!(value.as_str(), "100000000");
+
+    let value = Numeric::from_binary(&numeric_binary(&[], 0, 0xD000, 0)).unwrap();
+    assert_eq!(f64::try_from(value).unwrap(), f64::INFINITY);
+
+    let exact = "123456789012345678901234567890.123";
+    let value = Numeric::from_text(exact.as_bytes()).unwrap();
+    assert_eq!(value.as_str(), exact);
+    assert_eq!(f64::try_from(value).unwrap(), 1.2345678901234568e29);
+}
+
+#[test]
+fn test_text_array_with_nulls_and_quotes() {
+    let value = Vec::<Option<String>>::from_text(br#"{hello,"a,b",NULL}"#).unwrap();
+    assert_eq!(
+        value,
+        vec![Some("hello".to_string()), Some("a,b".to_string()), None]
+    );
+
+    // A NULL element can't be read without an `Option`
+    assert!(Vec::<String>::from_text(b"{a,NULL}").is_err());
+    assert_eq!(
+        Vec::<String>::from_text(br#"{"NULL"}"#).unwrap(),
+        vec!["NULL"]
+    );
+}
+
+#[test]
+fn test_text_array_quoting() {
+    let value = Vec::<Option<String>>::from_text(br#"{"a,b","c\"d",NULL,e}"#).unwrap();
+    assert_eq!(
+        value,
+        vec![
+            Some("a,b".to_string()),
+            Some(r#"c"d"#.to_string()),
+            None,
+            Some("e".to_string()),
+        ]
+    );
+
+    let value = Vec::<String>::from_text(br#"{"{x}","back\\slash","",NULL2}"#).unwrap();
+    assert_eq!(value, vec!["{x}", r"back\slash", "", "NULL2"]);
+
+    // No elements at all, unlike a single empty one
+    assert_eq!(
+        Vec::<String>::from_text(b"{}").unwrap(),
+        Vec::<String>::new()
+    );
+    assert_eq!(Vec::<String>::from_text(br#"{""}"#).unwrap(), vec![""]);
+
+    // Quotes and escapes in nested arrays are handled by the inner arrays
+    let value = Vec::<Vec<Option<String>>>::from_text(br#"{{"a,b","}"},{"c\\d",NULL}}"#).unwrap();
+    assert_eq!(
+        value,
+        vec![
+            vec![Some("a,b".to_string()), Some("}".to_string())],
+            vec![Some(r"c\d".to_string()), None],
+        ]
+    );
+
+    for invalid in [
+        &br#"{a,,b}"#[..],
+        br#"{,}"#,
+        br#"{"a"b}"#,
+        br#"{a"b"}"#,
+        br#"{"a}"#,
+        br#"{"a\"}"#,
+    ] {
+        assert!(Vec::<Option<String>>::from_text(invalid).is_err());
+    }
+}
+
+#[test]
+fn test_arrays_from_server() {
+    smol::block_on(async {
+        let mut connection = Connection::create(std::net::IpAddr::V4(Ipv4Addr::LOCALHOST), None)
+            .await
+            .unwrap();
+
+        let rows = connection
+            .query(
+                "SELECT ARRAY['hello', 'a,b', NULL, 'say \"hi\"']::text[] AS text, \
+                 ARRAY['pg_class', 'pg_type']::name[] AS name, \
+                 ARRAY[[1, 2], [3, 4]] AS matrix, \
+                 ARRAY['a,b', 'c\"d', 'NULL', 'e\\f', '{g}', '', ' h ']::text[] AS tricky, \
+                 '{}'::text[] AS empty",
+            )
+            .await
+            .unwrap();
+
+        let text = rows[0]
+            .get_and_parse::<Vec<Option<String>>>("text")
+            .unwrap();
+        assert_eq!(
+            text,
+            vec![
+                Some("hello".to_string()),
+                Some("a,b".to_string()),
+                None,
+                Some("say \"hi\"".to_string()),
+            ]
+        );
+
+        let name = rows[0].get_and_parse::<Vec<String>>("name").unwrap();
+        assert_eq!(name, vec!["pg_class", "pg_type"]);
+
+        let matrix = rows[0].get_and_parse::<Vec<Vec<i32>>>("matrix").unwrap();
+        assert_eq!(matrix, vec![vec![1, 2], vec![3, 4]]);
+
+        let tricky = rows[0].get_and_parse::<Vec<String>>("tricky").unwrap();
+        assert_eq!(tricky, ["a,b", "c\"d", "NULL", "e\\f", "{g}", "", " h "]);
+
+        let empty = rows[0].get_and_parse::<Vec<String>>("empty").unwrap();
+        assert!(empty.is_empty());
+    });
+}
+
+#[cfg(feature = "uuid")]
+#[test]
+fn test_uuid_array_binary() {
+    use uuid::Uuid;
+
+    let first = Uuid::from_u128(0x0123_4567_89ab_cdef_0123_4567_89ab_cdef);
+    let second = Uuid::nil();
+
+    // One dimension, no NULLs, element type `uuid`
+    let mut binary = Vec::new();
+    for value in [1i32, 0, 2950, 2, 1] {
+        binary.extend_from_slice(&value.to_be_bytes());
+    }
+    for uuid in [first, second] {
+        binary.extend_from_slice(&16i32.to_be_bytes());
+        binary.extend_from_slice(uuid.as_bytes());
+    }
+
+    assert_eq!(
+        Vec::<Uuid>::from_binary(&binary).unwrap(),
+        vec![first, second]
+    );
+
+    let text = format!("{{{},{}}}", first, second);
+    assert_eq!(
+        Vec::<Uuid>::from_text(text.as_bytes()).unwrap(),
+        vec![first, second]
+    );
+}
+
+#[cfg(feature = "json")]
+#[test]
+fn test_json_size_limit() {
+    use serde_json::Value;
+    use smol_pg::protocol::types::Json;
+
+    let text = br#"{"key": "a value longer than the limit"}"#;
+
+    let Json(value) = Json::<Value>::from_text(text).unwrap();
+    assert_eq!(value["key"], "a value longer than the limit");
+
+    let error = Json::<Value, 16>::from_text(text).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "JSON value of 40 bytes exceeds the limit of 16 bytes"
+    );
+
+    // The binary `jsonb` version byte doesn't count
+    let mut binary = vec![1];
+    binary.extend_from_slice(br#"{"a": 1}"#);
+    assert!(Json::<Value, 8>::from_binary(&binary).is_ok());
+}
+
+#[cfg(feature = "json")]
+#[test]
+fn test_jsonb_from_server() {
+    use serde_json::Value;
+    use smol_pg::protocol::types::Json;
+
+    smol::block_on(async {
+        let mut connection = Connection::create(std::net::IpAddr::V4(Ipv4Addr::LOCALHOST), None)
+            .await
+            .unwrap();
+
+        let rows = connection
+            .query(r#"SELECT '{"list": [1, 2], "nested": {"x": null}}'::jsonb AS value, to_jsonb(repeat('x', 100)) AS big"#)
+            .await
+            .unwrap();
+
+        let Json(value) = rows[0].get_and_parse::<Json<Value>>("value").unwrap();
+        assert_eq!(value["list"][1], 2);
+        assert!(value["nested"]["x"].is_null());
+
+        assert!(rows[0].get_and_parse::<Json<Value, 64>>("big").is_err());
+    });
+}
+
+#[test]
+fn test_fixed_size_byte_arrays() {
+    let mac = [0x08, 0x00, 0x2b, 0x01, 0x02, 0x03];
+    assert_eq!(<[u8; 6]>::from_binary(&mac).unwrap(), mac);
+    assert!(<[u8; 6]>::from_binary(&mac[..5]).is_err());
+    assert_eq!(<[u8; 6]>::from_text(b"08:00:2b:01:02:03").unwrap(), mac);
+
+    let key = std::array::from_fn::<u8, 16, _>(|index| index as u8 * 17);
+    assert_eq!(<[u8; 16]>::from_binary(&key).unwrap(), key);
+    assert_eq!(
+        <[u8; 16]>::from_text(b"\\x00112233445566778899aabbccddeeff").unwrap(),
+        key
+    );
+    assert!(<[u8; 16]>::from_text(b"\\x0011").is_err());
+    assert!(<[u8; 2]>::from_text(b"\\x00zz").is_err());
+}
+
+#[test]
+fn test_fixed_size_byte_arrays_from_server() {
+    smol::block_on(async {
+        let mut connection = Connection::create(std::net::IpAddr::V4(Ipv4Addr::LOCALHOST), None)
+            .await
+            .unwrap();
+
+        let rows = connection
+            .query(
+                "SELECT '08:00:2b:01:02:03'::macaddr AS mac, \
+                 decode('00112233445566778899aabbccddeeff', 'hex') AS key",
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(
+            rows[0].get_and_parse::<[u8; 6]>("mac").unwrap(),
+            [0x08, 0x00, 0x2b, 0x01, 0x02, 0x03]
+        );
+        assert_eq!(rows[0].get_and_parse::<[u8; 16]>("key").unwrap()[15], 0xff);
+    });
+}
+
+#[test]
+fn test_unsigned_integers() {
+    assert_eq!(u32::from_text(b"4294967295").unwrap(), u32::MAX);
+    assert_eq!(
+        u32::from_binary(&[0xff, 0xff, 0xff, 0xfe]).unwrap(),
+        u32::MAX - 1
+    );
+    assert!(u32::from_text(b"-1").is_err());
+
+    assert_eq!(u64::from_text(b"18446744073709551615").unwrap(), u64::MAX);
+    assert_eq!(u64::from_binary(&42i64.to_be_bytes()).unwrap(), 42);
+    assert!(u64::from_binary(&(-1i64).to_be_bytes()).is_err());
+
+    assert_eq!(u8::from_text(b"255").unwrap(), 255);
+    assert!(u8::from_text(b"256").is_err());
+    assert_eq!(u8::from_binary(&7i16.to_be_bytes()).unwrap(), 7);
+    assert!(u8::from_binary(&(-7i16).to_be_bytes()).is_err());
+}
+
+#[test]
+fn test_oid_from_server() {
+    smol::block_on(async {
+        let mut connection = Connection::create(std::net::IpAddr::V4(Ipv4Addr::LOCALHOST), None)
+            .await
+            .unwrap();
+
+        let rows = connection
+            .query("SELECT oid FROM pg_type WHERE typname = 'text'")
+            .await
+            .unwrap();
+        assert_eq!(rows[0].get_and_parse::<u32>("oid").unwrap(), 25);
+    });
+}
+
+#[test]
+fn test_type_names_match_server() {
+    smol::block_on(async {
+        let mut connection = Connection::create(std::net::IpAddr::V4(Ipv4Addr::LOCALHOST), None)
+            .await
+            .unwrap();
+
+        let rows = connection
+            .query("SELECT oid::int4 AS oid, typname::text AS name FROM pg_type")
+            .await
+            .unwrap();
+        let mut known = 0;
+        for row in &rows {
+            let oid = row.get_and_parse::<i32>("oid").unwrap();
+            if let Some(name) = oids::type_name(oid) {
+                assert_eq!(name, row.get_and_parse::<&str>("name").unwrap());
+                known += 1;
+            }
+        }
+        assert!(known > 60);
+        assert_eq!(oids::type_name(-1), None);
+
+        let statement = connection
+            .prepare("SELECT 1::int4 AS a, now() AS b")
+            .await
+            .unwrap();
+        let names = statement
+            .columns()
+            .iter()
+            .map(|column| column.type_name())
+            .collect::<Vec<_>>();
+        assert_eq!(names, [Some("int4"), Some("timestamptz")]);
+    });
+}
+
+#[test]
+fn test_column_sizes() {
+    smol::block_on(async {
+        let mut connection = Connection::create(std::net::IpAddr::V4(Ipv4Addr::LOCALHOST), None)
+            .await
+            .unwrap();
+
+        let statement = connection
+            .prepare("SELECT 1::int8 AS a, 'x'::text AS b, 'y'::cstring AS c")
+            .await
+            .unwrap();
+        let sizes = statement
+            .columns()
+            .iter()
+            .map(|column| (column.is_variable_length(), column.fixed_size()))
+            .collect::<Vec<_>>();
+        assert_eq!(sizes, [(false, Some(8)), (true, None), (true, None)]);
+    });
+}
+
+#[test]
+fn test_records() {
+    smol::block_on(async {
+        let mut connection = Connection::create(std::net::IpAddr::V4(Ipv4Addr::LOCALHOST), None)
+            .await
+            .unwrap();
+
+        let rows = connection
+            .query("SELECT ROW(1, 'say \"hi\", then (leave)', NULL::int, '') AS record")
+            .await
+            .unwrap();
+
+        let record = rows[0]
+            .get_and_parse::<(i32, String, Option<i32>, String)>("record")
+            .unwrap();
+        assert_eq!(
+            record,
+            (
+                1,
+                "say \"hi\", then (leave)".to_string(),
+                None,
+                String::new()
+            )
+        );
+
+        // The number of fields must match and NULL needs an `Option`
+        assert!(rows[0].get_and_parse::<(i32, String)>("record").is_err());
+        assert!(rows[0]
+            .get_and_parse::<(i32, String, i32, String)>("record")
+            .is_err());
+    });
+}
+
+#[test]
+fn test_record_binary() {
+    let mut binary = 2i32.to_be_bytes().to_vec();
+    // A `text` field and a `NULL` `oid` field
+    binary.extend_from_slice(&25i32.to_be_bytes());
+    binary.extend_from_slice(&2i32.to_be_bytes());
+    binary.extend_from_slice(b"hi");
+    binary.extend_from_slice(&26i32.to_be_bytes());
+    binary.extend_from_slice(&(-1i32).to_be_bytes());
+
+    assert_eq!(
+        <(String, Option<u32>)>::from_binary(&binary).unwrap(),
+        ("hi".to_string(), None)
+    );
+    assert!(<(String, u32)>::from_binary(&binary).is_err());
+    assert!(<(String, Option<u32>, String)>::from_binary(&binary).is_err());
+}
+
+#[test]
+fn test_interval() {
+    smol::block_on(async {
+        let mut connection = Connection::create(std::net::IpAddr::V4(Ipv4Addr::LOCALHOST), None)
+            .await
+            .unwrap();
+
+        let rows = connection
+            .query(
+                "SELECT '1 year 2 mons 3 days 04:05:06.5'::interval AS a, \
+                 '-1 day +02:03:00'::interval AS b, \
+                 '-00:00:01.25'::interval AS c, \
+                 '0'::interval AS d",
+            )
+            .await
+            .unwrap();
+
+        let interval = |name| rows[0].get_and_parse::<PgInterval>(name).unwrap();
+        assert_eq!(
+            interval("a"),
+            PgInterval {
+                months: 14,
+                days: 3,
+                micros: 14_706_500_000,
+            }
+        );
+        assert_eq!(
+            interval("b"),
+            PgInterval {
+                months: 0,
+                days: -1,
+                micros: 7_380_000_000,
+            }
+        );
+        assert_eq!(interval("c").micros, -1_250_000);
+        assert_eq!(interval("d"), PgInterval::default());
+
+        // Months have no fixed length and durations can't be negative
+        assert_eq!(interval("a").to_duration(), None);
+        assert_eq!(interval("b").to_duration(), None);
+        assert_eq!(interval("d").to_duration(), Some(std::time::Duration::ZERO));
+    });
+}
+
+#[test]
+fn test_interval_overflow() {
+    assert!(PgInterval::from_text(b"300000000 years").is_err());
+    assert!(PgInterval::from_text(b"2147483647 mons 1 mon").is_err());
+    assert!(PgInterval::from_text(b"2147483647 days 1 day").is_err());
+    assert!(PgInterval::from_text(b"3000000000000:00:00").is_err());
+    assert!(PgInterval::from_text(b"2000000000:00:00 2000000000:00:00").is_err());
+}
+
+#[test]
+fn test_interval_binary() {
+    let mut binary = 90_000_000i64.to_be_bytes().to_vec();
+    binary.extend_from_slice(&2i32.to_be_bytes());
+    binary.extend_from_slice(&0i32.to_be_bytes());
+
+    let interval = PgInterval::from_binary(&binary).unwrap();
+    assert_eq!(
+        interval,
+        PgInterval {
+            months: 0,
+            days: 2,
+            micros: 90_000_000,
+        }
+    );
+    assert_eq!(
+        interval.to_duration(),
+        Some(std::time::Duration::from_secs(2 * 24 * 3600 + 90))
+    );
+}
+
+#[test]
+fn test_floats() {
+    smol::block_on(async {
+        let mut connection = Connection::create(std::net::IpAddr::V4(Ipv4Addr::LOCALHOST), None)
+            .await
+            .unwrap();
+
+        // The spellings the server produces, and what they parse to
+        let cases = [
+            ("1e10", 1e10),
+            ("1.5e-3", 1.5e-3),
+            ("-0.1", -0.1),
+            ("1e30", 1e30),
+            ("123456789012345678", 123456789012345678.0),
+            ("'Infinity'", f64::INFINITY),
+            ("'-Infinity'", f64::NEG_INFINITY),
+        ];
+        for (literal, expected) in cases {
+            let rows = connection
+                .query(&format!(
+                    "SELECT {literal}::float8 AS d, {literal}::float4 AS r"
+                ))
+                .await
+                .unwrap();
+            assert_eq!(rows[0].get_and_parse::<f64>("d").unwrap(), expected);
+            assert_eq!(
+                rows[0].get_and_parse::<f32>("r").unwrap(),
+                expected as f32,
+                "{literal}"
+            );
+        }
+
+        let rows = connection
+            .query("SELECT 'NaN'::float8 AS d, 'NaN'::float4 AS r")
+            .await
+            .unwrap();
+        assert!(rows[0].get_and_parse::<f64>("d").unwrap().is_nan());
+        assert!(rows[0].get_and_parse::<f32>("r").unwrap().is_nan());
+    });
+}
+
+#[test]
+fn test_float_spellings() {
+    assert_eq!(f64::from_text(b"Infinity").unwrap(), f64::INFINITY);
+    assert_eq!(f64::from_text(b"1.5e+20").unwrap(), 1.5e20);
+    for text in ["inf", "nan", "infinity", "1,5", ""] {
+        assert!(f64::from_text(text.as_bytes()).is_err(), "{text}");
+    }
+
+    assert_eq!(f64::from_binary(&1.5f64.to_be_bytes()).unwrap(), 1.5);
+    assert_eq!(f64::from_binary(&1.5f32.to_be_bytes()).unwrap(), 1.5);
+    assert_eq!(f32::from_binary(&(-2.5f32).to_be_bytes()).unwrap(), -2.5);
+}
+
+#[cfg(feature = "chrono")]
+#[test]
+fn test_date_and_time() {
+    use chrono::{NaiveDate, NaiveTime};
+
+    let date = |year, month, day| NaiveDate::from_ymd_opt(year, month, day).unwrap();
+
+    // Binary dates count days from 2000-01-01
+    assert_eq!(
+        NaiveDate::from_binary(&0i32.to_be_bytes()).unwrap(),
+        date(2000, 1, 1)
+    );
+    assert_eq!(
+        NaiveDate::from_binary(&8825i32.to_be_bytes()).unwrap(),
+        date(2024, 2, 29)
+    );
+    assert_eq!(
+        NaiveDate::from_binary(&(-1i32).to_be_bytes()).unwrap(),
+        date(1999, 12, 31)
+    );
+    // `infinity`
+    assert!(NaiveDate::from_binary(&i32::MAX.to_be_bytes()).is_err());
+
+    assert_eq!(
+        NaiveDate::from_text(b"2024-02-29").unwrap(),
+        date(2024, 2, 29)
+    );
+    assert_eq!(
+        NaiveDate::from_text(b"0044-03-15 BC").unwrap(),
+        date(-43, 3, 15)
+    );
+    assert!(NaiveDate::from_text(b"2023-02-29").is_err());
+
+    // Binary times count microseconds since midnight
+    let time = NaiveTime::from_hms_micro_opt(13, 14, 15, 500_000).unwrap();
+    assert_eq!(
+        NaiveTime::from_binary(&47_655_500_000i64.to_be_bytes()).unwrap(),
+        time
+    );
+    assert_eq!(NaiveTime::from_text(b"13:14:15.5").unwrap(), time);
+    assert_eq!(NaiveTime::from_text(b"00:00:00").unwrap(), NaiveTime::MIN);
+    assert!(NaiveTime::from_text(b"24:00:00").is_err());
+}
+
+#[cfg(feature = "chrono")]
+#[test]
+fn test_date_and_time_from_server() {
+    use chrono::{NaiveDate, NaiveTime};
+
+    smol::block_on(async {
+        let mut connection = Connection::create(std::net::IpAddr::V4(Ipv4Addr::LOCALHOST), None)
+            .await
+            .unwrap();
+
+        let rows = connection
+            .query("SELECT '2000-01-01'::date AS epoch, '1970-06-15'::date AS d, '23:59:59.999999'::time AS t")
+            .await
+            .unwrap();
+
+        assert_eq!(
+            rows[0].get_and_parse::<NaiveDate>("epoch").unwrap(),
+            NaiveDate::from_ymd_opt(2000, 1, 1).unwrap()
+        );
+        assert_eq!(
+            rows[0].get_and_parse::<NaiveDate>("d").unwrap(),
+            NaiveDate::from_ymd_opt(1970, 6, 15).unwrap()
+        );
+        assert_eq!(
+            rows[0].get_and_parse::<NaiveTime>("t").unwrap(),
+            NaiveTime::from_hms_micro_opt(23, 59, 59, 999_999).unwrap()
+        );
+    });
+}
+
+#[test]
+fn test_pg_lsn() {
+    let lsn = PgLsn::from_text(b"0/16B3748").unwrap();
+    assert_eq!(lsn, PgLsn(0x16B3748));
+    assert_eq!(lsn.to_string(), "0/16B3748");
+
+    let lsn = PgLsn::from_binary(&0x1_0000_00FFu64.to_be_bytes()).unwrap();
+    assert_eq!(lsn.to_string(), "1/FF");
+    assert_eq!(PgLsn::from_text(lsn.to_string().as_bytes()).unwrap(), lsn);
+
+    for text in ["16B3748", "0/", "0/xyz", "1/2/3"] {
+        assert!(PgLsn::from_text(text.as_bytes()).is_err(), "{text}");
+    }
+
+    smol::block_on(async {
+        let mut connection = Connection::create(std::net::IpAddr::V4(Ipv4Addr::LOCALHOST), None)
+            .await
+            .unwrap();
+
+        let rows = connection
+            .query("SELECT 'FF/ABCDEF01'::pg_lsn AS lsn")
+            .await
+            .unwrap();
+        assert_eq!(
+            rows[0].get_and_parse::<PgLsn>("lsn").unwrap(),
+            PgLsn(0xFF_ABCD_EF01)
+        );
+    });
+}
+
+#[test]
+fn test_bit_string() {
+    let bits = BitString::from_text(b"10110").unwrap();
+    assert_eq!(bits, BitString(vec![true, false, true, true, false]));
+    assert_eq!(bits.to_string(), "10110");
+    assert_eq!(BitString::from_text(b"").unwrap(), BitString::default());
+
+    // 10 bits, the last byte is padded with zeros
+    let bits = BitString::from_binary(&[0, 0, 0, 10, 0b1010_0000, 0b0100_0000]).unwrap();
+    assert_eq!(bits.to_string(), "1010000001");
+
+    let error = BitString::from_text(b"10201").unwrap_err();
+    assert!(error.to_string().contains("invalid bit `2`"), "{error}");
+    // Missing and surplus bytes, and a negative length
+    assert!(BitString::from_binary(&[0, 0, 0, 9, 0xff]).is_err());
+    assert!(BitString::from_binary(&[0, 0, 0, 8, 0xff, 0]).is_err());
+    assert!(BitString::from_binary(&(-1i32).to_be_bytes()).is_err());
+
+    smol::block_on(async {
+        let config = ConnectionConfig::new().default_result_format(FormatCode::Binary);
+        let mut connection = Connection::connect(config).await.unwrap();
+
+        let query = "SELECT B'10110'::bit(5) AS bit, B'1000000001'::varbit AS varbit";
+        let binary = connection.query_typed(query, &[]).await.unwrap();
+        let text = connection.query(query).await.unwrap();
+
+        for row in [&binary[0], &text[0]] {
+            let bit = row.get_and_parse::<BitString>("bit").unwrap();
+            assert_eq!(bit.to_string(), "10110");
+            let varbit = row.get_and_parse::<BitString>("varbit").unwrap();
+            assert_eq!(varbit.to_string(), "1000000001");
+        }
+    });
+}
+
+#[test]
+fn test_array_element_types_match_server() {
+    smol::block_on(async {
+        let mut connection = Connection::create(std::net::IpAddr::V4(Ipv4Addr::LOCALHOST), None)
+            .await
+            .unwrap();
+
+        let rows = connection
+            .query("SELECT oid::int4 AS oid, typelem::int4 AS element FROM pg_type")
+            .await
+            .unwrap();
+        let mut known = 0;
+        for row in &rows {
+            let oid = row.get_and_parse::<i32>("oid").unwrap();
+            if let Some(element) = oids::array_element_type(oid) {
+                assert_eq!(element, row.get_and_parse::<i32>("element").unwrap());
+                known += 1;
+            }
+        }
+        assert!(known > 30);
+
+        assert_eq!(oids::array_element_type(oids::INT4_ARRAY), Some(oids::INT4));
+        assert_eq!(oids::array_element_type(oids::INT4), None);
+    });
+}
+
+#[test]
+fn test_dynamic_values() {
+    smol::block_on(async {
+        let config = ConnectionConfig::new().default_result_format(FormatCode::Binary);
+        let mut connection = Connection::connect(config).await.unwrap();
+
+        let query =
+            r"SELECT 42::int4, true, 'hi'::varchar, '\x00ff'::bytea, NULL::int4, 1.5::float8";
+        let binary = connection.query_typed(query, &[]).await.unwrap();
+        let text = connection.query(query).await.unwrap();
+
+        for (row, float) in [
+            (&binary[0], 1.5f64.to_be_bytes().to_vec()),
+            (&text[0], b"1.5".to_vec()),
+        ] {
+            let values = (0..row.columns().len())
+                .map(|index| row.get_value(index).unwrap())
+                .collect::<Vec<_>>();
+            assert_eq!(
+                values,
+                [
+                    PgValue::Int4(42),
+                    PgValue::Bool(true),
+                    PgValue::Text("hi".to_string()),
+                    PgValue::Bytea(vec![0x00, 0xff]),
+                    PgValue::Null,
+                    PgValue::Unknown(float),
+                ]
+            );
+        }
+    });
+
+    assert!(PgValue::decode(oids::BYTEA, FormatCode::Text, Some(br"\x0")).is_err());
+    assert!(PgValue::decode(oids::BOOL, FormatCode::Text, Some(b"yes")).is_err());
+}
+
+#[test]
+fn test_money() {
+    assert_eq!(Money::from_text(b"$1,234.56").unwrap(), Money(123456));
+    assert_eq!(Money::from_text(b"-$0.05").unwrap(), Money(-5));
+    assert_eq!(Money::from_text(b"($12.00)").unwrap(), Money(-1200));
+    assert_eq!(
+        Money::from_text("-1.234,56 €".as_bytes()).unwrap(),
+        Money(-123456)
+    );
+    assert!(Money::from_text(b"$").is_err());
+
+    smol::block_on(async {
+        let config = ConnectionConfig::new().default_result_format(FormatCode::Binary);
+        let mut connection = Connection::connect(config).await.unwrap();
+        connection
+            .batch_execute("SET lc_monetary = 'C'")
+            .await
+            .unwrap();
+
+        let query = "SELECT '-1234.56'::money AS money";
+        let binary = connection.query_typed(query, &[]).await.unwrap();
+        let text = connection.query(query).await.unwrap();
+
+        for row in [&binary[0], &text[0]] {
+            assert_eq!(row.get_and_parse::<Money>("money").unwrap(), Money(-123456));
+        }
+    });
+}
+
+#[test]
+fn test_point() {
+    let point = PgPoint { x: 1.5, y: -2.0 };
+    assert_eq!(PgPoint::from_text(b"(1.5,-2)").unwrap(), point);
+    assert_eq!(point.to_string(), "(1.5,-2)");
+    let infinite = PgPoint::from_text(b"(Infinity,-Infinity)").unwrap();
+    assert_eq!((infinite.x, infinite.y), (f64::INFINITY, f64::NEG_INFINITY));
+    assert!(PgPoint::from_text(b"(NaN,0)").unwrap().x.is_nan());
+    for malformed in [&b"1.5,-2"[..], b"(1.5)", b"(1.5,x)", b"(1.5,-2", b"(inf,0)"] {
+        assert!(PgPoint::from_text(malformed).is_err());
+    }
+    assert!(PgPoint::from_binary(&[0; 8]).is_err());
+
+    smol::block_on(async {
+        let config = ConnectionConfig::new().default_result_format(FormatCode::Binary);
+        let mut connection = Connection::connect(config).await.unwrap();
+
+        let query = "SELECT point(1.5, -2) AS point";
+        let binary = connection.query_typed(query, &[]).await.unwrap();
+        let text = connection.query(query).await.unwrap();
+
+        for row in [&binary[0], &text[0]] {
+            assert_eq!(row.get_and_parse::<PgPoint>("point").unwrap(), point);
+        }
+    });
+}